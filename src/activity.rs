@@ -0,0 +1,80 @@
+use aws_sdk_dynamodb::{error::DisplayErrorContext, types::AttributeValue};
+use axum_session::DatabaseError;
+
+use crate::{ReadConsistency, SessionDynamoDbPool, SessionKey};
+
+/// Id of the item holding the activity counters in each session table.
+pub(crate) const ACTIVITY_SUMMARY_ID: &str = "__stats__";
+
+/// Session activity counted since `with_activity_summary` was enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ActivitySummary {
+    /// Stores that wrote a new session rather than replacing one.
+    pub creates: i64,
+    /// `delete_one_by_id` calls that removed a session.
+    pub deletes: i64,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Reads the counters kept under `with_activity_summary`. Tables that
+    /// haven't seen any counted activity report zeros.
+    pub async fn activity_summary(&self, table_name: &str) -> Result<ActivitySummary, DatabaseError> {
+        let Some(item) = self
+            .get_item(ACTIVITY_SUMMARY_ID, ReadConsistency::Strong, table_name)
+            .await?
+        else {
+            return Ok(ActivitySummary::default());
+        };
+
+        let counter = |name: &str| {
+            item.get(name)
+                .and_then(|value| value.as_n().ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default()
+        };
+
+        Ok(ActivitySummary {
+            creates: counter("creates"),
+            deletes: counter("deletes"),
+        })
+    }
+
+    /// Adds one to the `counter` of the activity summary, if it is kept. A
+    /// failed update is logged and doesn't fail the operation. Called while
+    /// the operation's permit is held, so it takes none.
+    pub(crate) async fn record_activity(&self, counter: &str, table_name: &str) {
+        if !self.config.activity_summary {
+            return;
+        }
+
+        let key = match self.key(ACTIVITY_SUMMARY_ID) {
+            Ok(key) => key,
+            Err(err) => {
+                tracing::warn!("failed to update activity summary: {err}");
+                return;
+            }
+        };
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(key))
+            .update_expression("ADD #counter :one")
+            .expression_attribute_names("#counter", counter)
+            .expression_attribute_values(":one", AttributeValue::N("1".into()));
+
+        if let Err(err) = self.send(|| request.clone().send()).await {
+            tracing::warn!("failed to update activity summary: {}", DisplayErrorContext(&err));
+        }
+    }
+
+    /// The stored id of the activity summary item, which scans over the
+    /// sessions skip, if it is kept.
+    pub(crate) fn activity_summary_id(&self) -> Option<AttributeValue> {
+        if !self.config.activity_summary {
+            return None;
+        }
+        self.key(ACTIVITY_SUMMARY_ID).ok()?.remove(self.id_attribute())
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::{
+    error::{BuildError, DisplayErrorContext},
+    operation::{
+        delete_item::builders::DeleteItemFluentBuilder, put_item::builders::PutItemFluentBuilder,
+        transact_write_items::TransactWriteItemsError, update_item::builders::UpdateItemFluentBuilder,
+    },
+    types::{AttributeValue, Delete, Put, TransactWriteItem, Update},
+};
+use axum_session::DatabaseError;
+
+use crate::{client_request_token, codec, error, Item, SessionDynamoDbPool, SessionKey};
+
+/// How audit records are written to the table set with `with_audit_table`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuditMode {
+    /// The record is written after the session write succeeds. A failed
+    /// audit write is logged and doesn't fail the operation.
+    #[default]
+    BestEffort,
+    /// The record is written in the same transaction as the session write,
+    /// so one never lands without the other, at twice the write cost.
+    Transactional,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Returns `true` if session writes have to go through `transact_audited`.
+    pub(crate) fn audits_transactionally(&self) -> bool {
+        self.config.audit_table.is_some() && self.config.audit_mode == AuditMode::Transactional
+    }
+
+    /// Writes the audit record of `event` on `id` in best effort mode.
+    /// Called while the operation's permit is held, so it takes none.
+    pub(crate) async fn audit(&self, id: &str, event: &str) {
+        let Some(audit_table) = &self.config.audit_table else {
+            return;
+        };
+        if self.config.audit_mode != AuditMode::BestEffort {
+            return;
+        }
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(audit_table))
+            .set_item(Some(self.audit_item(id, event)));
+
+        if let Err(err) = self.send(|| request.clone().send()).await {
+            tracing::warn!("failed to write audit record for {event} of session {id}: {}", DisplayErrorContext(&err));
+        }
+    }
+
+    /// Runs `write` and the audit record of `event` on `id` in a single
+    /// transaction. A failed condition on `write` is reported as a
+    /// [`CONDITION_FAILED`](crate::CONDITION_FAILED) error, with the current
+    /// item if `write` asked for it.
+    pub(crate) async fn transact_audited(
+        &self,
+        write: Result<TransactWriteItem, BuildError>,
+        id: &str,
+        event: &str,
+        variant: fn(String) -> DatabaseError,
+    ) -> Result<(), DatabaseError> {
+        let audit_table = self.config.audit_table.as_deref().unwrap_or_default();
+        let audit = Put::builder()
+            .table_name(self.full_table_name(audit_table))
+            .set_item(Some(self.audit_item(id, event)))
+            .build()
+            .map_err(|e| variant(e.to_string()))?;
+
+        let request = self
+            .client
+            .transact_write_items()
+            .transact_items(write.map_err(|e| variant(e.to_string()))?)
+            .transact_items(TransactWriteItem::builder().put(audit).build())
+            .client_request_token(client_request_token());
+
+        let err = match self.send(|| request.clone().send()).await {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+
+        if let Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) = err.as_service_error() {
+            let reason = cancelled.cancellation_reasons().first();
+            if reason.and_then(|reason| reason.code()) == Some("ConditionalCheckFailed") {
+                let message = format!("{}: {}", error::CONDITION_FAILED, DisplayErrorContext(&err));
+                return Err(variant(match reason.and_then(|reason| reason.item()) {
+                    Some(item) => error::with_current_item(&message, item),
+                    None => message,
+                }));
+            }
+        }
+
+        Err(error::map_sdk_error(err, variant))
+    }
+
+    /// An audit record keyed by the session id, hashed like the session's
+    /// own key when `with_id_hashing` is set, and the time in microseconds.
+    fn audit_item(&self, id: &str, event: &str) -> Item {
+        let id = match &self.config.id_salt {
+            Some(salt) => codec::hash_id(salt, id),
+            None => id.to_string(),
+        };
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros());
+
+        Item::from([
+            ("id".to_string(), AttributeValue::S(id)),
+            ("at".to_string(), AttributeValue::N(at.to_string())),
+            ("event".to_string(), AttributeValue::S(event.into())),
+        ])
+    }
+}
+
+/// The transactional counterpart of a `put_item` request.
+pub(crate) fn put_write(request: &PutItemFluentBuilder) -> Result<TransactWriteItem, BuildError> {
+    let put = Put::builder()
+        .set_table_name(request.get_table_name().clone())
+        .set_item(request.get_item().clone())
+        .set_condition_expression(request.get_condition_expression().clone())
+        .set_expression_attribute_names(request.get_expression_attribute_names().clone())
+        .set_expression_attribute_values(request.get_expression_attribute_values().clone())
+        .set_return_values_on_condition_check_failure(request.get_return_values_on_condition_check_failure().clone())
+        .build()?;
+
+    Ok(TransactWriteItem::builder().put(put).build())
+}
+
+/// The transactional counterpart of an `update_item` request.
+pub(crate) fn update_write(request: &UpdateItemFluentBuilder) -> Result<TransactWriteItem, BuildError> {
+    let update = Update::builder()
+        .set_table_name(request.get_table_name().clone())
+        .set_key(request.get_key().clone())
+        .set_update_expression(request.get_update_expression().clone())
+        .set_condition_expression(request.get_condition_expression().clone())
+        .set_expression_attribute_names(request.get_expression_attribute_names().clone())
+        .set_expression_attribute_values(request.get_expression_attribute_values().clone())
+        .set_return_values_on_condition_check_failure(request.get_return_values_on_condition_check_failure().clone())
+        .build()?;
+
+    Ok(TransactWriteItem::builder().update(update).build())
+}
+
+/// The transactional counterpart of a `delete_item` request.
+pub(crate) fn delete_write(request: &DeleteItemFluentBuilder) -> Result<TransactWriteItem, BuildError> {
+    let delete = Delete::builder()
+        .set_table_name(request.get_table_name().clone())
+        .set_key(request.get_key().clone())
+        .set_condition_expression(request.get_condition_expression().clone())
+        .set_expression_attribute_names(request.get_expression_attribute_names().clone())
+        .set_expression_attribute_values(request.get_expression_attribute_values().clone())
+        .set_return_values_on_condition_check_failure(request.get_return_values_on_condition_check_failure().clone())
+        .build()?;
+
+    Ok(TransactWriteItem::builder().delete(delete).build())
+}
@@ -0,0 +1,75 @@
+use aws_sdk_applicationautoscaling::types::{
+    MetricType, PolicyType, PredefinedMetricSpecification, ScalableDimension, ServiceNamespace,
+    TargetTrackingScalingPolicyConfiguration,
+};
+use axum_session::DatabaseError;
+
+pub type ApplicationAutoScalingClient = aws_sdk_applicationautoscaling::Client;
+
+/// Application Auto Scaling of a provisioned table's read and write capacity,
+/// registered right after the pool creates the table.
+#[derive(Clone, Debug)]
+pub struct AutoScaling {
+    pub client: ApplicationAutoScalingClient,
+    pub min_capacity: i32,
+    pub max_capacity: i32,
+    /// Consumed-to-provisioned ratio to track, in percent (20 to 90).
+    pub target_utilization: f64,
+}
+
+impl AutoScaling {
+    pub(crate) async fn register(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let dimensions = [
+            (
+                ScalableDimension::DynamoDbTableReadCapacityUnits,
+                MetricType::DynamoDbReadCapacityUtilization,
+                "read",
+            ),
+            (
+                ScalableDimension::DynamoDbTableWriteCapacityUnits,
+                MetricType::DynamoDbWriteCapacityUtilization,
+                "write",
+            ),
+        ];
+
+        let resource_id = format!("table/{table_name}");
+
+        for (dimension, metric, label) in dimensions {
+            self.client
+                .register_scalable_target()
+                .service_namespace(ServiceNamespace::Dynamodb)
+                .resource_id(&resource_id)
+                .scalable_dimension(dimension.clone())
+                .min_capacity(self.min_capacity)
+                .max_capacity(self.max_capacity)
+                .send()
+                .await
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+
+            let configuration = TargetTrackingScalingPolicyConfiguration::builder()
+                .target_value(self.target_utilization)
+                .predefined_metric_specification(
+                    PredefinedMetricSpecification::builder()
+                        .predefined_metric_type(metric)
+                        .build()
+                        .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?,
+                )
+                .build()
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+
+            self.client
+                .put_scaling_policy()
+                .policy_name(format!("{table_name}-{label}-scaling"))
+                .service_namespace(ServiceNamespace::Dynamodb)
+                .resource_id(&resource_id)
+                .scalable_dimension(dimension)
+                .policy_type(PolicyType::TargetTrackingScaling)
+                .target_tracking_scaling_policy_configuration(configuration)
+                .send()
+                .await
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::HashMap, time::Duration};
+
+use aws_sdk_dynamodb::{
+    operation::scan::builders::ScanFluentBuilder,
+    types::{DeleteRequest, KeysAndAttributes, ReturnConsumedCapacity, WriteRequest},
+};
+use axum_session::DatabaseError;
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::sync::mpsc;
+
+use crate::{
+    error,
+    expression::{self, Filter},
+    retry::ScannedItems,
+    Item, SessionDynamoDbPool, SessionKey,
+};
+
+/// Maximum number of requests DynamoDB accepts in one `BatchWriteItem`.
+pub(crate) const MAX_BATCH_WRITE: usize = 25;
+
+/// Maximum number of keys DynamoDB accepts in one `BatchGetItem`.
+pub(crate) const MAX_BATCH_GET: usize = 100;
+
+/// How many scanned pages `scan_delete` lets wait for deletion while it
+/// fetches the next one.
+const DELETE_PIPELINE_DEPTH: usize = 2;
+
+/// Delay before the first resubmission of unprocessed batch items, doubled
+/// on every further one.
+const BATCH_RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Upper bound on the delay before any single resubmission.
+const MAX_BATCH_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Delay before resubmitting what attempt number `attempt`, counted from 1,
+/// left unprocessed: 100ms, doubled on every further attempt, at most
+/// `MAX_BATCH_RETRY_BACKOFF` however high `max_batch_retries` is set.
+fn batch_retry_backoff(attempt: u32) -> Duration {
+    BATCH_RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_BATCH_RETRY_BACKOFF)
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Submits the requests in batches of 25, resubmitting unprocessed items
+    /// with capped exponential backoff up to `max_batch_retries` times.
+    /// Returns the write capacity consumed.
+    pub(crate) async fn batch_write(
+        &self,
+        table_name: &str,
+        requests: Vec<WriteRequest>,
+    ) -> Result<f64, DatabaseError> {
+        let mut consumed = 0.0;
+
+        for chunk in requests.chunks(MAX_BATCH_WRITE) {
+            let mut pending = HashMap::from([(self.full_table_name(table_name), chunk.to_vec())]);
+            let mut attempt = 0;
+
+            while !pending.is_empty() {
+                if attempt > self.config.max_batch_retries {
+                    return Err(DatabaseError::GenericInsertError(format!(
+                        "batch write to {table_name} left items unprocessed after {} retries",
+                        self.config.max_batch_retries
+                    )));
+                }
+
+                if attempt > 0 {
+                    tokio::time::sleep(batch_retry_backoff(attempt)).await;
+                }
+
+                let request = self
+                    .client
+                    .batch_write_item()
+                    .set_request_items(Some(pending))
+                    .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+                let output = self
+                    .send(|| request.clone().send())
+                    .await
+                    .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError))?;
+
+                consumed += output.consumed_capacity().iter().filter_map(|c| c.capacity_units()).sum::<f64>();
+                pending = output.unprocessed_items.unwrap_or_default();
+                pending.retain(|_, requests| !requests.is_empty());
+                attempt += 1;
+            }
+        }
+
+        Ok(consumed)
+    }
+
+    /// Fetches the items with the given keys in batches of 100, resubmitting
+    /// unprocessed keys with capped exponential backoff up to
+    /// `max_batch_retries` times. Returns the items found, missing ones being
+    /// left out, and the keys still unprocessed once the retries ran out.
+    pub(crate) async fn batch_get(
+        &self,
+        table_name: &str,
+        keys: Vec<Item>,
+        projection: Option<(String, HashMap<String, String>)>,
+    ) -> Result<(Vec<Item>, Vec<Item>), DatabaseError> {
+        let mut items = Vec::with_capacity(keys.len());
+        let mut unprocessed = Vec::new();
+
+        for chunk in keys.chunks(MAX_BATCH_GET) {
+            let keys_and_attributes = KeysAndAttributes::builder()
+                .set_keys(Some(chunk.to_vec()))
+                .set_projection_expression(projection.as_ref().map(|(expression, _)| expression.clone()))
+                .set_expression_attribute_names(projection.as_ref().map(|(_, names)| names.clone()))
+                .consistent_read(self.config.default_read_consistency.is_strong())
+                .build()
+                .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+
+            let mut pending = HashMap::from([(self.full_table_name(table_name), keys_and_attributes)]);
+            let mut attempt = 0;
+
+            while !pending.is_empty() {
+                if attempt > self.config.max_batch_retries {
+                    unprocessed.extend(pending.into_values().flat_map(|keys| keys.keys));
+                    break;
+                }
+
+                if attempt > 0 {
+                    tokio::time::sleep(batch_retry_backoff(attempt)).await;
+                }
+
+                let request = self
+                    .client
+                    .batch_get_item()
+                    .set_request_items(Some(pending));
+
+                let output = self
+                    .send(|| request.clone().send())
+                    .await
+                    .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+                items.extend(output.responses.unwrap_or_default().into_values().flatten());
+
+                pending = output.unprocessed_keys.unwrap_or_default();
+                pending.retain(|_, keys| !keys.keys().is_empty());
+                attempt += 1;
+            }
+        }
+
+        Ok((items, unprocessed))
+    }
+
+    /// Scans the keys of the sessions matching the filter, if any, and
+    /// deletes them in batches. Returns the keys of the deleted items.
+    ///
+    /// Unless `force` is set, nothing is deleted if more items match than
+    /// `with_max_delete_guard` allows. Without a guard to check upfront,
+    /// each scanned page is deleted while the next one is fetched.
+    pub(crate) async fn scan_delete(
+        &self,
+        table_name: &str,
+        filter: Option<Filter>,
+        force: bool,
+    ) -> Result<Vec<Item>, DatabaseError> {
+        let mut request = self.scan_keys(table_name, &[]);
+
+        if let Some(filter) = filter {
+            request = expression::and_filter(request, filter);
+        }
+
+        let Some(max) = self.config.max_delete_guard.filter(|_| !force) else {
+            return self.pipelined_delete(table_name, request).await;
+        };
+
+        let items = self.scan_items(request).await?;
+
+        let keys: Vec<_> = items.iter().filter_map(|item| self.item_key(item)).collect();
+
+        if keys.len() > max {
+            return Err(DatabaseError::GenericDeleteError(format!(
+                "refusing to delete {} sessions from {table_name}, more than the guard of {max}",
+                keys.len()
+            )));
+        }
+
+        let batches = keys.chunks(MAX_BATCH_WRITE).map(<[Item]>::to_vec);
+        self.delete_batches(table_name, futures::stream::iter(batches)).await
+    }
+
+    /// Deletes the keys returned by a scan, split into the
+    /// `with_scan_segments` segments, while the scan goes on: each page is
+    /// deleted while the next ones are fetched, at most
+    /// `DELETE_PIPELINE_DEPTH` pages ahead.
+    async fn pipelined_delete(&self, table_name: &str, request: ScanFluentBuilder) -> Result<Vec<Item>, DatabaseError> {
+        let (pages, pending) = mpsc::channel::<Vec<Item>>(DELETE_PIPELINE_DEPTH);
+        let scanned = ScannedItems::default();
+
+        let scans: Vec<_> = self
+            .segment_requests(&request)
+            .into_iter()
+            .map(|request| {
+                let pages = pages.clone();
+                let scanned = scanned.clone();
+
+                async move {
+                    let mut start_key = None;
+
+                    loop {
+                        let mut page = self.scan_page(&request, start_key.take(), &scanned).await?;
+                        start_key = page.last_evaluated_key.take();
+
+                        let keys = page
+                            .items
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(|item| self.item_key(item))
+                            .collect();
+
+                        // A closed channel means the deletion failed, which reports the error.
+                        if pages.send(keys).await.is_err() || start_key.is_none() {
+                            return Ok::<_, DatabaseError>(());
+                        }
+                    }
+                }
+            })
+            .collect();
+        // The deletion ends once every segment's sender is dropped.
+        drop(pages);
+
+        let pending = futures::stream::unfold(pending, |mut pending| async move {
+            pending.recv().await.map(|keys| (keys, pending))
+        });
+
+        let (_, deleted) = futures::try_join!(
+            futures::future::try_join_all(scans),
+            self.delete_batches(table_name, pending)
+        )?;
+        Ok(deleted)
+    }
+
+    /// Deletes batches of keys with up to `with_delete_workers` batch
+    /// writes in flight, returning the deleted keys.
+    async fn delete_batches(
+        &self,
+        table_name: &str,
+        batches: impl Stream<Item = Vec<Item>>,
+    ) -> Result<Vec<Item>, DatabaseError> {
+        batches
+            .map(|keys| async move {
+                self.batch_write(table_name, delete_requests(&keys)?).await?;
+                Ok(keys)
+            })
+            .buffer_unordered(self.config.delete_workers)
+            .try_concat()
+            .await
+    }
+}
+
+pub(crate) fn delete_requests(keys: &[Item]) -> Result<Vec<WriteRequest>, DatabaseError> {
+    keys.iter()
+        .map(|key| {
+            DeleteRequest::builder()
+                .set_key(Some(key.clone()))
+                .build()
+                .map(|delete| WriteRequest::builder().delete_request(delete).build())
+                .map_err(|e| DatabaseError::GenericDeleteError(e.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_retry_backoff_doubles_up_to_its_cap() {
+        let delays: Vec<_> = (1..=4).map(batch_retry_backoff).collect();
+        assert_eq!(delays, [100, 200, 400, 800].map(Duration::from_millis));
+
+        assert_eq!(batch_retry_backoff(20), MAX_BATCH_RETRY_BACKOFF);
+        assert_eq!(batch_retry_backoff(u32::MAX), MAX_BATCH_RETRY_BACKOFF);
+    }
+}
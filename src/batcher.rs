@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use aws_sdk_dynamodb::types::{PutRequest, WriteRequest};
+use axum_session::DatabaseError;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{batch::MAX_BATCH_WRITE, error, Item, SessionDynamoDbPool, SessionKey};
+
+/// What the flusher is sent.
+enum Message {
+    Store(PendingStore),
+    /// Asks for everything queued so far to be written, then answered.
+    Flush(oneshot::Sender<()>),
+}
+
+/// A `store` waiting in the batching window.
+struct PendingStore {
+    table_name: String,
+    id: String,
+    item: Item,
+    done: oneshot::Sender<Result<(), DatabaseError>>,
+}
+
+/// Coalesces `store` calls into `BatchWriteItem` requests, set up with
+/// `with_write_batching`. Shared by the pool's clones.
+#[derive(Debug)]
+pub(crate) struct WriteBatcher {
+    window: Duration,
+    /// Started on the first store, as the pool may be built outside a runtime.
+    sender: OnceLock<mpsc::UnboundedSender<Message>>,
+}
+
+impl WriteBatcher {
+    pub(crate) fn new(window: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            window,
+            sender: OnceLock::new(),
+        })
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Queues `item` for the next batch and waits until it is written.
+    pub(crate) async fn store_batched(
+        &self,
+        batcher: &WriteBatcher,
+        id: &str,
+        item: Item,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        self.check_expires(id, &item)?;
+
+        let (done, result) = oneshot::channel();
+        let pending = PendingStore {
+            table_name: table_name.to_string(),
+            id: id.to_string(),
+            item,
+            done,
+        };
+
+        if self.batch_sender(batcher).send(Message::Store(pending)).is_err() {
+            return Err(DatabaseError::GenericInsertError("write batcher stopped".into()));
+        }
+
+        result
+            .await
+            .unwrap_or_else(|_| Err(DatabaseError::GenericInsertError("write batcher stopped".into())))
+    }
+
+    /// Writes every session buffered by `with_write_batching` right away,
+    /// without waiting for the window to pass, and returns once they are
+    /// all written. Each `store` still reports its own outcome. Does
+    /// nothing without batching.
+    pub async fn flush(&self) -> Result<(), DatabaseError> {
+        let Some(sender) = self.config.write_batching.as_ref().and_then(|batcher| batcher.sender.get()) else {
+            return Ok(());
+        };
+
+        let (done, flushed) = oneshot::channel();
+        if sender.send(Message::Flush(done)).is_err() {
+            return Ok(());
+        }
+        flushed
+            .await
+            .map_err(|_| DatabaseError::GenericInsertError("write batcher stopped".into()))
+    }
+
+    /// Flushes the buffered sessions and drops this pool, to be awaited
+    /// before the process exits. Once the runtime shuts down, sessions still
+    /// buffered are lost: the flush the batcher runs when the last clone of
+    /// the pool is dropped only completes while the runtime is alive.
+    pub async fn shutdown(self) -> Result<(), DatabaseError> {
+        self.flush().await
+    }
+
+    /// The batcher's queue, starting its flusher on first use.
+    fn batch_sender<'a>(&self, batcher: &'a WriteBatcher) -> &'a mpsc::UnboundedSender<Message> {
+        batcher.sender.get_or_init(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+
+            // The flusher's pool has batching turned off, so it doesn't keep
+            // the channel open once every clone of this pool is dropped.
+            let mut pool = self.clone();
+            pool.config.write_batching = None;
+            tokio::spawn(pool.flush_batches(receiver, batcher.window));
+
+            sender
+        })
+    }
+
+    /// Collects queued stores until `window` passes, `MAX_BATCH_WRITE` are
+    /// waiting or a flush is asked for, then writes them. Once the channel
+    /// closes, what is left is written before the task ends.
+    async fn flush_batches(self, mut receiver: mpsc::UnboundedReceiver<Message>, window: Duration) {
+        while let Some(first) = receiver.recv().await {
+            let mut batch = Vec::new();
+            let mut flushed = None;
+
+            match first {
+                Message::Store(pending) => batch.push(pending),
+                Message::Flush(done) => flushed = Some(done),
+            }
+
+            let deadline = tokio::time::sleep(window);
+            tokio::pin!(deadline);
+
+            while flushed.is_none() && batch.len() < MAX_BATCH_WRITE {
+                tokio::select! {
+                    message = receiver.recv() => match message {
+                        Some(Message::Store(pending)) => batch.push(pending),
+                        Some(Message::Flush(done)) => flushed = Some(done),
+                        None => break,
+                    },
+                    () = &mut deadline => break,
+                }
+            }
+
+            if !batch.is_empty() {
+                self.flush_batch(batch).await;
+            }
+            if let Some(done) = flushed {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    /// Writes a batch, table by table, and reports the outcome to every
+    /// waiting store. A batch can't write the same key twice, so a later
+    /// store of an id replaces an earlier one still in the batch.
+    async fn flush_batch(&self, batch: Vec<PendingStore>) {
+        let mut tables: HashMap<String, HashMap<String, (Item, Vec<_>)>> = HashMap::new();
+        for pending in batch {
+            let (item, waiters) = tables
+                .entry(pending.table_name)
+                .or_default()
+                .entry(pending.id)
+                .or_insert_with(|| (Item::new(), Vec::new()));
+            *item = pending.item;
+            waiters.push(pending.done);
+        }
+
+        for (table_name, stores) in tables {
+            let (items, waiters): (Vec<_>, Vec<_>) = stores.into_values().unzip();
+
+            let result = match self.permit().await {
+                Ok(_permit) => {
+                    let requests = items
+                        .into_iter()
+                        .map(|item| {
+                            PutRequest::builder()
+                                .set_item(Some(item))
+                                .build()
+                                .map(|put| WriteRequest::builder().put_request(put).build())
+                                .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))
+                        })
+                        .collect::<Result<Vec<_>, _>>();
+
+                    match requests {
+                        Ok(requests) => self.batch_write(&table_name, requests).await.map(|_| ()),
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+
+            for done in waiters.into_iter().flatten() {
+                let _ = done.send(result.as_ref().map(|_| ()).map_err(error::duplicate));
+            }
+        }
+    }
+}
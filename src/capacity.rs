@@ -0,0 +1,239 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use aws_sdk_dynamodb::{
+    config::{
+        interceptors::{BeforeSerializationInterceptorContextMut, FinalizerInterceptorContextRef},
+        ConfigBag, Intercept, RuntimeComponents,
+    },
+    operation::{
+        batch_get_item::{BatchGetItemInput, BatchGetItemOutput},
+        batch_write_item::{BatchWriteItemInput, BatchWriteItemOutput},
+        delete_item::{DeleteItemInput, DeleteItemOutput},
+        get_item::{GetItemInput, GetItemOutput},
+        put_item::{PutItemInput, PutItemOutput},
+        query::{QueryInput, QueryOutput},
+        scan::{ScanInput, ScanOutput},
+        transact_get_items::{TransactGetItemsInput, TransactGetItemsOutput},
+        transact_write_items::{TransactWriteItemsInput, TransactWriteItemsOutput},
+        update_item::{UpdateItemInput, UpdateItemOutput},
+    },
+    types::ReturnConsumedCapacity,
+};
+use aws_smithy_runtime_api::box_error::BoxError;
+
+use crate::{Capacity, SessionDynamoDbPool, SessionKey};
+
+/// Share of provisioned capacity that capacity suggestions leave in use at
+/// the observed peak, the usual target-tracking utilization.
+const TARGET_UTILIZATION: f64 = 0.7;
+
+/// Average use of provisioned capacity above which provisioned billing is
+/// cheaper than on-demand, at DynamoDB's list prices.
+const BREAK_EVEN_UTILIZATION: f64 = 0.3;
+
+/// A write unit costs five times a read unit in both billing modes.
+const WRITE_COST_WEIGHT: f64 = 5.0;
+
+/// Capacity consumed over one sampling interval, in units per second, e.g.
+/// the `ConsumedCapacity` of the pool's calls summed up and divided by the
+/// length of the interval.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CapacitySample {
+    pub read_units: f64,
+    pub write_units: f64,
+}
+
+/// Result of `analyze_usage`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapacityAnalysis {
+    pub peak_read: f64,
+    pub average_read: f64,
+    pub peak_write: f64,
+    pub average_write: f64,
+    /// The cheaper billing mode for the observed load. Provisioned capacity
+    /// is sized so the peak uses 70% of it.
+    pub recommended: Capacity,
+}
+
+/// Recommends on-demand or provisioned billing, with suggested capacity,
+/// from samples of consumed capacity covering a representative window.
+/// Returns `None` without samples.
+///
+/// Steady load favors provisioned capacity, while spiky load, whose peak
+/// would leave most provisioned capacity idle, favors on-demand. The result
+/// is a starting point: it knows nothing of growth or reserved capacity.
+pub fn analyze_usage(samples: &[CapacitySample]) -> Option<CapacityAnalysis> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let peak = |units: fn(&CapacitySample) -> f64| samples.iter().map(units).fold(0.0, f64::max);
+    let average = |units: fn(&CapacitySample) -> f64| samples.iter().map(units).sum::<f64>() / samples.len() as f64;
+
+    let peak_read = peak(|s| s.read_units);
+    let peak_write = peak(|s| s.write_units);
+    let average_read = average(|s| s.read_units);
+    let average_write = average(|s| s.write_units);
+
+    let read = provisioned_units(peak_read);
+    let write = provisioned_units(peak_write);
+
+    let utilization = (average_read + WRITE_COST_WEIGHT * average_write)
+        / (read as f64 + WRITE_COST_WEIGHT * write as f64);
+
+    let recommended = if utilization >= BREAK_EVEN_UTILIZATION {
+        Capacity::Provisioned { read, write }
+    } else {
+        Capacity::OnDemand
+    };
+
+    Some(CapacityAnalysis {
+        peak_read,
+        average_read,
+        peak_write,
+        average_write,
+        recommended,
+    })
+}
+
+/// Capacity units that keep `peak` at the target utilization.
+fn provisioned_units(peak: f64) -> i64 {
+    ((peak / TARGET_UTILIZATION).ceil() as i64).max(1)
+}
+
+/// Capacity consumed by a pool since `with_capacity_stats` enabled tracking,
+/// or since the last `reset_capacity_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CapacityStats {
+    pub read_units: f64,
+    pub write_units: f64,
+}
+
+/// Running capacity totals shared by a pool and its clones, stored as the
+/// bits of an `f64` so they can be added to without a lock.
+#[derive(Debug, Default)]
+pub(crate) struct CapacityTotals {
+    read_units: AtomicU64,
+    write_units: AtomicU64,
+}
+
+impl CapacityTotals {
+    pub(crate) fn stats(&self) -> CapacityStats {
+        CapacityStats {
+            read_units: f64::from_bits(self.read_units.load(Ordering::Relaxed)),
+            write_units: f64::from_bits(self.write_units.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn reset(&self) -> CapacityStats {
+        CapacityStats {
+            read_units: f64::from_bits(self.read_units.swap(0, Ordering::Relaxed)),
+            write_units: f64::from_bits(self.write_units.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    fn add(total: &AtomicU64, units: f64) {
+        let _ = total.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + units).to_bits())
+        });
+    }
+}
+
+/// Asks DynamoDB for the capacity every call consumes and adds it up.
+#[derive(Debug)]
+pub(crate) struct CapacityInterceptor(pub(crate) Arc<CapacityTotals>);
+
+impl Intercept for CapacityInterceptor {
+    fn name(&self) -> &'static str {
+        "CapacityStats"
+    }
+
+    fn modify_before_serialization(
+        &self,
+        context: &mut BeforeSerializationInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let input = context.input_mut();
+
+        macro_rules! return_consumed_capacity {
+            ($($input:ty),*) => {
+                $(if let Some(input) = input.downcast_mut::<$input>() {
+                    input.return_consumed_capacity.get_or_insert(ReturnConsumedCapacity::Total);
+                    return Ok(());
+                })*
+            };
+        }
+
+        return_consumed_capacity!(
+            GetItemInput,
+            PutItemInput,
+            UpdateItemInput,
+            DeleteItemInput,
+            QueryInput,
+            ScanInput,
+            BatchGetItemInput,
+            BatchWriteItemInput,
+            TransactGetItemsInput,
+            TransactWriteItemsInput
+        );
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let Some(Ok(output)) = context.output_or_error() else {
+            return Ok(());
+        };
+
+        macro_rules! consumed {
+            ($total:expr, $($output:ty),*) => {
+                $(if let Some(output) = output.downcast_ref::<$output>() {
+                    let units = output.consumed_capacity().into_iter().filter_map(|c| c.capacity_units()).sum();
+                    CapacityTotals::add($total, units);
+                    return Ok(());
+                })*
+            };
+        }
+
+        consumed!(&self.0.read_units, GetItemOutput, QueryOutput, ScanOutput, BatchGetItemOutput, TransactGetItemsOutput);
+        consumed!(
+            &self.0.write_units,
+            PutItemOutput,
+            UpdateItemOutput,
+            DeleteItemOutput,
+            BatchWriteItemOutput,
+            TransactWriteItemsOutput
+        );
+        Ok(())
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Capacity consumed by the pool and its clones, zero unless
+    /// `with_capacity_stats` is set.
+    pub fn capacity_stats(&self) -> CapacityStats {
+        self.config
+            .capacity_totals
+            .as_ref()
+            .map(|totals| totals.stats())
+            .unwrap_or_default()
+    }
+
+    /// Returns `capacity_stats` and starts counting again from zero, e.g.
+    /// to report consumption per interval.
+    pub fn reset_capacity_stats(&self) -> CapacityStats {
+        self.config
+            .capacity_totals
+            .as_ref()
+            .map(|totals| totals.reset())
+            .unwrap_or_default()
+    }
+}
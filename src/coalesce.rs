@@ -0,0 +1,56 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use axum_session::DatabaseError;
+use tokio::sync::OnceCell;
+
+use crate::{error, SessionDynamoDbPool, SessionKey};
+
+/// Outcome of a load, shared by every caller waiting on it.
+type SharedLoad = Arc<OnceCell<Result<Option<String>, DatabaseError>>>;
+
+/// Loads in flight, by table and id, set up with `with_load_coalescing`.
+/// Shared by the pool's clones.
+#[derive(Debug, Default)]
+pub(crate) struct LoadCoalescer {
+    in_flight: Mutex<HashMap<(String, String), SharedLoad>>,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Joins the load of `id` already in flight, if any, or starts one that
+    /// later callers join. If the caller running the load is cancelled, one
+    /// of those waiting takes over.
+    pub(crate) async fn load_coalesced(
+        &self,
+        coalescer: &LoadCoalescer,
+        id: &str,
+        table_name: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        let key = (table_name.to_string(), id.to_string());
+        let load = coalescer
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let result = load
+            .get_or_init(|| self.load_with_consistency(id, self.config.default_read_consistency, table_name))
+            .await;
+
+        // Callers arriving from now on start a fresh load, so nothing is
+        // served after the load it came from has finished.
+        let mut in_flight = coalescer.in_flight.lock().unwrap_or_else(PoisonError::into_inner);
+        if in_flight.get(&key).is_some_and(|current| Arc::ptr_eq(current, &load)) {
+            in_flight.remove(&key);
+        }
+
+        match result {
+            Ok(session) => Ok(session.clone()),
+            Err(e) => Err(error::duplicate(e)),
+        }
+    }
+}
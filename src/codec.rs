@@ -0,0 +1,246 @@
+use std::io::{Read, Write};
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{
+    read::{DeflateDecoder, GzDecoder},
+    write::{DeflateEncoder, GzEncoder},
+    Compression,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Marks a `session` string holding a gzipped, base64 encoded payload.
+/// Strings without it are plain payloads, which keeps legacy values readable.
+pub(crate) const GZIP_BASE64_PREFIX: &str = "gzb64:";
+
+/// Marks a `session` string holding a raw DEFLATE compressed, base64
+/// encoded payload.
+pub(crate) const DEFLATE_BASE64_PREFIX: &str = "dfb64:";
+
+/// How session payloads are encoded in the String `session` attribute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StringCodec {
+    /// The payload is stored as-is.
+    #[default]
+    Plain,
+    /// The payload is compressed with the `CompressionAlgorithm` set by
+    /// `with_compression_algorithm`, gzip by default, base64 encoded and
+    /// prefixed with a marker naming the algorithm.
+    GzipBase64,
+}
+
+/// Compression applied by `StringCodec::GzipBase64`. The marker of each
+/// payload records its algorithm, so payloads stored under one setting
+/// still load after switching to another.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// gzip, which any gzip tool can read.
+    #[default]
+    Gzip,
+    /// Raw DEFLATE, the same compression without gzip's 18 byte header and
+    /// trailer, which adds up on small payloads.
+    Deflate,
+}
+
+impl StringCodec {
+    pub(crate) fn encode(self, algorithm: CompressionAlgorithm, session: &str) -> Result<String, DatabaseError> {
+        let compressed = match (self, algorithm) {
+            (Self::Plain, _) => return Ok(session.into()),
+            (Self::GzipBase64, CompressionAlgorithm::Gzip) => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(session.as_bytes()).and_then(|_| encoder.finish())
+            }
+            (Self::GzipBase64, CompressionAlgorithm::Deflate) => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(session.as_bytes()).and_then(|_| encoder.finish())
+            }
+        };
+
+        compressed
+            .map(|compressed| format!("{}{}", algorithm.prefix(), STANDARD.encode(compressed)))
+            .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))
+    }
+}
+
+impl CompressionAlgorithm {
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Gzip => GZIP_BASE64_PREFIX,
+            Self::Deflate => DEFLATE_BASE64_PREFIX,
+        }
+    }
+}
+
+/// Returns `true` if a payload starts like a compressed one, so storing it
+/// as-is would make it load as something else.
+pub(crate) fn has_compression_prefix(session: &str) -> bool {
+    session.starts_with(GZIP_BASE64_PREFIX) || session.starts_with(DEFLATE_BASE64_PREFIX)
+}
+
+/// Decodes a stored `session` string whatever codec and algorithm wrote it.
+pub(crate) fn decode_string(stored: &str) -> Result<String, DatabaseError> {
+    let (algorithm, encoded) = if let Some(encoded) = stored.strip_prefix(GZIP_BASE64_PREFIX) {
+        (CompressionAlgorithm::Gzip, encoded)
+    } else if let Some(encoded) = stored.strip_prefix(DEFLATE_BASE64_PREFIX) {
+        (CompressionAlgorithm::Deflate, encoded)
+    } else {
+        return Ok(stored.into());
+    };
+
+    let compressed = STANDARD
+        .decode(encoded)
+        .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+
+    let mut session = String::new();
+    match algorithm {
+        CompressionAlgorithm::Gzip => GzDecoder::new(compressed.as_slice()).read_to_string(&mut session),
+        CompressionAlgorithm::Deflate => DeflateDecoder::new(compressed.as_slice()).read_to_string(&mut session),
+    }
+    .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+
+    Ok(session)
+}
+
+/// Hex encoded SHA-256 of a session payload, stored alongside it in the
+/// `checksum` attribute.
+pub(crate) fn checksum(session: &str) -> String {
+    hex::encode(Sha256::digest(session.as_bytes()))
+}
+
+/// Hex SHA-256 of a session id prefixed with a salt, used as the stored key
+/// when id hashing is enabled.
+pub(crate) fn hash_id(salt: &str, id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update([0]);
+    hasher.update(id.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Converts a JSON value into the matching DynamoDB attribute, recursing into
+/// objects (`M`) and arrays (`L`).
+pub fn json_to_attribute_value(value: &Value) -> AttributeValue {
+    match value {
+        Value::Null => AttributeValue::Null(true),
+        Value::Bool(b) => AttributeValue::Bool(*b),
+        Value::Number(n) => AttributeValue::N(n.to_string()),
+        Value::String(s) => AttributeValue::S(s.clone()),
+        Value::Array(values) => AttributeValue::L(values.iter().map(json_to_attribute_value).collect()),
+        Value::Object(map) => AttributeValue::M(
+            map.iter()
+                .map(|(k, v)| (k.clone(), json_to_attribute_value(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts a DynamoDB attribute back into JSON. Returns `None` for binary
+/// attributes, which have no JSON representation.
+pub fn attribute_value_to_json(value: &AttributeValue) -> Option<Value> {
+    Some(match value {
+        AttributeValue::Null(_) => Value::Null,
+        AttributeValue::Bool(b) => Value::Bool(*b),
+        AttributeValue::N(n) => number_to_json(n)?,
+        AttributeValue::S(s) => Value::String(s.clone()),
+        AttributeValue::Ss(values) => Value::Array(values.iter().cloned().map(Value::String).collect()),
+        AttributeValue::Ns(values) => Value::Array(values.iter().map(|n| number_to_json(n)).collect::<Option<_>>()?),
+        AttributeValue::L(values) => Value::Array(values.iter().map(attribute_value_to_json).collect::<Option<_>>()?),
+        AttributeValue::M(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Some((k.clone(), attribute_value_to_json(v)?)))
+                .collect::<Option<_>>()?,
+        ),
+        _ => return None,
+    })
+}
+
+fn number_to_json(n: &str) -> Option<Value> {
+    if let Ok(i) = n.parse::<i64>() {
+        return Some(i.into());
+    }
+
+    if let Ok(u) = n.parse::<u64>() {
+        return Some(u.into());
+    }
+
+    n.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::primitives::Blob;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_round_trips_through_nested_attributes() {
+        let value = json!({
+            "user": {"id": 7, "roles": ["admin", "dev"], "score": 1.5},
+            "flags": [true, null, {"depth": [[-3]]}],
+            "big": u64::MAX,
+            "name": "alice",
+        });
+
+        let attribute = json_to_attribute_value(&value);
+
+        let AttributeValue::M(map) = &attribute else {
+            panic!("objects map to M, got {attribute:?}");
+        };
+        let AttributeValue::M(user) = &map["user"] else {
+            panic!("nested objects map to M");
+        };
+        assert_eq!(user["id"], AttributeValue::N("7".into()));
+        assert!(matches!(&user["roles"], AttributeValue::L(roles) if roles.len() == 2));
+        assert_eq!(attribute_value_to_json(&attribute), Some(value));
+    }
+
+    #[test]
+    fn attribute_value_to_json_reads_number_sets_and_rejects_binary() {
+        let numbers = AttributeValue::Ns(vec!["1".into(), "2.5".into()]);
+        assert_eq!(attribute_value_to_json(&numbers), Some(json!([1, 2.5])));
+
+        let bytes = AttributeValue::B(Blob::new(vec![1, 2]));
+        assert_eq!(attribute_value_to_json(&bytes), None);
+
+        let nested = AttributeValue::M([("data".to_string(), AttributeValue::L(vec![bytes]))].into());
+        assert_eq!(attribute_value_to_json(&nested), None);
+    }
+
+    #[test]
+    fn string_codecs_round_trip_with_every_algorithm() {
+        let session = r#"{"cart":["a","b","c"],"note":"ünïcode"}"#.repeat(20);
+
+        for algorithm in [CompressionAlgorithm::Gzip, CompressionAlgorithm::Deflate] {
+            let plain = StringCodec::Plain.encode(algorithm, &session).unwrap();
+            assert_eq!(plain, session);
+
+            let compressed = StringCodec::GzipBase64.encode(algorithm, &session).unwrap();
+            assert!(compressed.starts_with(algorithm.prefix()));
+            assert!(compressed.len() < session.len());
+            assert_eq!(decode_string(&compressed).unwrap(), session);
+        }
+    }
+
+    #[test]
+    fn decode_string_passes_legacy_payloads_through() {
+        assert_eq!(decode_string(r#"{"legacy":true}"#).unwrap(), r#"{"legacy":true}"#);
+        assert!(!has_compression_prefix(r#"{"legacy":true}"#));
+    }
+
+    #[test]
+    fn decode_string_rejects_corrupt_payloads() {
+        let not_base64 = format!("{GZIP_BASE64_PREFIX}not base64!");
+        let not_gzip = format!("{GZIP_BASE64_PREFIX}{}", STANDARD.encode("plain bytes"));
+        let truncated = StringCodec::GzipBase64
+            .encode(CompressionAlgorithm::Deflate, &"x".repeat(1000))
+            .unwrap();
+        let truncated = &truncated[..truncated.len() / 2];
+
+        for stored in [not_base64.as_str(), not_gzip.as_str(), truncated] {
+            assert!(matches!(decode_string(stored), Err(DatabaseError::GenericSelectError(_))), "{stored}");
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+
+use crate::{
+    expression::{self, Filter},
+    Item, MissingAttributes, SessionDynamoDbPool, SessionKey,
+};
+
+/// Optional details about the client a session belongs to, stored next to
+/// it by `store_with_context` in `ip` and `user_agent` attributes, e.g. for
+/// security dashboards. The pool itself never reads them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl SessionContext {
+    /// Reads the context attributes of a stored item, e.g. one returned by
+    /// `load_raw`. Absent and NULL attributes both read as `None`.
+    pub fn from_item(item: &Item) -> Self {
+        let attribute = |name: &str| item.get(name).and_then(|value| value.as_s().ok()).cloned();
+
+        Self {
+            ip: attribute("ip"),
+            user_agent: attribute("user_agent"),
+        }
+    }
+
+    /// The attributes to store, with unset fields left out or, under
+    /// `MissingAttributes::Null`, stored as NULL.
+    fn into_attributes(self, missing: MissingAttributes) -> HashMap<String, AttributeValue> {
+        [("ip", self.ip), ("user_agent", self.user_agent)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let value = match value {
+                    Some(value) => AttributeValue::S(value),
+                    None if missing == MissingAttributes::Null => AttributeValue::Null(true),
+                    None => return None,
+                };
+                Some((name.to_string(), value))
+            })
+            .collect()
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Stores a session like `store`, along with the client context given.
+    /// Unset fields are left out of the item.
+    pub async fn store_with_context(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        context: SessionContext,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        self.store_with_attributes(id, session, expires, context.into_attributes(self.config.missing_attributes), table_name)
+            .await
+    }
+
+    /// Lists every unexpired session like `list_active`, along with the
+    /// context stored by `store_with_context`.
+    pub async fn list_active_with_context(
+        &self,
+        table_name: &str,
+    ) -> Result<Vec<(String, i64, SessionContext)>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(
+            self.scan_keys(table_name, &[self.expires_attribute(), "ip", "user_agent"]),
+            Filter::live(self.expires_attribute()),
+        );
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                let id = self.item_id(item)?;
+                let expires = item.get(self.expires_attribute())?.as_n().ok()?.parse().ok()?;
+                Some((id, expires, SessionContext::from_item(item)))
+            })
+            .collect())
+    }
+}
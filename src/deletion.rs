@@ -0,0 +1,100 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+
+use crate::{
+    error,
+    expression::{self, Filter},
+    session_pool::{unix_now, DELETED_ATTRIBUTE},
+    SessionDynamoDbPool, SessionKey,
+};
+
+/// Attribute of a deletion record naming the session table it was deleted from.
+const SESSION_TABLE_ATTRIBUTE: &str = "session_table";
+
+/// Attribute of a deletion record holding the reason given for the deletion.
+const REASON_ATTRIBUTE: &str = "reason";
+
+/// A session deletion recorded under `with_deletion_log`, as listed by
+/// `recent_deletions`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deletion {
+    pub id: String,
+    /// Unix timestamp of the deletion, in seconds.
+    pub deleted_at: i64,
+    /// The reason passed to `delete_with_reason`, if any.
+    pub reason: Option<String>,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Deletes a session like `delete_one_by_id`, recording `reason` with
+    /// the deletion under `with_deletion_log`, e.g. `"logout"` or `"revoked"`.
+    pub async fn delete_with_reason(&self, id: &str, reason: &str, table_name: &str) -> Result<(), DatabaseError> {
+        self.delete_session(id, Some(reason), table_name).await
+    }
+
+    /// Lists the deletions of sessions from `table_name` recorded under
+    /// `with_deletion_log` whose retention hasn't run out, in no particular
+    /// order. Only the latest deletion of each id is kept.
+    pub async fn recent_deletions(&self, table_name: &str) -> Result<Vec<Deletion>, DatabaseError> {
+        let Some((log_table, _)) = &self.config.deletion_log else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "recent_deletions requires with_deletion_log".into(),
+            ));
+        };
+
+        let _permit = self.permit().await?;
+
+        let from_table = Filter::new("#session_table = :session_table")
+            .name("#session_table", SESSION_TABLE_ATTRIBUTE)
+            .value(":session_table", AttributeValue::S(self.full_table_name(table_name)));
+        let request = self.scan_keys(log_table, &[DELETED_ATTRIBUTE, REASON_ATTRIBUTE]);
+        let request = expression::and_filter(expression::and_filter(request, Filter::live(self.expires_attribute())), from_table);
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                Some(Deletion {
+                    id: self.item_id(item)?,
+                    deleted_at: item.get(DELETED_ATTRIBUTE)?.as_n().ok()?.parse().ok()?,
+                    reason: item.get(REASON_ATTRIBUTE).and_then(|r| r.as_s().ok()).cloned(),
+                })
+            })
+            .collect())
+    }
+
+    /// Writes the deletion record of `id` under `with_deletion_log`, before
+    /// the session is deleted. Called while the delete's permit is held, so
+    /// it takes none.
+    pub(crate) async fn log_deletion(
+        &self,
+        id: &str,
+        reason: Option<&str>,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let Some((log_table, retention)) = &self.config.deletion_log else {
+            return Ok(());
+        };
+
+        let now = unix_now();
+        let mut item = self.key(id)?;
+        item.insert(DELETED_ATTRIBUTE.into(), AttributeValue::N(now.to_string()));
+        item.insert(self.expires_attribute().into(), AttributeValue::N((now + retention.as_secs() as i64).to_string()));
+        item.insert(SESSION_TABLE_ATTRIBUTE.into(), AttributeValue::S(self.full_table_name(table_name)));
+        if let Some(reason) = reason {
+            item.insert(REASON_ATTRIBUTE.into(), AttributeValue::S(reason.into()));
+        }
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(log_table))
+            .set_item(Some(item));
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericDeleteError))?;
+        Ok(())
+    }
+}
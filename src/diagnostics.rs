@@ -0,0 +1,996 @@
+use std::time::{Duration, Instant};
+
+use aws_sdk_dynamodb::{
+    error::{ProvideErrorMetadata, SdkError},
+    types::{
+        AttributeValue, BillingMode, IndexStatus, KeySchemaElement, KeyType, Projection, ScalarAttributeType, SseStatus,
+        SseType, TableStatus, TimeToLiveStatus,
+    },
+};
+use axum_session::DatabaseError;
+use chrono::DateTime;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error,
+    expression::{self, Filter},
+    retry::ScannedItems,
+    session_pool::{expires_value, unix_now, DELETED_ATTRIBUTE, PINNED_ATTRIBUTE},
+    Capacity, ExpectedSse, KeyLayout, SessionDynamoDbPool, SessionKey,
+};
+
+/// TTL configuration of a table as reported by `DescribeTimeToLive`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TtlStatus {
+    /// The attribute TTL reads expiry from, if one was ever configured.
+    pub attribute_name: Option<String>,
+    pub status: TimeToLiveStatus,
+}
+
+impl TtlStatus {
+    /// Returns `true` once TTL is enabled, or is being enabled.
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.status, TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling)
+    }
+}
+
+/// How many sessions `verify_ttl` samples for a numeric `expires`.
+const TTL_SAMPLE_SIZE: i32 = 25;
+
+/// Result of `verify_ttl`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TtlHealth {
+    /// TTL configuration found on the table, before any repair.
+    pub ttl: TtlStatus,
+    /// Whether TTL is enabled, or being enabled, on `expires`.
+    pub on_expires: bool,
+    /// Number of sessions sampled.
+    pub sampled: usize,
+    /// Sampled sessions without a numeric `expires`, which TTL never removes.
+    pub missing_expires: usize,
+    /// Whether `verify_ttl` enabled TTL on `expires`.
+    pub repaired: bool,
+}
+
+impl TtlHealth {
+    /// Returns `true` if TTL covers `expires` and every sampled session has one.
+    pub fn is_healthy(&self) -> bool {
+        (self.on_expires || self.repaired) && self.missing_expires == 0
+    }
+}
+
+/// Result of `expiry_diagnostics`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpiryDiagnostics {
+    /// The attribute the pool writes expiries to.
+    pub configured_attribute: String,
+    /// TTL configuration found on the table.
+    pub ttl: TtlStatus,
+    /// Whether TTL is disabled or reads another attribute than the
+    /// configured one, so the pool's expiries never remove sessions.
+    pub ttl_mismatch: bool,
+    /// Expiry related attributes of up to 25 items.
+    pub samples: Vec<ExpirySample>,
+}
+
+/// The expiry related attributes of one item sampled by `expiry_diagnostics`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExpirySample {
+    pub id: String,
+    /// The configured `expires`, if it holds a unix timestamp.
+    pub expires: Option<i64>,
+    /// The numeric value of the attribute TTL reads, if it has one.
+    pub ttl_value: Option<i64>,
+    /// The `expires_iso` written under `with_expires_iso`.
+    pub expires_iso: Option<String>,
+    /// When the session was soft deleted, under `with_soft_delete`.
+    pub deleted_at: Option<i64>,
+    pub pinned: bool,
+    /// Whether the item's timestamps disagree: TTL has no numeric value to
+    /// remove an unpinned item by, that value isn't `expires`, or
+    /// `expires_iso` names another time.
+    pub mismatch: bool,
+}
+
+/// How many items `ttl_lag_sample` reads at most, expired or not.
+const TTL_LAG_SCAN_LIMIT: usize = 1000;
+
+/// How many expired items `ttl_lag_sample` stops after.
+const TTL_LAG_SAMPLE_SIZE: usize = 100;
+
+/// Result of `ttl_lag_sample`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TtlLag {
+    /// Number of items read.
+    pub scanned: usize,
+    /// Number of expired items found among them, which TTL hasn't removed yet.
+    pub expired: usize,
+    /// How long past its expiry the longest-expired item found is, a rough
+    /// estimate of how far behind TTL is. `None` if none was found.
+    pub oldest_overdue: Option<Duration>,
+}
+
+/// How long the item written by `latency_probe` lives should its cleanup
+/// fail, for TTL to remove it.
+const LATENCY_PROBE_TTL_SECS: i64 = 300;
+
+/// Round-trip latencies measured by `latency_probe`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// Number of reads and of writes measured.
+    pub samples: usize,
+    pub read_p50: Duration,
+    pub read_p95: Duration,
+    pub write_p50: Duration,
+    pub write_p95: Duration,
+}
+
+/// Id used by the `check_permissions` probes, in forms every built-in key
+/// type accepts. The probes never write it.
+const PROBE_IDS: &[&str] = &["00000000-0000-0000-0000-000000000000", "0"];
+
+/// Largest item DynamoDB stores, in bytes as `item_size_bytes` counts them.
+pub const MAX_ITEM_SIZE_BYTES: usize = 400 * 1024;
+
+/// Result of `check_permissions`: whether each action the pool relies on is
+/// allowed on the table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PermissionReport {
+    pub describe_table: bool,
+    pub get_item: bool,
+    pub put_item: bool,
+    pub delete_item: bool,
+    pub scan: bool,
+}
+
+impl PermissionReport {
+    /// Returns `true` if every probed action is allowed.
+    pub fn all_permitted(&self) -> bool {
+        self.describe_table && self.get_item && self.put_item && self.delete_item && self.scan
+    }
+}
+
+/// Result of `storage_bytes_estimate`, as last reported by DynamoDB, which
+/// refreshes these figures about every six hours.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StorageEstimate {
+    pub table_size_bytes: i64,
+    pub item_count: i64,
+}
+
+/// A secondary index as listed by `list_indexes`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexInfo {
+    pub name: String,
+    /// `true` for a global secondary index, `false` for a local one.
+    pub global: bool,
+    /// Key attributes with their roles, partition key first.
+    pub key_schema: Vec<(String, KeyType)>,
+    /// Status of a global index, `None` for local ones, which are built
+    /// along with the table.
+    pub status: Option<IndexStatus>,
+    /// Whether a global index is still being backfilled with existing items.
+    pub backfilling: bool,
+}
+
+impl IndexInfo {
+    /// Returns `true` once the index is active and fully backfilled.
+    pub fn is_ready(&self) -> bool {
+        !self.backfilling && self.status.as_ref().is_none_or(|status| *status == IndexStatus::Active)
+    }
+}
+
+/// The table `initiate` would create, as returned by
+/// `describe_intended_schema`. `initiate` sets no tags and leaves the table
+/// on DynamoDB's default encryption with an AWS owned key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntendedSchema {
+    /// Full table name, including any table prefix.
+    pub table_name: String,
+    /// Key attributes with their roles, partition key first.
+    pub key_schema: Vec<(String, KeyType)>,
+    /// Types of the attributes in the key schema of the table or its indexes.
+    pub attribute_definitions: Vec<(String, ScalarAttributeType)>,
+    pub capacity: Capacity,
+    /// Global indexes, which project only keys.
+    pub indexes: Vec<IntendedIndex>,
+    /// Attribute TTL is enabled on, `None` unless TTL is managed.
+    pub ttl_attribute: Option<String>,
+    pub sse: ExpectedSse,
+}
+
+/// A global index in an `IntendedSchema`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntendedIndex {
+    pub name: String,
+    /// Key attributes with their roles, partition key first.
+    pub key_schema: Vec<(String, KeyType)>,
+    /// The table's capacity, unless `with_index_capacity` overrides it.
+    pub capacity: Capacity,
+}
+
+/// A malformed session found by `validate_table`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The session's id, or the raw key attribute if it isn't a valid id.
+    pub id: String,
+    pub kind: ValidationIssueKind,
+}
+
+/// What is wrong with a session reported by `validate_table`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssueKind {
+    /// The `session` attribute is missing or of a type `load` can't read.
+    MissingSession,
+    /// An unpinned session without a numeric `expires`, which TTL never
+    /// removes and `load` treats as expired.
+    InvalidExpires,
+    /// The stored checksum doesn't match the payload.
+    ChecksumMismatch,
+    /// The payload fails to decode under the pool's options, with the error.
+    Undecodable(String),
+}
+
+impl StorageEstimate {
+    /// Average size of an item in bytes, `None` for an empty table.
+    pub fn average_item_bytes(&self) -> Option<i64> {
+        (self.item_count > 0).then(|| self.table_size_bytes / self.item_count)
+    }
+}
+
+/// The nearest-rank percentile `p` of `latencies`, zero if there are none.
+fn percentile(latencies: &mut [Duration], p: f64) -> Duration {
+    latencies.sort();
+    let rank = (p * latencies.len() as f64).ceil() as usize;
+    latencies.get(rank.saturating_sub(1)).copied().unwrap_or_default()
+}
+
+/// Turns a probe's outcome into whether the action is allowed. Failed
+/// conditions prove the action itself was authorized.
+fn probe_permitted<T, E, R>(result: Result<T, SdkError<E, R>>) -> Result<bool, DatabaseError>
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) => match e.code() {
+            Some("ConditionalCheckFailedException") => Ok(true),
+            Some("AccessDeniedException") => Ok(false),
+            _ => Err(DatabaseError::GenericSelectError(e.to_string())),
+        },
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Probes the actions the pool needs on the table with requests that
+    /// change nothing, so missing IAM permissions show up at startup rather
+    /// than on the first session. Writes are conditional on an impossible
+    /// condition, so they are authorized but never applied.
+    pub async fn check_permissions(&self, table_name: &str) -> Result<PermissionReport, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let key = PROBE_IDS
+            .iter()
+            .find_map(|id| self.key(id).ok())
+            .ok_or_else(|| DatabaseError::GenericNotSupportedError("no probe id fits the key type".into()))?;
+        let impossible = "attribute_exists(#id) AND attribute_not_exists(#id)";
+
+        let describe = self.client.describe_table().table_name(self.full_table_name(table_name));
+        let get = self
+            .client
+            .get_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(key.clone()));
+        let put = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(table_name))
+            .set_item(Some(key.clone()))
+            .condition_expression(impossible)
+            .expression_attribute_names("#id", self.id_attribute());
+        let delete = self
+            .client
+            .delete_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(key))
+            .condition_expression(impossible)
+            .expression_attribute_names("#id", self.id_attribute());
+        let scan = self.scan_sessions(table_name).limit(1);
+
+        Ok(PermissionReport {
+            describe_table: probe_permitted(self.send(|| describe.clone().send()).await)?,
+            get_item: probe_permitted(self.send(|| get.clone().send()).await)?,
+            put_item: probe_permitted(self.send(|| put.clone().send()).await)?,
+            delete_item: probe_permitted(self.send(|| delete.clone().send()).await)?,
+            scan: probe_permitted(self.send(|| scan.clone().send()).await)?,
+        })
+    }
+
+    /// Checks that TTL is enabled on `expires` and that a sample of
+    /// sessions carries a numeric `expires` for it to act on.
+    ///
+    /// With `repair`, TTL is enabled on `expires` when it is disabled. TTL
+    /// pointing at another attribute has to be disabled by hand first, as
+    /// DynamoDB takes up to an hour to do so.
+    pub async fn verify_ttl(&self, repair: bool, table_name: &str) -> Result<TtlHealth, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let ttl = self.describe_ttl(table_name).await?;
+        let on_expires = ttl.is_enabled() && ttl.attribute_name.as_deref() == Some(self.expires_attribute());
+
+        let request = self
+            .scan_keys(table_name, &[self.expires_attribute()])
+            .limit(TTL_SAMPLE_SIZE);
+
+        let sample = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .items
+            .unwrap_or_default();
+
+        let missing_expires = sample
+            .iter()
+            .filter(|item| item.get(self.expires_attribute()).and_then(|e| e.as_n().ok()).is_none())
+            .count();
+
+        let repaired = repair && ttl.status == TimeToLiveStatus::Disabled;
+        if repaired {
+            self.enable_ttl(table_name)
+                .await
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+        }
+
+        Ok(TtlHealth {
+            ttl,
+            on_expires,
+            sampled: sample.len(),
+            missing_expires,
+            repaired,
+        })
+    }
+
+    /// Puts the attribute the pool writes expiries to next to the one TTL
+    /// actually reads, and samples the expiry related attributes of up to 25
+    /// items, flagging those whose timestamps disagree, to troubleshoot
+    /// sessions that don't expire when they should.
+    pub async fn expiry_diagnostics(&self, table_name: &str) -> Result<ExpiryDiagnostics, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let ttl = self.describe_ttl(table_name).await?;
+        let configured_attribute = self.expires_attribute().to_string();
+        let ttl_attribute = ttl.attribute_name.clone().filter(|_| ttl.is_enabled());
+        let ttl_mismatch = ttl_attribute.as_ref() != Some(&configured_attribute);
+
+        let mut extra = vec![self.expires_attribute(), "expires_iso", DELETED_ATTRIBUTE, PINNED_ATTRIBUTE];
+        extra.extend(ttl_attribute.as_deref());
+        let request = self.scan_keys(table_name, &extra).limit(TTL_SAMPLE_SIZE);
+
+        let items = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .items
+            .unwrap_or_default();
+
+        let samples = items
+            .iter()
+            .filter_map(|item| {
+                let expires = self.item_expires(item);
+                let ttl_value = ttl_attribute
+                    .as_ref()
+                    .and_then(|attribute| item.get(attribute))
+                    .and_then(|value| value.as_n().ok())
+                    .and_then(|value| value.parse().ok());
+                let expires_iso = item.get("expires_iso").and_then(|value| value.as_s().ok()).cloned();
+                let pinned = item.contains_key(PINNED_ATTRIBUTE);
+
+                let iso_mismatch = expires_iso.as_deref().is_some_and(|iso| {
+                    DateTime::parse_from_rfc3339(iso).ok().map(|time| time.timestamp()) != expires
+                });
+                let mismatch = (!pinned && ttl_value.is_none())
+                    || (ttl_value.is_some() && ttl_value != expires)
+                    || iso_mismatch;
+
+                Some(ExpirySample {
+                    id: self.item_id(item)?,
+                    expires,
+                    ttl_value,
+                    expires_iso,
+                    deleted_at: item.get(DELETED_ATTRIBUTE).and_then(expires_value),
+                    pinned,
+                    mismatch,
+                })
+            })
+            .collect();
+
+        Ok(ExpiryDiagnostics {
+            configured_attribute,
+            ttl,
+            ttl_mismatch,
+            samples,
+        })
+    }
+
+    /// Reports whether TTL is enabled on the table and which attribute it uses.
+    pub async fn ttl_status(&self, table_name: &str) -> Result<TtlStatus, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        self.describe_ttl(table_name).await
+    }
+
+    /// Estimates how far behind TTL is in removing expired sessions, which
+    /// DynamoDB documents as usually within a few days, to decide whether
+    /// reads may rely on TTL alone. Reads up to 1,000 items, stopping once
+    /// 100 expired ones are found, so the estimate is a lower bound that
+    /// covers a part of the table only.
+    pub async fn ttl_lag_sample(&self, table_name: &str) -> Result<TtlLag, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let now = unix_now();
+        let request = expression::and_filter(self.scan_keys(table_name, &[self.expires_attribute()]), Filter::expired(self.expires_attribute()));
+
+        let mut lag = TtlLag::default();
+        let scanned = ScannedItems::default();
+        let mut start_key = None;
+
+        loop {
+            let remaining = TTL_LAG_SCAN_LIMIT - lag.scanned;
+            let page_request = request.clone().limit(i32::try_from(remaining).unwrap_or(i32::MAX));
+            let page = self.scan_page(&page_request, start_key.take(), &scanned).await?;
+
+            lag.scanned += usize::try_from(page.scanned_count()).unwrap_or_default();
+            for expires in page.items().iter().filter_map(|item| self.item_expires(item)) {
+                let overdue = Duration::from_secs(u64::try_from(now - expires).unwrap_or_default());
+                lag.expired += 1;
+                lag.oldest_overdue = lag.oldest_overdue.max(Some(overdue));
+            }
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() || lag.scanned >= TTL_LAG_SCAN_LIMIT || lag.expired >= TTL_LAG_SAMPLE_SIZE {
+                return Ok(lag);
+            }
+        }
+    }
+
+    /// `ttl_status` for callers already holding a permit.
+    pub(crate) async fn describe_ttl(&self, table_name: &str) -> Result<TtlStatus, DatabaseError> {
+        let request = self
+            .client
+            .describe_time_to_live()
+            .table_name(self.full_table_name(table_name));
+
+        let description = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .time_to_live_description;
+
+        Ok(match description {
+            Some(description) => TtlStatus {
+                attribute_name: description.attribute_name,
+                status: description.time_to_live_status.unwrap_or(TimeToLiveStatus::Disabled),
+            },
+            None => TtlStatus {
+                attribute_name: None,
+                status: TimeToLiveStatus::Disabled,
+            },
+        })
+    }
+
+    /// Lists the attributes stored for a session with their DynamoDB type
+    /// tags (`S`, `N`, `B`, `BOOL`, `M`, ...), sorted by name, e.g. to find
+    /// out whether a table created elsewhere stores `expires` as a Number.
+    /// Returns `None` if there is no such item, expired or not.
+    pub async fn inspect_item(&self, id: &str, table_name: &str) -> Result<Option<Vec<(String, String)>>, DatabaseError> {
+        let Some(item) = self.get_item(id, self.config.default_read_consistency, table_name).await? else {
+            return Ok(None);
+        };
+
+        let mut attributes: Vec<_> = item
+            .iter()
+            .map(|(name, value)| (name.clone(), type_tag(value).to_string()))
+            .collect();
+        attributes.sort();
+
+        Ok(Some(attributes))
+    }
+
+    /// Size of a session's item as DynamoDB accounts for it against
+    /// [`MAX_ITEM_SIZE_BYTES`], attribute names included, e.g. to trim a
+    /// session before it outgrows the limit. Returns `None` if there is no
+    /// such item, expired or not.
+    pub async fn item_size_bytes(&self, id: &str, table_name: &str) -> Result<Option<usize>, DatabaseError> {
+        let item = self.get_item(id, self.config.default_read_consistency, table_name).await?;
+
+        Ok(item.map(|item| item.iter().map(|(name, value)| name.len() + value_size(value)).sum()))
+    }
+
+    /// Reports the table's size and item count from `DescribeTable`, e.g. to
+    /// weigh shorter TTLs or compression. Both lag by up to about six hours
+    /// and cover the whole table, including items that aren't sessions.
+    pub async fn storage_bytes_estimate(&self, table_name: &str) -> Result<StorageEstimate, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.client.describe_table().table_name(self.full_table_name(table_name));
+
+        let table = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .table;
+
+        Ok(StorageEstimate {
+            table_size_bytes: table.as_ref().and_then(|t| t.table_size_bytes).unwrap_or_default(),
+            item_count: table.as_ref().and_then(|t| t.item_count).unwrap_or_default(),
+        })
+    }
+
+    /// Reports whether the table is billed on demand or for provisioned
+    /// capacity, e.g. to skip client-side rate limiting on on-demand
+    /// tables. Tables DynamoDB reports no billing mode for predate on-demand
+    /// billing and are provisioned.
+    pub async fn billing_mode(&self, table_name: &str) -> Result<BillingMode, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.client.describe_table().table_name(self.full_table_name(table_name));
+
+        let table = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .table;
+
+        Ok(table
+            .and_then(|table| table.billing_mode_summary?.billing_mode)
+            .unwrap_or(BillingMode::Provisioned))
+    }
+
+    /// Lists the table's secondary indexes with their status, e.g. to
+    /// confirm the index set up by `with_user_index` or `with_expiry_index`
+    /// finished backfilling before relying on it.
+    pub async fn list_indexes(&self, table_name: &str) -> Result<Vec<IndexInfo>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.client.describe_table().table_name(self.full_table_name(table_name));
+
+        let Some(table) = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .table
+        else {
+            return Ok(Vec::new());
+        };
+
+        let key_schema = |elements: &[KeySchemaElement]| {
+            elements
+                .iter()
+                .map(|element| (element.attribute_name().to_string(), element.key_type().clone()))
+                .collect()
+        };
+
+        let global = table.global_secondary_indexes().iter().map(|index| IndexInfo {
+            name: index.index_name().unwrap_or_default().to_string(),
+            global: true,
+            key_schema: key_schema(index.key_schema()),
+            status: index.index_status().cloned(),
+            backfilling: index.backfilling().unwrap_or_default(),
+        });
+        let local = table.local_secondary_indexes().iter().map(|index| IndexInfo {
+            name: index.index_name().unwrap_or_default().to_string(),
+            global: false,
+            key_schema: key_schema(index.key_schema()),
+            status: None,
+            backfilling: false,
+        });
+
+        Ok(global.chain(local).collect())
+    }
+
+    /// Measures the round-trip latency of `samples` small writes and
+    /// strongly consistent reads of a throwaway item, e.g. at startup to spot
+    /// a pool talking to a distant region. The item holds only its key and
+    /// an `expires`, is keyed by an id from the pool's id generator, see
+    /// `with_id_generator`, and is deleted afterwards, or removed by TTL if
+    /// that fails. Latencies include the pool's retries.
+    pub async fn latency_probe(&self, samples: usize, table_name: &str) -> Result<LatencyReport, DatabaseError> {
+        let samples = samples.max(1);
+        let id = (self.config.id_generator)();
+        let key = self.key(&id)?;
+
+        let mut item = key.clone();
+        item.insert(
+            self.expires_attribute().into(),
+            AttributeValue::N((unix_now() + LATENCY_PROBE_TTL_SECS).to_string()),
+        );
+
+        let table = self.full_table_name(table_name);
+        let put = self.client.put_item().table_name(&table).set_item(Some(item));
+        let (projection, names) = self.key_projection(&[]);
+        let get = self
+            .client
+            .get_item()
+            .table_name(&table)
+            .set_key(Some(key.clone()))
+            .consistent_read(true)
+            .projection_expression(projection)
+            .set_expression_attribute_names(Some(names));
+
+        let _permit = self.permit().await?;
+
+        let mut reads = Vec::with_capacity(samples);
+        let mut writes = Vec::with_capacity(samples);
+        let mut result = Ok(());
+
+        for _ in 0..samples {
+            let started = Instant::now();
+            if let Err(e) = self.send(|| put.clone().send()).await {
+                result = Err(error::map_sdk_error(e, DatabaseError::GenericInsertError));
+                break;
+            }
+            writes.push(started.elapsed());
+
+            let started = Instant::now();
+            if let Err(e) = self.send(|| get.clone().send()).await {
+                result = Err(error::map_sdk_error(e, DatabaseError::GenericSelectError));
+                break;
+            }
+            reads.push(started.elapsed());
+        }
+
+        let delete = self.client.delete_item().table_name(&table).set_key(Some(key));
+        if let Err(e) = self.send(|| delete.clone().send()).await {
+            tracing::warn!("failed to delete latency probe item {id}, left to TTL: {e}");
+        }
+        result?;
+
+        Ok(LatencyReport {
+            samples,
+            read_p50: percentile(&mut reads, 0.5),
+            read_p95: percentile(&mut reads, 0.95),
+            write_p50: percentile(&mut writes, 0.5),
+            write_p95: percentile(&mut writes, 0.95),
+        })
+    }
+
+    /// Hashes the table's key schema, attribute definitions, billing mode,
+    /// secondary indexes, encryption and TTL into a hex SHA-256 digest, for
+    /// deployments to compare against an expected value and catch manual
+    /// changes. The table's name, its status and its provisioned capacity,
+    /// which auto scaling adjusts, don't enter it, so identical tables in
+    /// different environments share a fingerprint.
+    pub async fn schema_fingerprint(&self, table_name: &str) -> Result<String, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.client.describe_table().table_name(self.full_table_name(table_name));
+
+        let Some(table) = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?
+            .table
+        else {
+            return Err(DatabaseError::GenericSelectError(format!(
+                "{}: {table_name}",
+                error::TABLE_NOT_FOUND
+            )));
+        };
+        let ttl = self.describe_ttl(table_name).await?;
+
+        let key_schema = |elements: &[KeySchemaElement]| {
+            let mut elements: Vec<_> = elements
+                .iter()
+                .map(|element| format!("{}={}", element.key_type().as_str(), element.attribute_name()))
+                .collect();
+            elements.sort();
+            elements.join(",")
+        };
+        let projection = |projection: Option<&Projection>| {
+            let mut non_key: Vec<_> = projection.map(|p| p.non_key_attributes()).unwrap_or_default().to_vec();
+            non_key.sort();
+            let kind = projection.and_then(|p| p.projection_type()).map_or("", |t| t.as_str());
+            format!("{kind}({})", non_key.join(","))
+        };
+
+        let mut lines = vec![format!("key {}", key_schema(table.key_schema()))];
+
+        lines.extend(
+            table
+                .attribute_definitions()
+                .iter()
+                .map(|d| format!("attribute {}={}", d.attribute_name(), d.attribute_type().as_str())),
+        );
+
+        let billing_mode = table
+            .billing_mode_summary()
+            .and_then(|summary| summary.billing_mode())
+            .unwrap_or(&BillingMode::Provisioned);
+        lines.push(format!("billing {}", billing_mode.as_str()));
+
+        lines.extend(table.global_secondary_indexes().iter().map(|index| {
+            format!(
+                "gsi {} {} {}",
+                index.index_name().unwrap_or_default(),
+                key_schema(index.key_schema()),
+                projection(index.projection())
+            )
+        }));
+        lines.extend(table.local_secondary_indexes().iter().map(|index| {
+            format!(
+                "lsi {} {} {}",
+                index.index_name().unwrap_or_default(),
+                key_schema(index.key_schema()),
+                projection(index.projection())
+            )
+        }));
+
+        let sse = table
+            .sse_description()
+            .filter(|sse| matches!(sse.status(), Some(SseStatus::Enabled | SseStatus::Enabling | SseStatus::Updating)));
+        lines.push(match sse {
+            Some(sse) => format!(
+                "sse {} {}",
+                sse.sse_type().map_or("", |t| t.as_str()),
+                sse.kms_master_key_arn().unwrap_or_default()
+            ),
+            None => "sse default".into(),
+        });
+
+        lines.push(match (ttl.is_enabled(), &ttl.attribute_name) {
+            (true, Some(attribute)) => format!("ttl {attribute}"),
+            _ => "ttl disabled".into(),
+        });
+
+        // Every line starts with its kind and indexes with their name, so
+        // sorting makes the order DynamoDB lists things in irrelevant.
+        lines.sort();
+
+        Ok(hex::encode(Sha256::digest(lines.join("\n").as_bytes())))
+    }
+
+    /// Checks that an existing table is keyed the way the pool expects.
+    /// `DescribeTable` can briefly return an incomplete description right
+    /// after another process created the table, without a key schema or
+    /// with attribute definitions missing for its keys. Such descriptions
+    /// are retried with backoff until `with_schema_check_timeout` runs out,
+    /// while a complete description that doesn't match fails at once.
+    pub(crate) async fn validate_key_schema(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let table_name = self.full_table_name(table_name);
+        let mut expected = Vec::new();
+
+        if let KeyLayout::Composite { partition_key, .. } = &self.config.key_layout {
+            expected.push((partition_key.as_str(), KeyType::Hash));
+            expected.push((self.id_attribute(), KeyType::Range));
+        } else {
+            expected.push((self.id_attribute(), KeyType::Hash));
+        }
+
+        let request = self.client.describe_table().table_name(&table_name);
+        let deadline = Instant::now() + self.config.schema_check_timeout;
+        let mut attempt = 0;
+
+        loop {
+            let table = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?
+                .table;
+
+            let key_schema = table.as_ref().map(|t| t.key_schema()).unwrap_or_default();
+            let definitions = table.as_ref().map(|t| t.attribute_definitions()).unwrap_or_default();
+
+            let actual: Vec<_> = key_schema
+                .iter()
+                .map(|element| (element.attribute_name(), element.key_type().clone()))
+                .collect();
+
+            if actual == expected {
+                return Ok(());
+            }
+
+            let complete = !key_schema.is_empty()
+                && key_schema.iter().all(|element| {
+                    definitions
+                        .iter()
+                        .any(|definition| definition.attribute_name() == element.attribute_name())
+                });
+
+            let backoff = Duration::from_millis(100 << attempt.min(4));
+            if complete || Instant::now() + backoff > deadline {
+                return Err(DatabaseError::GenericCreateError(format!(
+                    "table {table_name} is keyed by {actual:?}, expected {expected:?}"
+                )));
+            }
+
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Checks that the table's server-side encryption is `expected`.
+    /// DynamoDB reports no SSE description for tables encrypted with the
+    /// AWS owned key.
+    pub(crate) async fn verify_sse(&self, table_name: &str, expected: &ExpectedSse) -> Result<(), DatabaseError> {
+        let table_name = self.full_table_name(table_name);
+        let request = self.client.describe_table().table_name(&table_name);
+
+        let sse = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?
+            .table
+            .and_then(|table| table.sse_description)
+            .filter(|sse| matches!(sse.status(), Some(SseStatus::Enabled | SseStatus::Enabling | SseStatus::Updating)));
+
+        let key_arn = sse.as_ref().and_then(|sse| sse.kms_master_key_arn());
+        let matches = match (expected, sse.as_ref().and_then(|sse| sse.sse_type())) {
+            (ExpectedSse::AwsOwned, None) => true,
+            (ExpectedSse::Kms { key_arn: None }, Some(SseType::Kms)) => true,
+            (ExpectedSse::Kms { key_arn: Some(expected) }, Some(SseType::Kms)) => key_arn == Some(expected.as_str()),
+            _ => false,
+        };
+
+        if matches {
+            return Ok(());
+        }
+
+        let actual = match sse.as_ref().and_then(|sse| sse.sse_type()) {
+            None => "the AWS owned key".to_string(),
+            Some(sse_type) => format!("{} key {}", sse_type.as_str(), key_arn.unwrap_or("unknown")),
+        };
+        Err(DatabaseError::GenericCreateError(format!(
+            "table {table_name} is encrypted with {actual}, expected {expected:?}"
+        )))
+    }
+
+    /// Explains a `ResourceNotFoundException` from a read: DynamoDB also
+    /// raises it while the table is being created or updated, which is
+    /// reported as [`error::TABLE_NOT_ACTIVE`] so callers know to retry, and
+    /// for missing tables, reported as [`error::TABLE_NOT_FOUND`].
+    ///
+    /// `table_name` is the name the failed request was sent to, including
+    /// any table prefix.
+    pub(crate) async fn resource_not_found(&self, table_name: &str, message: String) -> DatabaseError {
+        let request = self.client.describe_table().table_name(table_name);
+
+        let status = self
+            .send(|| request.clone().send())
+            .await
+            .ok()
+            .and_then(|output| output.table?.table_status);
+
+        match status {
+            Some(status) if status != TableStatus::Active => DatabaseError::GenericSelectError(format!(
+                "{}: table {table_name} is {status}",
+                error::TABLE_NOT_ACTIVE
+            )),
+            Some(_) => DatabaseError::GenericSelectError(message),
+            None => DatabaseError::GenericSelectError(format!("{}: {message}", error::TABLE_NOT_FOUND)),
+        }
+    }
+
+    /// Describes the table `initiate` would create for `table_name` under
+    /// the current options, without calling AWS, e.g. to review it or to
+    /// write it down as infrastructure code.
+    pub fn describe_intended_schema(&self, table_name: &str) -> IntendedSchema {
+        let id = self.id_attribute().to_string();
+
+        let (mut key_schema, mut attribute_definitions) = match &self.config.key_layout {
+            KeyLayout::Simple => (Vec::new(), Vec::new()),
+            KeyLayout::Composite { partition_key, .. } => (
+                vec![(partition_key.clone(), KeyType::Hash)],
+                vec![(partition_key.clone(), ScalarAttributeType::S)],
+            ),
+        };
+        let id_key_type = if key_schema.is_empty() { KeyType::Hash } else { KeyType::Range };
+        key_schema.push((id.clone(), id_key_type));
+        attribute_definitions.push((id, self.id_attribute_type()));
+
+        let managed = self.managed_indexes();
+
+        // Every index is sorted by `expires`.
+        if !managed.is_empty() {
+            attribute_definitions.push((self.expires_attribute().into(), ScalarAttributeType::N));
+        }
+
+        let mut indexes = Vec::new();
+        for (index_name, partition_key) in managed {
+            indexes.push(IntendedIndex {
+                name: index_name.to_string(),
+                key_schema: vec![(partition_key.to_string(), KeyType::Hash), (self.expires_attribute().into(), KeyType::Range)],
+                capacity: self.index_capacity(index_name),
+            });
+            attribute_definitions.push((partition_key.to_string(), ScalarAttributeType::S));
+        }
+
+        IntendedSchema {
+            table_name: self.full_table_name(table_name),
+            key_schema,
+            attribute_definitions,
+            capacity: self.config.capacity,
+            indexes,
+            ttl_attribute: self.config.manage_ttl.then(|| self.expires_attribute().into()),
+            sse: ExpectedSse::AwsOwned,
+        }
+    }
+
+    /// Scans every session and reports the ones `load` would fail on or
+    /// misread, as a dry-run audit: nothing is changed. Checks payloads with
+    /// the pool's codec, encryption and checksum options, so a pool set up
+    /// like the one serving the table gives the most accurate report.
+    pub async fn validate_table(&self, table_name: &str) -> Result<Vec<ValidationIssue>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let mut issues = Vec::new();
+
+        self.scan_pages(self.scan_sessions(table_name), |page| {
+            for item in page.items.unwrap_or_default() {
+                let id = self.item_id(&item).unwrap_or_else(|| {
+                    item.get(self.id_attribute()).map(|id| format!("{id:?}")).unwrap_or_default()
+                });
+                let mut report = |kind| issues.push(ValidationIssue { id: id.clone(), kind });
+
+                let expires = item.get(self.expires_attribute()).and_then(|e| e.as_n().ok()?.parse::<i64>().ok());
+                if expires.is_none() && !item.contains_key(PINNED_ATTRIBUTE) {
+                    report(ValidationIssueKind::InvalidExpires);
+                }
+
+                match self.decode_session(&id, &item) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => report(ValidationIssueKind::MissingSession),
+                    Err(e) if error::is_checksum_mismatch(&e) => report(ValidationIssueKind::ChecksumMismatch),
+                    Err(e) => report(ValidationIssueKind::Undecodable(e.to_string())),
+                }
+            }
+        })
+        .await?;
+
+        Ok(issues)
+    }
+}
+
+/// Bytes DynamoDB charges for an attribute value, without its name.
+/// Numbers take a byte per two significant digits plus one; lists and maps
+/// take three bytes plus a byte per element on top of their contents.
+fn value_size(value: &AttributeValue) -> usize {
+    match value {
+        AttributeValue::S(s) => s.len(),
+        AttributeValue::N(n) => number_size(n),
+        AttributeValue::B(b) => b.as_ref().len(),
+        AttributeValue::Bool(_) | AttributeValue::Null(_) => 1,
+        AttributeValue::Ss(values) => values.iter().map(String::len).sum(),
+        AttributeValue::Ns(values) => values.iter().map(|n| number_size(n)).sum(),
+        AttributeValue::Bs(values) => values.iter().map(|b| b.as_ref().len()).sum(),
+        AttributeValue::L(values) => 3 + values.iter().map(|value| 1 + value_size(value)).sum::<usize>(),
+        AttributeValue::M(map) => {
+            3 + map.iter().map(|(name, value)| 1 + name.len() + value_size(value)).sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+fn number_size(n: &str) -> usize {
+    let mantissa = n.split(['e', 'E']).next().unwrap_or(n);
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let significant = digits.trim_start_matches('0').trim_end_matches('0').len();
+    significant.div_ceil(2) + 1
+}
+
+/// DynamoDB's type descriptor for an attribute value.
+fn type_tag(value: &AttributeValue) -> &'static str {
+    match value {
+        AttributeValue::S(_) => "S",
+        AttributeValue::N(_) => "N",
+        AttributeValue::B(_) => "B",
+        AttributeValue::Bool(_) => "BOOL",
+        AttributeValue::Null(_) => "NULL",
+        AttributeValue::M(_) => "M",
+        AttributeValue::L(_) => "L",
+        AttributeValue::Ss(_) => "SS",
+        AttributeValue::Ns(_) => "NS",
+        AttributeValue::Bs(_) => "BS",
+        _ => "UNKNOWN",
+    }
+}
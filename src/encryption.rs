@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Key, Nonce,
+};
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{error, SessionDynamoDbPool, SessionKey};
+
+/// Marks a `session` string holding an encrypted payload: the base64 of the
+/// key version byte, the nonce and the AES-256-GCM ciphertext.
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Length of an AES-GCM nonce in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Keys for client-side encryption of session payloads, set with
+/// `with_encryption`. Each key has a version that is stored with every
+/// payload it encrypts, so payloads written under a retired key still load
+/// after the primary key is rotated.
+#[derive(Clone)]
+pub struct EncryptionKeys {
+    primary: u8,
+    keys: HashMap<u8, [u8; 32]>,
+}
+
+impl EncryptionKeys {
+    /// Encrypts new payloads with the AES-256 `key`, tagged with `version`.
+    pub fn new(version: u8, key: [u8; 32]) -> Self {
+        Self {
+            primary: version,
+            keys: HashMap::from([(version, key)]),
+        }
+    }
+
+    /// Keeps a previous key to decrypt payloads tagged with `version`. A
+    /// retired key never replaces the primary key.
+    pub fn with_retired(mut self, version: u8, key: [u8; 32]) -> Self {
+        if version != self.primary {
+            self.keys.insert(version, key);
+        }
+        self
+    }
+
+    /// Version of the key new payloads are encrypted with.
+    pub fn primary_version(&self) -> u8 {
+        self.primary
+    }
+
+    fn cipher(&self, version: u8) -> Option<Aes256Gcm> {
+        self.keys
+            .get(&version)
+            .map(|key| Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)))
+    }
+
+    fn encrypt(&self, payload: &str) -> Result<String, DatabaseError> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher(self.primary)
+            .ok_or_else(|| DatabaseError::GenericInsertError("missing primary encryption key".into()))?
+            .encrypt(Nonce::from_slice(&nonce), payload.as_bytes())
+            .map_err(|e| DatabaseError::GenericInsertError(format!("failed to encrypt session: {e}")))?;
+
+        let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        sealed.push(self.primary);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend(ciphertext);
+
+        Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(sealed)))
+    }
+
+    fn decrypt(&self, id: &str, sealed: &str) -> Result<String, DatabaseError> {
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+
+        let Some((&version, rest)) = sealed.split_first().filter(|(_, rest)| rest.len() >= NONCE_LEN) else {
+            return Err(DatabaseError::GenericSelectError(format!("session {id} has a truncated encrypted payload")));
+        };
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let cipher = self.cipher(version).ok_or_else(|| {
+            DatabaseError::GenericSelectError(format!("session {id} is encrypted with unknown key version {version}"))
+        })?;
+        let payload = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DatabaseError::GenericSelectError(format!("failed to decrypt session {id}")))?;
+
+        String::from_utf8(payload).map_err(|e| DatabaseError::GenericSelectError(e.to_string()))
+    }
+}
+
+impl std::fmt::Debug for EncryptionKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut versions: Vec<_> = self.keys.keys().collect();
+        versions.sort();
+
+        f.debug_struct("EncryptionKeys")
+            .field("primary", &self.primary)
+            .field("versions", &versions)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Key version of an encrypted `session` string, `None` for plain ones.
+fn key_version(stored: &str) -> Option<u8> {
+    let sealed = stored.strip_prefix(ENCRYPTED_PREFIX)?;
+    STANDARD.decode(sealed).ok()?.first().copied()
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Encrypts an encoded payload under the primary key, if encryption is
+    /// configured.
+    pub(crate) fn encrypt_payload(&self, encoded: String) -> Result<String, DatabaseError> {
+        match &self.config.encryption {
+            Some(keys) => keys.encrypt(&encoded),
+            None => Ok(encoded),
+        }
+    }
+
+    /// Undoes `encrypt_payload`. Payloads without the encryption marker are
+    /// returned as they are, so sessions stored before encryption was
+    /// enabled still load.
+    pub(crate) fn decrypt_payload(&self, id: &str, stored: &str) -> Result<String, DatabaseError> {
+        match (&self.config.encryption, stored.strip_prefix(ENCRYPTED_PREFIX)) {
+            (Some(keys), Some(sealed)) => keys.decrypt(id, sealed),
+            (None, Some(_)) => Err(DatabaseError::GenericSelectError(format!(
+                "session {id} is encrypted but no encryption keys are configured"
+            ))),
+            (_, None) => Ok(stored.into()),
+        }
+    }
+
+    /// Rewrites every session that isn't encrypted under the primary key,
+    /// e.g. after a rotation, so the retired keys can eventually be dropped.
+    /// Each rewrite only applies if the payload is unchanged since the scan,
+    /// so concurrent stores win. Returns the number of rewritten sessions.
+    pub async fn reencrypt_all(&self, table_name: &str) -> Result<usize, DatabaseError> {
+        let Some(keys) = &self.config.encryption else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "reencrypt_all requires with_encryption".into(),
+            ));
+        };
+
+        self.rewrite_encrypted(table_name, |version| version == Some(keys.primary)).await
+    }
+
+    /// Encrypts the sessions stored in plain text under the primary key,
+    /// e.g. once encryption is enabled on an existing table, leaving those
+    /// already encrypted, under any key, untouched, so it can be rerun
+    /// safely. Like `reencrypt_all`, rewrites lose to concurrent stores.
+    /// Returns the number of encrypted sessions.
+    pub async fn encrypt_existing(&self, table_name: &str) -> Result<usize, DatabaseError> {
+        if self.config.encryption.is_none() {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "encrypt_existing requires with_encryption".into(),
+            ));
+        }
+
+        self.rewrite_encrypted(table_name, |version| version.is_some()).await
+    }
+
+    /// Encrypts every String payload under the primary key, unless `skip`
+    /// holds for the key version it is encrypted with, `None` if it is plain.
+    async fn rewrite_encrypted(
+        &self,
+        table_name: &str,
+        skip: impl Fn(Option<u8>) -> bool,
+    ) -> Result<usize, DatabaseError> {
+        let Some(keys) = &self.config.encryption else {
+            return Ok(0);
+        };
+
+        let _permit = self.permit().await?;
+
+        let items = self.scan_items(self.scan_keys(table_name, &["session"])).await?;
+
+        let mut rewritten = 0;
+
+        for item in items {
+            let (Some(key), Some(AttributeValue::S(stored))) = (self.item_key(&item), item.get("session")) else {
+                continue;
+            };
+            if skip(key_version(stored)) {
+                continue;
+            }
+
+            let id = self.item_id(&item).unwrap_or_default();
+            let encoded = self.decrypt_payload(&id, stored)?;
+
+            let request = self
+                .client
+                .update_item()
+                .table_name(self.full_table_name(table_name))
+                .set_key(Some(key))
+                .update_expression("SET #session = :session")
+                .condition_expression("#session = :stored")
+                .expression_attribute_names("#session", "session")
+                .expression_attribute_values(":session", AttributeValue::S(keys.encrypt(&encoded)?))
+                .expression_attribute_values(":stored", AttributeValue::S(stored.clone()));
+
+            let result = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+            match result {
+                Ok(_) => rewritten += 1,
+                Err(e) if error::is_condition_failed(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(rewritten)
+    }
+}
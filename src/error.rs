@@ -0,0 +1,272 @@
+use std::time::Duration;
+
+use aws_sdk_dynamodb::{
+    config::http::HttpResponse,
+    error::{DisplayErrorContext, ProvideErrorMetadata, SdkError},
+    operation::{delete_item::DeleteItemError, put_item::PutItemError, update_item::UpdateItemError},
+};
+use axum_session::DatabaseError;
+use serde_json::{Map, Value};
+
+use crate::{codec, Item};
+
+/// Message prefix of every error caused by an unmet condition expression.
+///
+/// `DatabaseError` is defined by axum_session and can't grow new variants,
+/// so failed conditions are reported through the operation's usual variant
+/// with this prefix. Use [`is_condition_failed`] rather than matching on it.
+pub const CONDITION_FAILED: &str = "condition not met";
+
+/// Message prefix of errors caused by a table that exists but is still
+/// `CREATING` or `UPDATING`. Such calls are worth retrying once it settles.
+pub const TABLE_NOT_ACTIVE: &str = "table not active";
+
+/// Message prefix of the error `store` fails with under
+/// `StoreMode::RejectExisting` when the id already has a session.
+pub const SESSION_EXISTS: &str = "session already exists";
+
+/// Message prefix of the error a write fails with when, while TTL is
+/// managed, the item it would store has no valid numeric `expires`, see
+/// `with_strict_expires_check`.
+pub const MISSING_EXPIRES: &str = "missing expires";
+
+/// Message prefix of errors caused by DynamoDB throttling the request once
+/// the pool's own retries, if any, gave up. Use [`retry_after`] for how
+/// long to back off.
+pub const THROTTLED: &str = "throttled";
+
+/// Message prefix of errors caused by a scan that read more items than
+/// `with_max_scanned_items` allows.
+pub const SCAN_LIMIT_EXCEEDED: &str = "scan limit exceeded";
+
+/// Message prefix of errors caused by a write that would grow an item
+/// collection, the items sharing a partition key value in a table with a
+/// local secondary index, past DynamoDB's 10 GB limit. Retrying won't help:
+/// items in the partition have to be deleted, or the data spread across
+/// more partition key values.
+pub const ITEM_COLLECTION_LIMIT_EXCEEDED: &str = "item collection size limit exceeded";
+
+/// Message prefix of the error a load fails with when the payload doesn't
+/// match its checksum.
+const CHECKSUM_MISMATCH: &str = "checksum mismatch";
+
+/// Error codes DynamoDB throttles requests with.
+const THROTTLING_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+];
+
+/// Backoff suggested for throttled requests without a `Retry-After`
+/// header. DynamoDB meters capacity per second, so that's when it refills.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Message prefix of errors caused by a table that doesn't exist. Writes
+/// to a table that is still being created report it too, as DynamoDB
+/// doesn't tell the two apart there.
+pub const TABLE_NOT_FOUND: &str = "table not found";
+
+/// Separates the message of a failed condition from the item that failed
+/// it, which [`conflicting_item`] reads back.
+const CURRENT_ITEM_MARKER: &str = "; current item: ";
+
+/// Returns `true` if the error was caused by a `ConditionalCheckFailedException`.
+pub fn is_condition_failed(err: &DatabaseError) -> bool {
+    message(err).starts_with(CONDITION_FAILED)
+}
+
+/// Returns `true` if the call failed because the table wasn't ACTIVE yet.
+pub fn is_table_not_active(err: &DatabaseError) -> bool {
+    message(err).starts_with(TABLE_NOT_ACTIVE)
+}
+
+/// Returns `true` if a store was rejected because the session already exists.
+pub fn is_session_exists(err: &DatabaseError) -> bool {
+    message(err).starts_with(SESSION_EXISTS)
+}
+
+/// Returns `true` if a write was rejected for storing a session without a
+/// valid `expires`.
+pub fn is_missing_expires(err: &DatabaseError) -> bool {
+    message(err).starts_with(MISSING_EXPIRES)
+}
+
+/// Returns `true` if DynamoDB throttled the call.
+pub fn is_throttled(err: &DatabaseError) -> bool {
+    message(err).starts_with(THROTTLED)
+}
+
+/// Returns `true` if a scan was stopped by `with_max_scanned_items`.
+pub fn is_scan_limit_exceeded(err: &DatabaseError) -> bool {
+    message(err).starts_with(SCAN_LIMIT_EXCEEDED)
+}
+
+/// How long to wait before retrying a throttled call: the response's
+/// `Retry-After` header if it had one, a second otherwise. Returns `None`
+/// for errors other than throttling.
+pub fn retry_after(err: &DatabaseError) -> Option<Duration> {
+    let millis = message(err).strip_prefix(THROTTLED)?.strip_prefix(", retry after ")?;
+    let millis = &millis[..millis.find("ms")?];
+    millis.parse().ok().map(Duration::from_millis)
+}
+
+/// The item that made a conditional write fail, as DynamoDB returned it
+/// along with a [`CONDITION_FAILED`] or [`SESSION_EXISTS`] error, so
+/// optimistic retry loops can skip a read. Attributes are converted like
+/// [`attribute_value_to_json`](crate::attribute_value_to_json) does;
+/// Binary ones are left out. Returns `None` for other errors and for
+/// conditions that failed on a missing item.
+pub fn conflicting_item(err: &DatabaseError) -> Option<Map<String, Value>> {
+    if !is_condition_failed(err) && !is_session_exists(err) {
+        return None;
+    }
+    let (_, item) = message(err).split_once(CURRENT_ITEM_MARKER)?;
+    serde_json::from_str(item).ok()
+}
+
+/// Returns `true` if the write would have grown an item collection past
+/// 10 GB, see [`ITEM_COLLECTION_LIMIT_EXCEEDED`].
+pub fn is_item_collection_limit_exceeded(err: &DatabaseError) -> bool {
+    message(err).starts_with(ITEM_COLLECTION_LIMIT_EXCEEDED)
+}
+
+/// Returns `true` if the call failed because the table doesn't exist.
+pub fn is_table_not_found(err: &DatabaseError) -> bool {
+    message(err).starts_with(TABLE_NOT_FOUND)
+}
+
+fn message(err: &DatabaseError) -> &str {
+    match err {
+        DatabaseError::GenericAquire(msg)
+        | DatabaseError::GenericInsertError(msg)
+        | DatabaseError::GenericSelectError(msg)
+        | DatabaseError::GenericCreateError(msg)
+        | DatabaseError::GenericDeleteError(msg)
+        | DatabaseError::GenericNotSupportedError(msg) => msg,
+    }
+}
+
+/// Converts an SDK error into the given `DatabaseError` variant, giving
+/// failed condition expressions, throttling and missing tables their
+/// well-known message.
+pub(crate) fn map_sdk_error<E>(err: SdkError<E, HttpResponse>, variant: fn(String) -> DatabaseError) -> DatabaseError
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    if err.code().is_some_and(|code| THROTTLING_CODES.contains(&code)) {
+        let retry_after = err
+            .raw_response()
+            .and_then(|response| response.headers().get("retry-after"))
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+
+        return variant(format!(
+            "{THROTTLED}, retry after {}ms: {}",
+            retry_after.as_millis(),
+            DisplayErrorContext(&err)
+        ));
+    }
+
+    if err.code() == Some("ConditionalCheckFailedException") {
+        return variant(format!("{CONDITION_FAILED}: {}", DisplayErrorContext(&err)));
+    }
+
+    if err.code() == Some("ResourceNotFoundException") {
+        return variant(format!("{TABLE_NOT_FOUND}: {}", DisplayErrorContext(&err)));
+    }
+
+    if err.code() == Some("ItemCollectionSizeLimitExceededException") {
+        return variant(format!(
+            "{ITEM_COLLECTION_LIMIT_EXCEEDED}, delete items from the partition or spread them over more partition key values: {}",
+            DisplayErrorContext(&err)
+        ));
+    }
+
+    variant(err.to_string())
+}
+
+/// Service errors that can carry the item a condition failed on.
+pub(crate) trait ConditionFailure {
+    fn current_item(&self) -> Option<&Item>;
+}
+
+impl ConditionFailure for PutItemError {
+    fn current_item(&self) -> Option<&Item> {
+        match self {
+            Self::ConditionalCheckFailedException(e) => e.item(),
+            _ => None,
+        }
+    }
+}
+
+impl ConditionFailure for UpdateItemError {
+    fn current_item(&self) -> Option<&Item> {
+        match self {
+            Self::ConditionalCheckFailedException(e) => e.item(),
+            _ => None,
+        }
+    }
+}
+
+impl ConditionFailure for DeleteItemError {
+    fn current_item(&self) -> Option<&Item> {
+        match self {
+            Self::ConditionalCheckFailedException(e) => e.item(),
+            _ => None,
+        }
+    }
+}
+
+/// `map_sdk_error` for conditional writes sent with
+/// `ReturnValuesOnConditionCheckFailure::AllOld`, which appends the item
+/// returned with a failed condition to the message.
+pub(crate) fn map_conditional_error<E>(err: SdkError<E, HttpResponse>, variant: fn(String) -> DatabaseError) -> DatabaseError
+where
+    E: ConditionFailure + ProvideErrorMetadata + std::error::Error + 'static,
+{
+    let item = err.as_service_error().and_then(ConditionFailure::current_item).cloned();
+    let mapped = map_sdk_error(err, variant);
+
+    match item {
+        Some(item) if is_condition_failed(&mapped) => variant(with_current_item(message(&mapped), &item)),
+        _ => mapped,
+    }
+}
+
+/// Appends `item` to an error message for [`conflicting_item`].
+pub(crate) fn with_current_item(message: &str, item: &Item) -> String {
+    let item: Map<String, Value> = item
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), codec::attribute_value_to_json(value)?)))
+        .collect();
+
+    format!("{message}{CURRENT_ITEM_MARKER}{}", Value::Object(item))
+}
+
+/// The part of a failed condition's message that carries the current item,
+/// empty if there is none, for errors that rephrase the message.
+pub(crate) fn current_item_suffix(err: &DatabaseError) -> &str {
+    let message = message(err);
+    message.find(CURRENT_ITEM_MARKER).map_or("", |at| &message[at..])
+}
+
+/// Rebuilds `err` for another caller, as `DatabaseError` isn't `Clone`.
+pub(crate) fn duplicate(err: &DatabaseError) -> DatabaseError {
+    let msg = message(err).to_string();
+    match err {
+        DatabaseError::GenericAquire(_) => DatabaseError::GenericAquire(msg),
+        DatabaseError::GenericInsertError(_) => DatabaseError::GenericInsertError(msg),
+        DatabaseError::GenericSelectError(_) => DatabaseError::GenericSelectError(msg),
+        DatabaseError::GenericCreateError(_) => DatabaseError::GenericCreateError(msg),
+        DatabaseError::GenericDeleteError(_) => DatabaseError::GenericDeleteError(msg),
+        DatabaseError::GenericNotSupportedError(_) => DatabaseError::GenericNotSupportedError(msg),
+    }
+}
+
+pub(crate) fn checksum_mismatch(id: &str) -> DatabaseError {
+    DatabaseError::GenericSelectError(format!("{CHECKSUM_MISMATCH} for session {id}"))
+}
+
+pub(crate) fn is_checksum_mismatch(err: &DatabaseError) -> bool {
+    message(err).starts_with(CHECKSUM_MISMATCH)
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{operation::scan::builders::ScanFluentBuilder, types::AttributeValue};
+use axum_session::DatabaseError;
+
+use crate::session_pool::unix_now;
+
+/// A filter expression together with the placeholders it references.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Filter {
+    pub(crate) expression: String,
+    pub(crate) names: HashMap<String, String>,
+    pub(crate) values: HashMap<String, AttributeValue>,
+}
+
+impl Filter {
+    pub(crate) fn new(expression: impl Into<String>) -> Self {
+        Self {
+            expression: expression.into(),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn name(mut self, placeholder: &str, name: impl Into<String>) -> Self {
+        self.names.insert(placeholder.into(), name.into());
+        self
+    }
+
+    pub(crate) fn value(mut self, placeholder: &str, value: AttributeValue) -> Self {
+        self.values.insert(placeholder.into(), value);
+        self
+    }
+
+    /// Combines two filters into one that only matches what both match.
+    pub(crate) fn and(mut self, other: Filter) -> Self {
+        self.expression = format!("({}) AND ({})", self.expression, other.expression);
+        self.names.extend(other.names);
+        self.values.extend(other.values);
+        self
+    }
+
+    /// Wraps a caller supplied filter expression, checking that every
+    /// placeholder it uses is mapped so a typo fails before any request.
+    pub(crate) fn checked(
+        expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+    ) -> Result<Self, DatabaseError> {
+        for placeholder in placeholders(expression) {
+            let mapped = match placeholder.as_bytes()[0] {
+                b'#' => names.contains_key(placeholder),
+                _ => values.contains_key(placeholder),
+            };
+
+            if !mapped {
+                return Err(DatabaseError::GenericSelectError(format!(
+                    "placeholder {placeholder} in filter expression has no mapping"
+                )));
+            }
+        }
+
+        Ok(Self {
+            expression: expression.into(),
+            names,
+            values,
+        })
+    }
+
+    /// Selects sessions that are still live: those whose expiry, held in
+    /// `expires_attribute`, is in the future and those without one, which
+    /// never expire.
+    pub(crate) fn live(expires_attribute: &str) -> Self {
+        Self::new("attribute_not_exists(#expires) OR #expires > :now").expires_now(expires_attribute)
+    }
+
+    /// Selects sessions whose expiry has passed, the complement of [`Filter::live`].
+    pub(crate) fn expired(expires_attribute: &str) -> Self {
+        Self::new("#expires <= :now").expires_now(expires_attribute)
+    }
+
+    fn expires_now(self, expires_attribute: &str) -> Self {
+        self.name("#expires", expires_attribute)
+            .value(":now", AttributeValue::N(unix_now().to_string()))
+    }
+}
+
+/// Returns the `#name` and `:value` placeholders used in an expression.
+fn placeholders(expression: &str) -> impl Iterator<Item = &str> {
+    expression
+        .split(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '#' | ':')))
+        .filter(|token| token.len() > 1 && (token.starts_with('#') || token.starts_with(':')))
+}
+
+/// Adds a filter to a scan, combined with any filter it already has.
+pub(crate) fn and_filter(request: ScanFluentBuilder, filter: Filter) -> ScanFluentBuilder {
+    let expression = match request.get_filter_expression() {
+        Some(existing) => format!("({existing}) AND ({})", filter.expression),
+        None => filter.expression,
+    };
+
+    let request = filter
+        .names
+        .into_iter()
+        .fold(request.filter_expression(expression), |request, (placeholder, name)| {
+            request.expression_attribute_names(placeholder, name)
+        });
+
+    filter
+        .values
+        .into_iter()
+        .fold(request, |request, (placeholder, value)| {
+            request.expression_attribute_values(placeholder, value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_pool::test_pool;
+
+    #[test]
+    fn checked_rejects_unmapped_placeholders() {
+        let names = HashMap::from([("#user".to_string(), "user".to_string())]);
+        let values = HashMap::from([(":user".to_string(), AttributeValue::S("alice".into()))]);
+
+        assert!(Filter::checked("#user = :user", names.clone(), values.clone()).is_ok());
+        assert!(Filter::checked("#user = :other", names.clone(), values.clone()).is_err());
+        assert!(Filter::checked("#owner = :user", names, values).is_err());
+    }
+
+    #[test]
+    fn and_filter_combines_expressions_and_placeholders() {
+        let request = test_pool().client.scan();
+        let first = Filter::new("#a = :a").name("#a", "a").value(":a", AttributeValue::N("1".into()));
+        let second = Filter::live("expires_at");
+
+        let request = and_filter(and_filter(request, first), second);
+
+        assert_eq!(
+            request.get_filter_expression().as_deref(),
+            Some("(#a = :a) AND (attribute_not_exists(#expires) OR #expires > :now)")
+        );
+        let names = request.get_expression_attribute_names().clone().unwrap_or_default();
+        assert_eq!(names.get("#a").map(String::as_str), Some("a"));
+        assert_eq!(names.get("#expires").map(String::as_str), Some("expires_at"));
+        let values = request.get_expression_attribute_values().clone().unwrap_or_default();
+        assert!(values.contains_key(":a") && values.contains_key(":now"));
+    }
+
+    #[test]
+    fn expired_reads_the_given_attribute() {
+        let filter = Filter::expired("ttl");
+
+        assert_eq!(filter.expression, "#expires <= :now");
+        assert_eq!(filter.names.get("#expires").map(String::as_str), Some("ttl"));
+    }
+}
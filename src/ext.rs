@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::{DatabaseError, DatabasePool};
+
+use crate::{
+    ActivitySummary, Deletion, ExpirySweepStats, Item, LoadMany, SessionContext, SessionDynamoDbPool, SessionKey, SessionValidity,
+};
+
+/// DynamoDB specific operations that aren't part of axum_session's
+/// [`DatabasePool`].
+///
+/// `SessionDynamoDbPool` is object safe for `DatabasePool`, so it can sit
+/// behind `axum_session::SessionAnyPool` (a `dyn DatabasePool`) next to
+/// other pools. Code that needs the methods below keeps a
+/// `Arc<dyn DynamoSessionExt>` to the same pool, which is object safe too.
+#[async_trait]
+pub trait DynamoSessionExt: DatabasePool + Send + Sync {
+    async fn prepare(&self, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn drop_table(&self, table_name: &str, wait: bool) -> Result<(), DatabaseError>;
+
+    async fn load_raw(&self, id: &str, table_name: &str) -> Result<Option<Item>, DatabaseError>;
+
+    async fn load_versioned(&self, id: &str, table_name: &str) -> Result<Option<(u8, String)>, DatabaseError>;
+
+    async fn validate(&self, id: &str, table_name: &str) -> Result<SessionValidity, DatabaseError>;
+
+    async fn load_many(&self, ids: &[String], table_name: &str) -> Result<LoadMany, DatabaseError>;
+
+    async fn load_ordered(&self, ids: &[String], table_name: &str) -> Result<Vec<Option<String>>, DatabaseError>;
+
+    async fn regenerate(&self, old_id: &str, new_id: &str, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn regenerate_with_token(
+        &self,
+        old_id: &str,
+        new_id: &str,
+        token: &str,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn store_transactional(&self, items: &[(String, String, i64)], table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn store_transactional_with_token(
+        &self,
+        items: &[(String, String, i64)],
+        token: &str,
+        table_name: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn store_returning_old(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<Option<String>, DatabaseError>;
+
+    async fn create(&self, session: &str, expires: i64, table_name: &str) -> Result<String, DatabaseError>;
+
+    async fn store_if(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        expected_current: Option<String>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn upsert(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn store_with_attributes(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        extra: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn store_with_header(
+        &self,
+        id: &str,
+        header: &str,
+        body: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn load_header(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError>;
+
+    async fn store_for_user(
+        &self,
+        id: &str,
+        user_id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn count_for_user(&self, user_id: &str, table_name: &str) -> Result<i64, DatabaseError>;
+
+    async fn distinct_active_users(&self, table_name: &str) -> Result<i64, DatabaseError>;
+
+    async fn expire_now(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn consume(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError>;
+
+    async fn delete_with_reason(&self, id: &str, reason: &str, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn recent_deletions(&self, table_name: &str) -> Result<Vec<Deletion>, DatabaseError>;
+
+    async fn store_sharded(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn load_sharded(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError>;
+
+    async fn delete_sharded(&self, id: &str, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn try_set_flag(&self, id: &str, attribute: &str, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn pin(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn unpin(&self, id: &str, expires: i64, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn delete_created_before(&self, cutoff_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError>;
+
+    async fn list_active(&self, table_name: &str) -> Result<Vec<(String, i64)>, DatabaseError>;
+
+    async fn partition_by_expiry(
+        &self,
+        now: i64,
+        table_name: &str,
+    ) -> Result<(Vec<String>, Vec<String>), DatabaseError>;
+
+    async fn get_ids_with_prefix(&self, prefix: &str, table_name: &str) -> Result<Vec<String>, DatabaseError>;
+
+    async fn get_ids_in_partition(&self, partition_value: &str, table_name: &str) -> Result<Vec<String>, DatabaseError>;
+
+    async fn ids_modified_since(&self, since_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError>;
+
+    async fn remap_attributes(&self, mapping: HashMap<String, String>, table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn migrate_ttl_attribute(&self, new_name: &str, table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn expiry_histogram(&self, buckets: &[i64], table_name: &str) -> Result<Vec<(i64, i64)>, DatabaseError>;
+
+    async fn activity_summary(&self, table_name: &str) -> Result<ActivitySummary, DatabaseError>;
+
+    async fn store_with_context(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        context: SessionContext,
+        table_name: &str,
+    ) -> Result<(), DatabaseError>;
+
+    async fn list_active_with_context(
+        &self,
+        table_name: &str,
+    ) -> Result<Vec<(String, i64, SessionContext)>, DatabaseError>;
+
+    async fn revoke(&self, id: &str, until_unix: i64, table_name: &str) -> Result<(), DatabaseError>;
+
+    async fn is_revoked(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn delete_all_stale(&self, before_unix: i64, table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn delete_all_forced(&self, table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn touch(&self, id: &str, delta_seconds: i64, table_name: &str) -> Result<bool, DatabaseError>;
+
+    async fn touch_many(&self, ids: &[String], new_expires: i64, table_name: &str) -> Result<Vec<String>, DatabaseError>;
+
+    async fn extend_all(&self, additional_seconds: i64, table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn delete_by_expiry_paged(
+        &self,
+        max_items: usize,
+        table_name: &str,
+    ) -> Result<(Vec<String>, bool), DatabaseError>;
+
+    async fn delete_by_expiry_stats(&self, table_name: &str) -> Result<ExpirySweepStats, DatabaseError>;
+
+    async fn query_index(
+        &self,
+        index_name: &str,
+        key_values: &[AttributeValue],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError>;
+
+    async fn store_many_conditional(
+        &self,
+        items: &[(String, String, i64)],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError>;
+
+    async fn warm(&self, ids: &[String], table_name: &str) -> Result<usize, DatabaseError>;
+
+    async fn count_filtered(
+        &self,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<i64, DatabaseError>;
+
+    async fn exists_where(
+        &self,
+        id: &str,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn count_active_via_index(&self, table_name: &str) -> Result<i64, DatabaseError>;
+
+    async fn ids_by_expiry(
+        &self,
+        ascending: bool,
+        limit: usize,
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError>;
+}
+
+#[async_trait]
+impl<K: SessionKey> DynamoSessionExt for SessionDynamoDbPool<K> {
+    async fn prepare(&self, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::prepare(self, table_name).await
+    }
+
+    async fn drop_table(&self, table_name: &str, wait: bool) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::drop_table(self, table_name, wait).await
+    }
+
+    async fn load_raw(&self, id: &str, table_name: &str) -> Result<Option<Item>, DatabaseError> {
+        SessionDynamoDbPool::load_raw(self, id, table_name).await
+    }
+
+    async fn load_versioned(&self, id: &str, table_name: &str) -> Result<Option<(u8, String)>, DatabaseError> {
+        SessionDynamoDbPool::load_versioned(self, id, table_name).await
+    }
+
+    async fn validate(&self, id: &str, table_name: &str) -> Result<SessionValidity, DatabaseError> {
+        SessionDynamoDbPool::validate(self, id, table_name).await
+    }
+
+    async fn load_many(&self, ids: &[String], table_name: &str) -> Result<LoadMany, DatabaseError> {
+        SessionDynamoDbPool::load_many(self, ids, table_name).await
+    }
+
+    async fn load_ordered(&self, ids: &[String], table_name: &str) -> Result<Vec<Option<String>>, DatabaseError> {
+        SessionDynamoDbPool::load_ordered(self, ids, table_name).await
+    }
+
+    async fn regenerate(&self, old_id: &str, new_id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::regenerate(self, old_id, new_id, table_name).await
+    }
+
+    async fn regenerate_with_token(
+        &self,
+        old_id: &str,
+        new_id: &str,
+        token: &str,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::regenerate_with_token(self, old_id, new_id, token, table_name).await
+    }
+
+    async fn store_transactional(&self, items: &[(String, String, i64)], table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_transactional(self, items, table_name).await
+    }
+
+    async fn store_transactional_with_token(
+        &self,
+        items: &[(String, String, i64)],
+        token: &str,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_transactional_with_token(self, items, token, table_name).await
+    }
+
+    async fn store_returning_old(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        SessionDynamoDbPool::store_returning_old(self, id, session, expires, table_name).await
+    }
+
+    async fn create(&self, session: &str, expires: i64, table_name: &str) -> Result<String, DatabaseError> {
+        SessionDynamoDbPool::create(self, session, expires, table_name).await
+    }
+
+    async fn store_if(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        expected_current: Option<String>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::store_if(self, id, session, expires, expected_current, table_name).await
+    }
+
+    async fn upsert(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::upsert(self, id, session, expires, table_name).await
+    }
+
+    async fn store_with_attributes(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        extra: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_with_attributes(self, id, session, expires, extra, table_name).await
+    }
+
+    async fn store_with_header(
+        &self,
+        id: &str,
+        header: &str,
+        body: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_with_header(self, id, header, body, expires, table_name).await
+    }
+
+    async fn load_header(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        SessionDynamoDbPool::load_header(self, id, table_name).await
+    }
+
+    async fn store_for_user(
+        &self,
+        id: &str,
+        user_id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_for_user(self, id, user_id, session, expires, table_name).await
+    }
+
+    async fn count_for_user(&self, user_id: &str, table_name: &str) -> Result<i64, DatabaseError> {
+        SessionDynamoDbPool::count_for_user(self, user_id, table_name).await
+    }
+
+    async fn distinct_active_users(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        SessionDynamoDbPool::distinct_active_users(self, table_name).await
+    }
+
+    async fn expire_now(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::expire_now(self, id, table_name).await
+    }
+
+    async fn consume(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        SessionDynamoDbPool::consume(self, id, table_name).await
+    }
+
+    async fn delete_with_reason(&self, id: &str, reason: &str, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::delete_with_reason(self, id, reason, table_name).await
+    }
+
+    async fn recent_deletions(&self, table_name: &str) -> Result<Vec<Deletion>, DatabaseError> {
+        SessionDynamoDbPool::recent_deletions(self, table_name).await
+    }
+
+    async fn store_sharded(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_sharded(self, id, session, expires, table_name).await
+    }
+
+    async fn load_sharded(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        SessionDynamoDbPool::load_sharded(self, id, table_name).await
+    }
+
+    async fn delete_sharded(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::delete_sharded(self, id, table_name).await
+    }
+
+    async fn try_set_flag(&self, id: &str, attribute: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::try_set_flag(self, id, attribute, table_name).await
+    }
+
+    async fn pin(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::pin(self, id, table_name).await
+    }
+
+    async fn unpin(&self, id: &str, expires: i64, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::unpin(self, id, expires, table_name).await
+    }
+
+    async fn delete_created_before(&self, cutoff_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::delete_created_before(self, cutoff_unix, table_name).await
+    }
+
+    async fn list_active(&self, table_name: &str) -> Result<Vec<(String, i64)>, DatabaseError> {
+        SessionDynamoDbPool::list_active(self, table_name).await
+    }
+
+    async fn partition_by_expiry(
+        &self,
+        now: i64,
+        table_name: &str,
+    ) -> Result<(Vec<String>, Vec<String>), DatabaseError> {
+        SessionDynamoDbPool::partition_by_expiry(self, now, table_name).await
+    }
+
+    async fn get_ids_with_prefix(&self, prefix: &str, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::get_ids_with_prefix(self, prefix, table_name).await
+    }
+
+    async fn get_ids_in_partition(&self, partition_value: &str, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::get_ids_in_partition(self, partition_value, table_name).await
+    }
+
+    async fn ids_modified_since(&self, since_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::ids_modified_since(self, since_unix, table_name).await
+    }
+
+    async fn remap_attributes(&self, mapping: HashMap<String, String>, table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::remap_attributes(self, mapping, table_name).await
+    }
+
+    async fn migrate_ttl_attribute(&self, new_name: &str, table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::migrate_ttl_attribute(self, new_name, table_name).await
+    }
+
+    async fn expiry_histogram(&self, buckets: &[i64], table_name: &str) -> Result<Vec<(i64, i64)>, DatabaseError> {
+        SessionDynamoDbPool::expiry_histogram(self, buckets, table_name).await
+    }
+
+    async fn activity_summary(&self, table_name: &str) -> Result<ActivitySummary, DatabaseError> {
+        SessionDynamoDbPool::activity_summary(self, table_name).await
+    }
+
+    async fn store_with_context(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        context: SessionContext,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::store_with_context(self, id, session, expires, context, table_name).await
+    }
+
+    async fn list_active_with_context(
+        &self,
+        table_name: &str,
+    ) -> Result<Vec<(String, i64, SessionContext)>, DatabaseError> {
+        SessionDynamoDbPool::list_active_with_context(self, table_name).await
+    }
+
+    async fn revoke(&self, id: &str, until_unix: i64, table_name: &str) -> Result<(), DatabaseError> {
+        SessionDynamoDbPool::revoke(self, id, until_unix, table_name).await
+    }
+
+    async fn is_revoked(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::is_revoked(self, id, table_name).await
+    }
+
+    async fn delete_all_stale(&self, before_unix: i64, table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::delete_all_stale(self, before_unix, table_name).await
+    }
+
+    async fn delete_all_forced(&self, table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::delete_all_forced(self, table_name).await
+    }
+
+    async fn touch(&self, id: &str, delta_seconds: i64, table_name: &str) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::touch(self, id, delta_seconds, table_name).await
+    }
+
+    async fn touch_many(&self, ids: &[String], new_expires: i64, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::touch_many(self, ids, new_expires, table_name).await
+    }
+
+    async fn extend_all(&self, additional_seconds: i64, table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::extend_all(self, additional_seconds, table_name).await
+    }
+
+    async fn delete_by_expiry_paged(
+        &self,
+        max_items: usize,
+        table_name: &str,
+    ) -> Result<(Vec<String>, bool), DatabaseError> {
+        SessionDynamoDbPool::delete_by_expiry_paged(self, max_items, table_name).await
+    }
+
+    async fn delete_by_expiry_stats(&self, table_name: &str) -> Result<ExpirySweepStats, DatabaseError> {
+        SessionDynamoDbPool::delete_by_expiry_stats(self, table_name).await
+    }
+
+    async fn query_index(
+        &self,
+        index_name: &str,
+        key_values: &[AttributeValue],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::query_index(self, index_name, key_values, table_name).await
+    }
+
+    async fn store_many_conditional(
+        &self,
+        items: &[(String, String, i64)],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::store_many_conditional(self, items, table_name).await
+    }
+
+    async fn warm(&self, ids: &[String], table_name: &str) -> Result<usize, DatabaseError> {
+        SessionDynamoDbPool::warm(self, ids, table_name).await
+    }
+
+    async fn count_filtered(
+        &self,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<i64, DatabaseError> {
+        SessionDynamoDbPool::count_filtered(self, filter_expression, names, values, table_name).await
+    }
+
+    async fn exists_where(
+        &self,
+        id: &str,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        SessionDynamoDbPool::exists_where(self, id, filter_expression, names, values, table_name).await
+    }
+
+    async fn count_active_via_index(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        SessionDynamoDbPool::count_active_via_index(self, table_name).await
+    }
+
+    async fn ids_by_expiry(
+        &self,
+        ascending: bool,
+        limit: usize,
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        SessionDynamoDbPool::ids_by_expiry(self, ascending, limit, table_name).await
+    }
+}
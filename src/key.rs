@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    operation::scan::builders::ScanFluentBuilder,
+    primitives::Blob,
+    types::{AttributeValue, ScalarAttributeType},
+};
+use axum_session::DatabaseError;
+use uuid::Uuid;
+
+use crate::{
+    codec,
+    expression::{self, Filter},
+    Item, SessionDynamoDbPool,
+};
+
+/// How session items are keyed in the table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyLayout {
+    /// The session id is the table's only key, in an `id` attribute.
+    #[default]
+    Simple,
+    /// The session id is the sort key under a constant partition key value,
+    /// so sessions can share a table with other entities in a single-table
+    /// design. Scans only consider items in that partition.
+    Composite {
+        partition_key: String,
+        partition_value: String,
+        sort_key: String,
+    },
+}
+
+/// How session ids are represented in the table's `id` attribute.
+///
+/// axum_session always hands ids to the pool as strings; a key type parses
+/// them into its DynamoDB representation and formats them back for `get_ids`.
+pub trait SessionKey: Clone + std::fmt::Debug + Send + Sync + 'static {
+    /// The scalar type of the `id` attribute in the key schema.
+    fn attribute_type() -> ScalarAttributeType;
+
+    fn to_attribute_value(id: &str) -> Result<AttributeValue, DatabaseError>;
+
+    fn from_attribute_value(value: &AttributeValue) -> Option<String>;
+}
+
+/// Ids stored as-is in a String (`S`) attribute.
+impl SessionKey for String {
+    fn attribute_type() -> ScalarAttributeType {
+        ScalarAttributeType::S
+    }
+
+    fn to_attribute_value(id: &str) -> Result<AttributeValue, DatabaseError> {
+        Ok(AttributeValue::S(id.into()))
+    }
+
+    fn from_attribute_value(value: &AttributeValue) -> Option<String> {
+        value.as_s().ok().cloned()
+    }
+}
+
+/// UUID ids stored as their 16 raw bytes in a Binary (`B`) attribute.
+impl SessionKey for Uuid {
+    fn attribute_type() -> ScalarAttributeType {
+        ScalarAttributeType::B
+    }
+
+    fn to_attribute_value(id: &str) -> Result<AttributeValue, DatabaseError> {
+        let uuid = Uuid::parse_str(id)
+            .map_err(|e| DatabaseError::GenericNotSupportedError(format!("invalid session id {id}: {e}")))?;
+        Ok(AttributeValue::B(Blob::new(uuid.as_bytes().to_vec())))
+    }
+
+    fn from_attribute_value(value: &AttributeValue) -> Option<String> {
+        let bytes = value.as_b().ok()?;
+        Uuid::from_slice(bytes.as_ref()).ok().map(|uuid| uuid.to_string())
+    }
+}
+
+/// Integer ids stored in a Number (`N`) attribute.
+impl SessionKey for i64 {
+    fn attribute_type() -> ScalarAttributeType {
+        ScalarAttributeType::N
+    }
+
+    fn to_attribute_value(id: &str) -> Result<AttributeValue, DatabaseError> {
+        let id: i64 = id
+            .parse()
+            .map_err(|e| DatabaseError::GenericNotSupportedError(format!("invalid session id {id}: {e}")))?;
+        Ok(AttributeValue::N(id.to_string()))
+    }
+
+    fn from_attribute_value(value: &AttributeValue) -> Option<String> {
+        value.as_n().ok().cloned()
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Builds the primary key of a session's item.
+    pub(crate) fn key(&self, id: &str) -> Result<Item, DatabaseError> {
+        let prefix = self.config.key_value_prefix.as_deref();
+
+        let id = match (&self.config.id_salt, prefix) {
+            (Some(salt), prefix) => AttributeValue::S(format!("{}{}", prefix.unwrap_or_default(), codec::hash_id(salt, id))),
+            (None, Some(prefix)) => {
+                // Still reject ids that aren't valid for the key type.
+                K::to_attribute_value(id)?;
+                AttributeValue::S(format!("{prefix}{id}"))
+            }
+            (None, None) => K::to_attribute_value(id)?,
+        };
+
+        Ok(match &self.config.key_layout {
+            KeyLayout::Simple => Item::from([("id".to_string(), id)]),
+            KeyLayout::Composite {
+                partition_key,
+                partition_value,
+                sort_key,
+            } => Item::from([
+                (partition_key.clone(), AttributeValue::S(partition_value.clone())),
+                (sort_key.clone(), id),
+            ]),
+        })
+    }
+
+    /// Name of the attribute holding the session id.
+    pub(crate) fn id_attribute(&self) -> &str {
+        match &self.config.key_layout {
+            KeyLayout::Simple => "id",
+            KeyLayout::Composite { sort_key, .. } => sort_key,
+        }
+    }
+
+    /// Scalar type of the attribute holding the session id.
+    pub(crate) fn id_attribute_type(&self) -> ScalarAttributeType {
+        if self.config.id_salt.is_some() || self.config.key_value_prefix.is_some() {
+            ScalarAttributeType::S
+        } else {
+            K::attribute_type()
+        }
+    }
+
+    /// Projection expression selecting the key attributes, followed by
+    /// `extra`, together with its attribute name placeholders. Names are
+    /// always escaped, so key names that are reserved words work too.
+    pub(crate) fn key_projection(&self, extra: &[&str]) -> (String, HashMap<String, String>) {
+        let mut attributes = match &self.config.key_layout {
+            KeyLayout::Simple => vec!["id"],
+            KeyLayout::Composite {
+                partition_key,
+                sort_key,
+                ..
+            } => vec![partition_key.as_str(), sort_key.as_str()],
+        };
+        attributes.extend(extra);
+
+        let names: HashMap<_, _> = attributes
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (format!("#projected{i}"), name.to_string()))
+            .collect();
+        let expression = (0..attributes.len())
+            .map(|i| format!("#projected{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        (expression, names)
+    }
+
+    /// Starts a scan over the sessions like `scan_sessions`, returning only
+    /// their key attributes and `extra`.
+    pub(crate) fn scan_keys(&self, table_name: &str, extra: &[&str]) -> ScanFluentBuilder {
+        let (expression, names) = self.key_projection(extra);
+
+        names.into_iter().fold(
+            self.scan_sessions(table_name).projection_expression(expression),
+            |request, (placeholder, name)| request.expression_attribute_names(placeholder, name),
+        )
+    }
+
+    /// Extracts the session id from a scanned item.
+    pub(crate) fn item_id(&self, item: &Item) -> Option<String> {
+        let id = item.get(self.id_attribute())?;
+
+        match (&self.config.id_salt, &self.config.key_value_prefix) {
+            (_, Some(prefix)) => id.as_s().ok()?.strip_prefix(prefix.as_str()).map(str::to_string),
+            (Some(_), None) => id.as_s().ok().cloned(),
+            (None, None) => K::from_attribute_value(id),
+        }
+    }
+
+    /// Extracts the primary key from a scanned item.
+    pub(crate) fn item_key(&self, item: &Item) -> Option<Item> {
+        let attribute = |name: &str| Some((name.to_string(), item.get(name)?.clone()));
+
+        match &self.config.key_layout {
+            KeyLayout::Simple => Some(Item::from([attribute("id")?])),
+            KeyLayout::Composite {
+                partition_key,
+                sort_key,
+                ..
+            } => Some(Item::from([attribute(partition_key)?, attribute(sort_key)?])),
+        }
+    }
+
+    /// Starts a scan over the sessions in the table, restricted to the
+    /// session partition under the composite layout and to ids carrying the
+    /// key value prefix, if any.
+    pub(crate) fn scan_sessions(&self, table_name: &str) -> ScanFluentBuilder {
+        let mut request = self.client.scan().table_name(self.full_table_name(table_name));
+
+        if let Some(prefix) = &self.config.key_value_prefix {
+            request = expression::and_filter(
+                request,
+                Filter::new("begins_with(#session_id, :session_prefix)")
+                    .name("#session_id", self.id_attribute())
+                    .value(":session_prefix", AttributeValue::S(prefix.clone())),
+            );
+        }
+
+        if let Some(stats_id) = self.activity_summary_id() {
+            request = expression::and_filter(
+                request,
+                Filter::new("#stats_id <> :stats_id")
+                    .name("#stats_id", self.id_attribute())
+                    .value(":stats_id", stats_id),
+            );
+        }
+
+        match &self.config.key_layout {
+            KeyLayout::Simple => request,
+            KeyLayout::Composite {
+                partition_key,
+                partition_value,
+                ..
+            } => expression::and_filter(
+                request,
+                Filter::new("#session_pk = :session_pk")
+                    .name("#session_pk", partition_key)
+                    .value(":session_pk", AttributeValue::S(partition_value.clone())),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_pool::test_pool;
+
+    fn composite() -> KeyLayout {
+        KeyLayout::Composite {
+            partition_key: "pk".into(),
+            partition_value: "SESSION".into(),
+            sort_key: "sk".into(),
+        }
+    }
+
+    #[test]
+    fn simple_keys_hold_the_id_alone() {
+        let pool = test_pool();
+
+        let key = pool.key("abc").unwrap();
+
+        assert_eq!(key, Item::from([("id".to_string(), AttributeValue::S("abc".into()))]));
+        assert_eq!(pool.id_attribute(), "id");
+        assert_eq!(pool.item_id(&key).as_deref(), Some("abc"));
+        assert_eq!(pool.item_key(&key), Some(key));
+    }
+
+    #[test]
+    fn composite_keys_put_the_id_under_the_sort_key() {
+        let pool = test_pool().with_key_layout(composite());
+
+        let key = pool.key("abc").unwrap();
+
+        assert_eq!(
+            key,
+            Item::from([
+                ("pk".to_string(), AttributeValue::S("SESSION".into())),
+                ("sk".to_string(), AttributeValue::S("abc".into())),
+            ])
+        );
+        assert_eq!(pool.id_attribute(), "sk");
+        assert_eq!(pool.item_id(&key).as_deref(), Some("abc"));
+
+        let mut item = key.clone();
+        item.insert("session".into(), AttributeValue::S("{}".into()));
+        assert_eq!(pool.item_key(&item), Some(key));
+    }
+
+    #[test]
+    fn key_value_prefix_is_added_and_stripped() {
+        let pool = test_pool().with_key_value_prefix("session#");
+
+        let key = pool.key("abc").unwrap();
+
+        assert_eq!(key["id"], AttributeValue::S("session#abc".into()));
+        assert_eq!(pool.item_id(&key).as_deref(), Some("abc"));
+        let foreign = Item::from([("id".to_string(), AttributeValue::S("user#abc".into()))]);
+        assert_eq!(pool.item_id(&foreign), None);
+    }
+
+    #[test]
+    fn key_value_prefix_still_validates_the_key_type() {
+        let pool = test_pool().with_key_type::<i64>().with_key_value_prefix("n#");
+
+        assert_eq!(pool.key("42").unwrap()["id"], AttributeValue::S("n#42".into()));
+        assert_eq!(pool.id_attribute_type(), ScalarAttributeType::S);
+        assert!(pool.key("forty-two").is_err());
+    }
+
+    #[test]
+    fn typed_keys_round_trip() {
+        let pool = test_pool().with_key_type::<Uuid>();
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+        let key = pool.key(id).unwrap();
+
+        assert!(matches!(&key["id"], AttributeValue::B(bytes) if bytes.as_ref().len() == 16));
+        assert_eq!(pool.item_id(&key).as_deref(), Some(id));
+        assert!(pool.key("not-a-uuid").is_err());
+    }
+}
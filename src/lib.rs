@@ -1,4 +1,58 @@
+mod activity;
+mod audit;
+#[cfg(feature = "autoscaling")]
+mod autoscaling;
+mod batch;
+#[cfg(feature = "batching")]
+mod batcher;
+mod capacity;
+mod coalesce;
+mod codec;
+mod context;
+mod deletion;
+mod diagnostics;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod error;
+mod expression;
+mod ext;
+mod key;
+mod lock;
+mod maintenance;
+#[cfg(test)]
+mod mock;
+mod observer;
+mod retry;
+mod revocation;
 mod session_pool;
+mod shard;
+mod transact;
+#[cfg(feature = "streams")]
+pub mod streams;
 
 pub type DynamoDbClient = aws_sdk_dynamodb::Client;
+pub use activity::ActivitySummary;
+pub use audit::AuditMode;
+#[cfg(feature = "autoscaling")]
+pub use autoscaling::*;
+pub use capacity::{analyze_usage, CapacityAnalysis, CapacitySample, CapacityStats};
+pub use codec::{attribute_value_to_json, json_to_attribute_value, CompressionAlgorithm, StringCodec};
+pub use context::SessionContext;
+pub use deletion::Deletion;
+pub use diagnostics::*;
+#[cfg(feature = "encryption")]
+pub use encryption::EncryptionKeys;
+pub use error::{
+    conflicting_item, is_condition_failed, is_item_collection_limit_exceeded, is_missing_expires,
+    is_scan_limit_exceeded, is_session_exists, is_table_not_active, is_table_not_found, is_throttled, retry_after,
+    CONDITION_FAILED, ITEM_COLLECTION_LIMIT_EXCEEDED, MISSING_EXPIRES, SCAN_LIMIT_EXCEEDED, SESSION_EXISTS,
+    TABLE_NOT_ACTIVE, TABLE_NOT_FOUND, THROTTLED,
+};
+pub use ext::DynamoSessionExt;
+pub use key::{KeyLayout, SessionKey};
+pub use lock::LockGuard;
+pub use maintenance::*;
+pub use observer::{ItemCollectionObserver, OperationObserver};
+pub use retry::{RetryClassifier, RetryPolicy};
 pub use session_pool::*;
+pub use transact::client_request_token;
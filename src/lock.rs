@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+
+use crate::{error, session_pool::unix_now, SessionDynamoDbPool, SessionKey};
+
+/// Attribute holding the token of the guard that owns a lock.
+const LOCK_TOKEN_ATTRIBUTE: &str = "lock_token";
+
+/// A lock taken by `acquire_lock`. It is released when the guard is
+/// dropped, in a background task that may not run, e.g. during runtime
+/// shutdown, so call `release` where the release has to be known. A lock
+/// that is never released lapses once its TTL runs out.
+#[derive(Debug)]
+pub struct LockGuard<K: SessionKey = String> {
+    pool: SessionDynamoDbPool<K>,
+    id: String,
+    table_name: String,
+    token: String,
+    released: bool,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Takes the lock on `id` for `ttl`, for apps that serialize the
+    /// processing of a session across instances. Returns `None` if another
+    /// guard holds an unexpired lock on `id`.
+    ///
+    /// Locks are keyed like sessions, so `table_name` should be a dedicated
+    /// table, set up with `initiate`, rather than the session table, where a
+    /// lock would replace the session with the same id. TTL removes lapsed
+    /// locks.
+    pub async fn acquire_lock(
+        &self,
+        id: &str,
+        ttl: Duration,
+        table_name: &str,
+    ) -> Result<Option<LockGuard<K>>, DatabaseError> {
+        let token = hex::encode(rand::random::<[u8; 16]>());
+        let now = unix_now();
+
+        let mut item = self.key(id)?;
+        item.insert(LOCK_TOKEN_ATTRIBUTE.into(), AttributeValue::S(token.clone()));
+        item.insert(self.expires_attribute().into(), AttributeValue::N(lock_expiry(now, ttl).to_string()));
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(table_name))
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(#id) OR #expires <= :now")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()));
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(Some(LockGuard {
+                pool: self.clone(),
+                id: id.to_string(),
+                table_name: table_name.to_string(),
+                token,
+                released: false,
+            })),
+            Err(e) if error::is_condition_failed(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deletes the lock on `id` if `token` still owns it.
+    async fn release_lock(&self, id: &str, token: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .delete_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .condition_expression("#token = :token")
+            .expression_attribute_names("#token", LOCK_TOKEN_ATTRIBUTE)
+            .expression_attribute_values(":token", AttributeValue::S(token.into()));
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericDeleteError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<K: SessionKey> LockGuard<K> {
+    /// The id the lock is held on.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Pushes the lock's expiry to `ttl` from now. Returns `false` if the
+    /// lock lapsed and was taken by another guard, or removed, meanwhile.
+    pub async fn extend(&self, ttl: Duration) -> Result<bool, DatabaseError> {
+        let pool = &self.pool;
+        let now = unix_now();
+
+        let _permit = pool.permit().await?;
+
+        let request = pool
+            .client
+            .update_item()
+            .table_name(pool.full_table_name(&self.table_name))
+            .set_key(Some(pool.key(&self.id)?))
+            .update_expression("SET #expires = :expires")
+            .condition_expression("#token = :token")
+            .expression_attribute_names("#token", LOCK_TOKEN_ATTRIBUTE)
+            .expression_attribute_names("#expires", pool.expires_attribute())
+            .expression_attribute_values(":token", AttributeValue::S(self.token.clone()))
+            .expression_attribute_values(":expires", AttributeValue::N(lock_expiry(now, ttl).to_string()));
+
+        let result = pool
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Releases the lock. Returns `false` if it had already lapsed and been
+    /// taken by another guard, or removed.
+    pub async fn release(mut self) -> Result<bool, DatabaseError> {
+        self.released = true;
+        self.pool.release_lock(&self.id, &self.token, &self.table_name).await
+    }
+}
+
+impl<K: SessionKey> Drop for LockGuard<K> {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("lock on {} dropped outside a runtime, left to lapse", self.id);
+            return;
+        };
+
+        let pool = self.pool.clone();
+        let id = std::mem::take(&mut self.id);
+        let token = std::mem::take(&mut self.token);
+        let table_name = std::mem::take(&mut self.table_name);
+
+        runtime.spawn(async move {
+            if let Err(err) = pool.release_lock(&id, &token, &table_name).await {
+                tracing::warn!("failed to release lock on {id}: {err}");
+            }
+        });
+    }
+}
+
+/// Unix timestamp `ttl` after `now`, rounded up to whole seconds.
+fn lock_expiry(now: i64, ttl: Duration) -> i64 {
+    now + ttl.as_secs_f64().ceil() as i64
+}
@@ -0,0 +1,1558 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use aws_sdk_dynamodb::types::{
+    AttributeValue, DeleteRequest, PutRequest, ReturnConsumedCapacity, ScalarAttributeType, Select,
+    TimeToLiveSpecification, TimeToLiveStatus, WriteRequest,
+};
+use axum_session::DatabaseError;
+use chrono::DateTime;
+use futures::{Stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+
+use crate::{
+    batch, error,
+    expression::{self, Filter},
+    retry::ScannedItems,
+    session_pool::{is_soft_deleted, unix_now, DEFAULT_EXPIRES_ATTRIBUTE, DELETED_ATTRIBUTE, EXPIRY_PARTITION_KEY, EXPIRY_PARTITION_VALUE, LAST_MODIFIED_ATTRIBUTE, PINNED_ATTRIBUTE, USER_ATTRIBUTE},
+    BatchFailureMode, Item, KeyLayout, SessionDynamoDbPool, SessionKey,
+};
+
+/// How many single-item writes bulk operations keep in flight at once.
+const BULK_WRITE_CONCURRENCY: usize = 16;
+
+/// How long `migrate_ttl_attribute` waits for TTL to be disabled. DynamoDB
+/// documents up to an hour.
+const TTL_DISABLED_TIMEOUT: Duration = Duration::from_secs(90 * 60);
+const TTL_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Position in a table scan, as returned by DynamoDB's `LastEvaluatedKey`.
+pub type ScanCursor = HashMap<String, AttributeValue>;
+
+/// Result of one `copy_to` call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyProgress {
+    /// Number of sessions copied by this call.
+    pub copied: usize,
+    /// Where to resume, or `None` once the copy is complete.
+    pub cursor: Option<ScanCursor>,
+}
+
+/// Result of a `load_many` call.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadMany {
+    /// Payloads of the unexpired sessions found, by id.
+    pub sessions: HashMap<String, String>,
+    /// Ids that were still unprocessed once `max_batch_retries` ran out.
+    /// Their sessions may exist, so the result is partial unless this is empty.
+    pub unprocessed: Vec<String>,
+}
+
+/// Totals of a `delete_by_expiry_stats` sweep, accumulated page by page.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExpirySweepStats {
+    /// Number of scan pages read.
+    pub pages: usize,
+    /// Items read by the scan, before the expiry filter.
+    pub scanned: usize,
+    /// Items the expiry filter matched.
+    pub matched: usize,
+    /// Items deleted.
+    pub deleted: usize,
+    /// Read and write capacity units consumed by the scan and the deletes.
+    pub consumed_capacity: f64,
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Rewrites every `expires` attribute stored as a String into a Number,
+    /// which DynamoDB TTL and the numeric expiry filters require.
+    ///
+    /// Values that don't parse as an integer are logged and left untouched.
+    /// Returns the number of migrated items.
+    pub async fn migrate_expires_to_number(&self, table_name: &str) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.scan_keys(table_name, &[self.expires_attribute()]);
+
+        let items = self.scan_items(request).await?;
+
+        let mut migrated = 0;
+
+        for item in items {
+            let (Some(key), Some(AttributeValue::S(expires))) =
+                (self.item_key(&item), item.get(self.expires_attribute()))
+            else {
+                continue;
+            };
+
+            let Ok(expires) = expires.trim().parse::<i64>() else {
+                tracing::warn!(?key, expires, "skipping session with unparseable expires");
+                continue;
+            };
+
+            let request = self
+                .client
+                .update_item()
+                .table_name(self.full_table_name(table_name))
+                .set_key(Some(key))
+                .update_expression("SET #expires = :expires")
+                .expression_attribute_names("#expires", self.expires_attribute())
+                .expression_attribute_values(":expires", AttributeValue::N(expires.to_string()));
+
+            self.send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError))?;
+
+            migrated += 1;
+        }
+
+        Ok(migrated)
+    }
+
+    /// Moves TTL from the attribute it is enabled on, `expires` for tables
+    /// set up by `initiate`, to `new_name`: copies each item's expiry to
+    /// `new_name`, disables TTL, waits for DynamoDB to finish disabling it,
+    /// which can take up to an hour, then enables it on `new_name`. Returns
+    /// the number of items whose expiry was copied.
+    ///
+    /// While TTL is managed, the pool has to be built with
+    /// `with_expires_attribute(new_name)`, so the sessions it stores carry
+    /// the new attribute, and this fails otherwise. Switch every pool over
+    /// before the move: until the copy reaches an item, they see it as
+    /// never expiring, while a pool still writing the old attribute would
+    /// leave its sessions uncovered. The old attribute is left in place.
+    /// Items are copied before TTL moves, so no item goes uncovered, and
+    /// each copy only applies if the expiry is unchanged since the scan.
+    pub async fn migrate_ttl_attribute(&self, new_name: &str, table_name: &str) -> Result<usize, DatabaseError> {
+        let (_, key_names) = self.key_projection(&[]);
+        if key_names.values().any(|name| name == new_name) {
+            return Err(DatabaseError::GenericNotSupportedError(format!(
+                "migrate_ttl_attribute can't use key attribute {new_name}"
+            )));
+        }
+        if self.config.manage_ttl && new_name != self.expires_attribute() {
+            return Err(DatabaseError::GenericNotSupportedError(format!(
+                "migrate_ttl_attribute to {new_name} requires with_expires_attribute({new_name:?}) while TTL is managed"
+            )));
+        }
+
+        let _permit = self.permit().await?;
+
+        let ttl = self.describe_ttl(table_name).await?;
+        let old_name = ttl.attribute_name.clone().unwrap_or_else(|| DEFAULT_EXPIRES_ATTRIBUTE.into());
+        if old_name == new_name && ttl.is_enabled() {
+            return Ok(0);
+        }
+
+        let items = self.scan_items(self.scan_keys(table_name, &[&old_name])).await?;
+
+        let mut copied = 0;
+
+        for item in items {
+            let (Some(key), Some(expires @ AttributeValue::N(_))) = (self.item_key(&item), item.get(&old_name)) else {
+                continue;
+            };
+
+            let request = self
+                .client
+                .update_item()
+                .table_name(self.full_table_name(table_name))
+                .set_key(Some(key))
+                .update_expression("SET #new = :expires")
+                .condition_expression("#old = :expires")
+                .expression_attribute_names("#new", new_name)
+                .expression_attribute_names("#old", &old_name)
+                .expression_attribute_values(":expires", expires.clone());
+
+            let result = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+            match result {
+                Ok(_) => copied += 1,
+                Err(e) if error::is_condition_failed(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if ttl.status != TimeToLiveStatus::Disabled {
+            if ttl.status != TimeToLiveStatus::Disabling {
+                self.update_ttl(&old_name, false, table_name).await?;
+            }
+            self.wait_until_ttl_disabled(table_name).await?;
+        }
+        self.update_ttl(new_name, true, table_name).await?;
+
+        Ok(copied)
+    }
+
+    /// Enables or disables TTL on `attribute_name`.
+    async fn update_ttl(&self, attribute_name: &str, enabled: bool, table_name: &str) -> Result<(), DatabaseError> {
+        let specification = TimeToLiveSpecification::builder()
+            .enabled(enabled)
+            .attribute_name(attribute_name)
+            .build()
+            .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+
+        let request = self
+            .client
+            .update_time_to_live()
+            .table_name(self.full_table_name(table_name))
+            .time_to_live_specification(specification);
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?;
+        Ok(())
+    }
+
+    /// Polls TTL until it is DISABLED, as it can't be enabled again before.
+    async fn wait_until_ttl_disabled(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let deadline = tokio::time::Instant::now() + TTL_DISABLED_TIMEOUT;
+
+        loop {
+            let status = self.describe_ttl(table_name).await?.status;
+
+            if status == TimeToLiveStatus::Disabled {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(DatabaseError::GenericCreateError(format!(
+                    "TTL on {table_name} wasn't disabled within {TTL_DISABLED_TIMEOUT:?}, last status {status}"
+                )));
+            }
+
+            tokio::time::sleep(TTL_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Renames attributes across the table, e.g. `session` to `data`, per
+    /// `mapping` from old to new names: each item holding an old attribute
+    /// is rewritten with the value under the new name, replacing any value
+    /// already there. Items already in the new layout are left alone, so an
+    /// interrupted run can simply be repeated. Key attributes can't be
+    /// renamed. Returns the number of rewritten items.
+    ///
+    /// Each rewrite only applies if the old values are unchanged since the
+    /// scan, so items written meanwhile are skipped rather than clobbered.
+    pub async fn remap_attributes(
+        &self,
+        mapping: HashMap<String, String>,
+        table_name: &str,
+    ) -> Result<usize, DatabaseError> {
+        let key_attributes = match &self.config.key_layout {
+            KeyLayout::Simple => vec!["id"],
+            KeyLayout::Composite {
+                partition_key,
+                sort_key,
+                ..
+            } => vec![partition_key.as_str(), sort_key.as_str()],
+        };
+        if let Some(name) = mapping
+            .iter()
+            .flat_map(|(old, new)| [old, new])
+            .find(|name| key_attributes.contains(&name.as_str()))
+        {
+            return Err(DatabaseError::GenericNotSupportedError(format!(
+                "remap_attributes can't rename key attribute {name}"
+            )));
+        }
+
+        let _permit = self.permit().await?;
+
+        let old_names: Vec<&str> = mapping.keys().map(String::as_str).collect();
+        let items = self.scan_items(self.scan_keys(table_name, &old_names)).await?;
+
+        let mut rewritten = 0;
+
+        for item in items {
+            let Some(key) = self.item_key(&item) else {
+                continue;
+            };
+
+            let mut set = Vec::new();
+            let mut remove = Vec::new();
+            let mut condition = Vec::new();
+            let mut request = self
+                .client
+                .update_item()
+                .table_name(self.full_table_name(table_name))
+                .set_key(Some(key));
+
+            for (i, (old, new)) in mapping.iter().enumerate() {
+                let Some(value) = item.get(old).filter(|_| old != new) else {
+                    continue;
+                };
+
+                set.push(format!("#new{i} = :value{i}"));
+                remove.push(format!("#old{i}"));
+                condition.push(format!("#old{i} = :value{i}"));
+                request = request
+                    .expression_attribute_names(format!("#old{i}"), old)
+                    .expression_attribute_names(format!("#new{i}"), new)
+                    .expression_attribute_values(format!(":value{i}"), value.clone());
+            }
+
+            if set.is_empty() {
+                continue;
+            }
+
+            let request = request
+                .update_expression(format!("SET {} REMOVE {}", set.join(", "), remove.join(", ")))
+                .condition_expression(condition.join(" AND "));
+
+            let result = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+            match result {
+                Ok(_) => rewritten += 1,
+                Err(e) if error::is_condition_failed(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Counts every session and collects up to `limit` of their ids in a
+    /// single scan, for admin tooling that wants both.
+    pub async fn count_and_sample(
+        &self,
+        limit: i32,
+        table_name: &str,
+    ) -> Result<(i64, Vec<String>), DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.scan_keys(table_name, &[]);
+
+        let limit = usize::try_from(limit).unwrap_or_default();
+        let mut count = 0;
+        let mut sample = Vec::with_capacity(limit);
+
+        self.scan_pages(request, |page| {
+            count += i64::from(page.count);
+
+            let remaining = limit - sample.len();
+            sample.extend(
+                page.items()
+                    .iter()
+                    .filter_map(|item| self.item_id(item))
+                    .take(remaining),
+            );
+        })
+        .await?;
+
+        Ok((count, sample))
+    }
+
+    /// Copies sessions, with all their attributes, from `table_name` into
+    /// `dest_table_name`.
+    ///
+    /// At most `max_items` are copied per call when set, which must then be
+    /// at least 1. Pass the returned cursor back in to resume where the
+    /// previous call stopped; it is `None` once the whole table has been
+    /// copied.
+    pub async fn copy_to(
+        &self,
+        table_name: &str,
+        dest_table_name: &str,
+        cursor: Option<ScanCursor>,
+        max_items: Option<usize>,
+    ) -> Result<CopyProgress, DatabaseError> {
+        if max_items == Some(0) {
+            return Err(DatabaseError::GenericInsertError(
+                "copy_to max_items must be at least 1".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let mut cursor = cursor;
+        let mut copied = 0;
+        let scanned = ScannedItems::default();
+
+        loop {
+            let remaining = max_items.map(|max| max - copied);
+            let request = self
+                .scan_sessions(table_name)
+                .set_limit(remaining.map(|r| i32::try_from(r).unwrap_or(i32::MAX)));
+
+            let page = self.scan_page(&request, cursor.take(), &scanned).await?;
+
+            let requests = page
+                .items
+                .unwrap_or_default()
+                .into_iter()
+                .map(|item| {
+                    PutRequest::builder()
+                        .set_item(Some(item))
+                        .build()
+                        .map(|put| WriteRequest::builder().put_request(put).build())
+                        .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            copied += requests.len();
+            self.batch_write(dest_table_name, requests).await?;
+
+            cursor = page.last_evaluated_key;
+
+            if cursor.is_none() || max_items.is_some_and(|max| copied >= max) {
+                return Ok(CopyProgress { copied, cursor });
+            }
+        }
+    }
+
+    /// Copies sessions from `table_name` into `dest_table_name` in one pass,
+    /// passing each as `(id, session, expires)` through `transform`, e.g. to
+    /// re-serialize or relabel them. Sessions `transform` returns `None` for
+    /// are skipped, as are pinned ones, which have no `expires`. The results
+    /// are written like `store` writes sessions, so only the id, payload and
+    /// expiry carry over. Returns the number of sessions written.
+    pub async fn copy_transform(
+        &self,
+        table_name: &str,
+        dest_table_name: &str,
+        transform: impl Fn((String, String, i64)) -> Option<(String, String, i64)>,
+    ) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.scan_sessions(table_name);
+        let scanned = ScannedItems::default();
+        let mut start_key = None;
+        let mut copied = 0;
+
+        loop {
+            let page = self.scan_page(&request, start_key.take(), &scanned).await?;
+
+            let mut requests = Vec::new();
+            for item in page.items() {
+                let Some(id) = self.item_id(item) else {
+                    continue;
+                };
+                let Some(expires) = self.item_expires(item) else {
+                    continue;
+                };
+                let Some(session) = self.decode_session(&id, item)? else {
+                    continue;
+                };
+                let Some((id, session, expires)) = transform((id, session, expires)) else {
+                    continue;
+                };
+
+                let put = PutRequest::builder()
+                    .set_item(Some(self.session_item(&id, &session, expires)?))
+                    .build()
+                    .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))?;
+                requests.push(WriteRequest::builder().put_request(put).build());
+            }
+
+            copied += requests.len();
+            self.batch_write(dest_table_name, requests).await?;
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(copied);
+            }
+        }
+    }
+
+    /// Lists every unexpired session id along with its `expires` timestamp.
+    /// Sessions without an `expires` are left out.
+    pub async fn list_active(&self, table_name: &str) -> Result<Vec<(String, i64)>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(self.scan_keys(table_name, &[self.expires_attribute()]), Filter::live(self.expires_attribute()));
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                let id = self.item_id(item)?;
+                let expires = item.get(self.expires_attribute())?.as_n().ok()?.parse().ok()?;
+                Some((id, expires))
+            })
+            .collect())
+    }
+
+    /// Splits every stored session id into those live at `now`, a unix
+    /// timestamp, and those expired by then, without consulting the system
+    /// clock, e.g. for tests of cleanup logic pinned to a known time. Pinned
+    /// sessions count as live and soft-deleted ones as expired. Returns
+    /// `(active_ids, expired_ids)`.
+    pub async fn partition_by_expiry(
+        &self,
+        now: i64,
+        table_name: &str,
+    ) -> Result<(Vec<String>, Vec<String>), DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let items = self
+            .scan_items(self.scan_keys(table_name, &[self.expires_attribute(), DELETED_ATTRIBUTE]))
+            .await?;
+
+        Ok(self.partition_items_by_expiry(&items, now))
+    }
+
+    /// The `partition_by_expiry` split of scanned items.
+    fn partition_items_by_expiry(&self, items: &[Item], now: i64) -> (Vec<String>, Vec<String>) {
+        let (expired, active): (Vec<_>, Vec<_>) = items
+            .iter()
+            .filter_map(|item| Some((self.item_id(item)?, self.is_expired_at(item, now) || is_soft_deleted(item))))
+            .partition(|(_, expired)| *expired);
+
+        (
+            active.into_iter().map(|(id, _)| id).collect(),
+            expired.into_iter().map(|(id, _)| id).collect(),
+        )
+    }
+
+    /// Lists the ids of the stored sessions starting with `prefix`, letting
+    /// DynamoDB filter out the rest, e.g. unrelated entities sharing the
+    /// table. Any `with_key_value_prefix` prefix comes on top of `prefix`.
+    ///
+    /// Needs ids stored as Strings, so it doesn't work with hashed ids or
+    /// other key types unless a key value prefix is set.
+    pub async fn get_ids_with_prefix(&self, prefix: &str, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        if self.config.id_salt.is_some() || self.id_attribute_type() != ScalarAttributeType::S {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "get_ids_with_prefix requires String ids that aren't hashed".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let stored_prefix = format!("{}{prefix}", self.config.key_value_prefix.as_deref().unwrap_or_default());
+        let request = expression::and_filter(
+            self.scan_keys(table_name, &[]),
+            Filter::new("begins_with(#id_prefix, :id_prefix)")
+                .name("#id_prefix", self.id_attribute())
+                .value(":id_prefix", AttributeValue::S(stored_prefix)),
+        );
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items.iter().filter_map(|item| self.item_id(item)).collect())
+    }
+
+    /// Lists the ids of the sessions written at or after `since_unix`, for
+    /// incremental syncs to another store. Requires `with_last_modified`;
+    /// sessions written without it are never listed. Deleted sessions leave
+    /// no trace, so a sync also has to reconcile deletions.
+    pub async fn ids_modified_since(&self, since_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        if !self.config.last_modified {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "ids_modified_since requires with_last_modified".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(
+            self.scan_keys(table_name, &[]),
+            Filter::new("#modified >= :since")
+                .name("#modified", LAST_MODIFIED_ATTRIBUTE)
+                .value(":since", AttributeValue::N(since_unix.to_string())),
+        );
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items.iter().filter_map(|item| self.item_id(item)).collect())
+    }
+
+    /// Pushes back the expiry of every unexpired session by
+    /// `additional_seconds`, e.g. so a maintenance window doesn't log users
+    /// out. Sessions without an `expires` never expire and are left alone,
+    /// as are soft-deleted ones, and sessions deleted while the scan runs
+    /// are skipped rather than recreated. Returns the number of extended
+    /// sessions.
+    ///
+    /// Updates run concurrently, within `with_max_concurrent_requests`.
+    pub async fn extend_all(&self, additional_seconds: i64, table_name: &str) -> Result<usize, DatabaseError> {
+        let request = expression::and_filter(self.scan_keys(table_name, &[self.expires_attribute()]), Filter::live(self.expires_attribute()));
+
+        let items = {
+            let _permit = self.permit().await?;
+            self.scan_items(request).await?
+        };
+        let keys: Vec<_> = items
+            .iter()
+            .filter(|item| item.contains_key(self.expires_attribute()))
+            .filter_map(|item| self.item_key(item))
+            .collect();
+
+        futures::stream::iter(keys)
+            .map(|key| async move {
+                let _permit = self.permit().await?;
+
+                let request = self
+                    .client
+                    .update_item()
+                    .table_name(self.full_table_name(table_name))
+                    .set_key(Some(key))
+                    .update_expression("SET #expires = #expires + :d")
+                    .condition_expression("attribute_exists(#expires) AND attribute_not_exists(#deleted)")
+                    .expression_attribute_names("#expires", self.expires_attribute())
+                    .expression_attribute_names("#deleted", DELETED_ATTRIBUTE)
+                    .expression_attribute_values(":d", AttributeValue::N(additional_seconds.to_string()));
+
+                let result = self
+                    .send(|| request.clone().send())
+                    .await
+                    .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+                match result {
+                    Ok(_) => Ok(1),
+                    Err(e) if error::is_condition_failed(&e) => Ok(0),
+                    Err(e) => Err(e),
+                }
+            })
+            .buffer_unordered(BULK_WRITE_CONCURRENCY)
+            .try_fold(0, |extended, n| async move { Ok(extended + n) })
+            .await
+    }
+
+    /// Sets `expires` to `new_expires` on each of the given sessions with
+    /// concurrent conditional updates, within `with_max_concurrent_requests`.
+    /// Returns the ids that had no session, along with pinned ones, which
+    /// are left without an expiry, and soft-deleted ones, which stay hidden.
+    pub async fn touch_many(
+        &self,
+        ids: &[String],
+        new_expires: i64,
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let mut set = "SET #expires = :expires".to_string();
+        let mut values = HashMap::from([(":expires".to_string(), AttributeValue::N(new_expires.to_string()))]);
+
+        let mut names = HashMap::from([
+            ("#id".to_string(), self.id_attribute().to_string()),
+            ("#pinned".to_string(), PINNED_ATTRIBUTE.to_string()),
+            ("#deleted".to_string(), DELETED_ATTRIBUTE.to_string()),
+            ("#expires".to_string(), self.expires_attribute().to_string()),
+        ]);
+
+        if self.config.expires_iso {
+            if let Some(time) = DateTime::from_timestamp(new_expires, 0) {
+                set.push_str(", #expires_iso = :expires_iso");
+                names.insert("#expires_iso".into(), "expires_iso".into());
+                values.insert(":expires_iso".into(), AttributeValue::S(time.to_rfc3339()));
+            }
+        }
+
+        if self.config.last_modified {
+            set.push_str(", #modified = :modified");
+            names.insert("#modified".into(), LAST_MODIFIED_ATTRIBUTE.into());
+            values.insert(":modified".into(), AttributeValue::N(unix_now().to_string()));
+        }
+
+        let mut requests = Vec::with_capacity(ids.len());
+        for id in ids {
+            let request = self
+                .client
+                .update_item()
+                .table_name(self.full_table_name(table_name))
+                .set_key(Some(self.key(id)?))
+                .update_expression(&set)
+                .condition_expression("attribute_exists(#id) AND attribute_not_exists(#pinned) AND attribute_not_exists(#deleted)")
+                .set_expression_attribute_names(Some(names.clone()))
+                .set_expression_attribute_values(Some(values.clone()));
+
+            requests.push((id.clone(), request));
+        }
+
+        futures::stream::iter(requests)
+            .map(|(id, request)| async move {
+                let _permit = self.permit().await?;
+
+                let result = self
+                    .send(|| request.clone().send())
+                    .await
+                    .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+                match result {
+                    Ok(_) => Ok(None),
+                    Err(e) if error::is_condition_failed(&e) => Ok(Some(id)),
+                    Err(e) => Err(e),
+                }
+            })
+            .buffer_unordered(BULK_WRITE_CONCURRENCY)
+            .try_filter_map(|missing| async move { Ok(missing) })
+            .try_collect()
+            .await
+    }
+
+    /// Imports sessions given as `(id, session, expires)` without clobbering
+    /// newer ones: each is written like `store`, by its own conditional
+    /// write, which only succeeds if the id is new or its stored session
+    /// expires earlier. Pinned sessions never expire, so they are kept.
+    /// Returns the ids skipped because a newer session was already stored,
+    /// or under `StoreMode::RejectExisting` any session.
+    ///
+    /// Writes run concurrently, within `with_max_concurrent_requests`.
+    pub async fn store_many_conditional(
+        &self,
+        items: &[(String, String, i64)],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let mut writes = Vec::with_capacity(items.len());
+
+        for (id, session, expires) in items {
+            let item = self.session_item(id, session, *expires)?;
+            let older = Filter::new("attribute_not_exists(#id) OR #expires < :expires")
+                .name("#id", self.id_attribute())
+                .name("#expires", self.expires_attribute())
+                .value(":expires", AttributeValue::N(expires.to_string()));
+
+            writes.push((id.clone(), item, older));
+        }
+
+        let update = self.config.upsert || self.config.created_at;
+
+        futures::stream::iter(writes)
+            .map(|(id, item, older)| async move {
+                match self.write_session_if(&id, item, update, Some(older), false, table_name).await {
+                    Ok(_) => Ok(None),
+                    Err(e) if error::is_condition_failed(&e) || error::is_session_exists(&e) => Ok(Some(id)),
+                    Err(e) => Err(e),
+                }
+            })
+            .buffer_unordered(BULK_WRITE_CONCURRENCY)
+            .try_filter_map(|skipped| async move { Ok(skipped) })
+            .try_collect()
+            .await
+    }
+
+    /// Counts the unexpired sessions by querying the index set up by
+    /// `with_expiry_index` for keys expiring after now, which reads only the
+    /// live part of the index instead of scanning every item.
+    ///
+    /// Pinned sessions have no `expires` and are left out of the index, so
+    /// this count misses them. The index is read with eventual consistency.
+    pub async fn count_active_via_index(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        let Some(index_name) = &self.config.expiry_index else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "count_active_via_index requires with_expiry_index".into(),
+            ));
+        };
+
+        let _permit = self.permit().await?;
+
+        let mut count = 0;
+        let mut start_key = None;
+
+        loop {
+            let request = self
+                .client
+                .query()
+                .table_name(self.full_table_name(table_name))
+                .index_name(index_name)
+                .key_condition_expression("#partition = :partition AND #expires > :now")
+                .expression_attribute_names("#partition", EXPIRY_PARTITION_KEY)
+                .expression_attribute_names("#expires", self.expires_attribute())
+                .expression_attribute_values(":partition", AttributeValue::S(EXPIRY_PARTITION_VALUE.into()))
+                .expression_attribute_values(":now", AttributeValue::N(unix_now().to_string()))
+                .select(Select::Count)
+                .consistent_read(false)
+                .set_exclusive_start_key(start_key.take());
+
+            let page = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+            count += i64::from(page.count);
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Returns up to `limit` session ids ordered by `expires`, soonest first
+    /// when `ascending`, by querying the index set up by `with_expiry_index`.
+    ///
+    /// The index is always read with eventual consistency, whatever the
+    /// pool's default, and still lists expired sessions TTL hasn't removed yet.
+    pub async fn ids_by_expiry(
+        &self,
+        ascending: bool,
+        limit: usize,
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let Some(index_name) = &self.config.expiry_index else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "ids_by_expiry requires with_expiry_index".into(),
+            ));
+        };
+
+        let _permit = self.permit().await?;
+
+        let mut ids = Vec::with_capacity(limit);
+        let mut start_key = None;
+
+        while ids.len() < limit {
+            let request = self
+                .client
+                .query()
+                .table_name(self.full_table_name(table_name))
+                .index_name(index_name)
+                .key_condition_expression("#partition = :partition")
+                .expression_attribute_names("#partition", EXPIRY_PARTITION_KEY)
+                .expression_attribute_values(":partition", AttributeValue::S(EXPIRY_PARTITION_VALUE.into()))
+                .scan_index_forward(ascending)
+                // Global secondary indexes reject strongly consistent reads,
+                // so the pool's default consistency doesn't apply here.
+                .consistent_read(false)
+                .limit(i32::try_from(limit - ids.len()).unwrap_or(i32::MAX))
+                .set_exclusive_start_key(start_key.take());
+
+            let page = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+            ids.extend(page.items().iter().filter_map(|item| self.item_id(item)));
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Deletes up to `max_items` expired sessions TTL hasn't removed yet, so
+    /// cron jobs can bound the work done per run. Returns the deleted ids and
+    /// whether more expired sessions may remain.
+    pub async fn delete_by_expiry_paged(
+        &self,
+        max_items: usize,
+        table_name: &str,
+    ) -> Result<(Vec<String>, bool), DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(self.scan_keys(table_name, &[]), Filter::expired(self.expires_attribute()));
+
+        let mut items = Vec::new();
+        let scanned = ScannedItems::default();
+        let mut start_key = None;
+
+        let more = loop {
+            let page = self.scan_page(&request, start_key.take(), &scanned).await?;
+
+            items.extend(page.items.unwrap_or_default());
+            start_key = page.last_evaluated_key;
+
+            if items.len() >= max_items {
+                break items.len() > max_items || start_key.is_some();
+            }
+            if start_key.is_none() {
+                break false;
+            }
+        };
+
+        items.truncate(max_items);
+
+        let requests = items
+            .iter()
+            .filter_map(|item| self.item_key(item))
+            .map(|key| {
+                DeleteRequest::builder()
+                    .set_key(Some(key))
+                    .build()
+                    .map(|delete| WriteRequest::builder().delete_request(delete).build())
+                    .map_err(|e| DatabaseError::GenericDeleteError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.batch_write(table_name, requests).await?;
+
+        Ok((items.iter().filter_map(|item| self.item_id(item)).collect(), more))
+    }
+
+    /// Deletes every expired session TTL hasn't removed yet, page by page,
+    /// and reports what the sweep did, for monitoring cleanup jobs. Each
+    /// page's deletes go out before the next page is read.
+    pub async fn delete_by_expiry_stats(&self, table_name: &str) -> Result<ExpirySweepStats, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(self.scan_keys(table_name, &[]), Filter::expired(self.expires_attribute()))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total);
+
+        let mut stats = ExpirySweepStats::default();
+        let scanned = ScannedItems::default();
+        let mut start_key = None;
+
+        loop {
+            let page = self.scan_page(&request, start_key.take(), &scanned).await?;
+
+            let keys = page.items().iter().filter_map(|item| self.item_key(item)).collect::<Vec<_>>();
+
+            stats.pages += 1;
+            stats.scanned += page.scanned_count().max(0) as usize;
+            stats.matched += page.count().max(0) as usize;
+            stats.consumed_capacity += page.consumed_capacity().and_then(|c| c.capacity_units()).unwrap_or_default();
+            stats.consumed_capacity += self.batch_write(table_name, batch::delete_requests(&keys)?).await?;
+            stats.deleted += keys.len();
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Counts the unexpired sessions stored for a user by `store_for_user`,
+    /// by querying the index set up by `with_user_index`. The index is always
+    /// read with eventual consistency, whatever the pool's default.
+    pub async fn count_for_user(&self, user_id: &str, table_name: &str) -> Result<i64, DatabaseError> {
+        let Some(index_name) = &self.config.user_index else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "count_for_user requires with_user_index".into(),
+            ));
+        };
+
+        let _permit = self.permit().await?;
+
+        let mut count = 0;
+        let mut start_key = None;
+
+        loop {
+            let request = self
+                .client
+                .query()
+                .table_name(self.full_table_name(table_name))
+                .index_name(index_name)
+                .key_condition_expression("#user = :user AND #expires > :now")
+                .expression_attribute_names("#user", USER_ATTRIBUTE)
+                .expression_attribute_names("#expires", self.expires_attribute())
+                .expression_attribute_values(":user", AttributeValue::S(user_id.into()))
+                .expression_attribute_values(":now", AttributeValue::N(unix_now().to_string()))
+                .select(Select::Count)
+                .consistent_read(false)
+                .set_exclusive_start_key(start_key.take());
+
+            let page = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+            count += i64::from(page.count);
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Returns the ids of the unexpired sessions whose attributes match
+    /// `key_values` on an index registered with `with_query_index`, one value
+    /// per key attribute in registration order. The sort key may be left
+    /// out. Indexes are read with eventual consistency; sessions whose
+    /// `expires` the index doesn't project are never filtered out.
+    pub async fn query_index(
+        &self,
+        index_name: &str,
+        key_values: &[AttributeValue],
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let Some(key_attributes) = self.config.query_indexes.get(index_name) else {
+            return Err(DatabaseError::GenericNotSupportedError(format!(
+                "index {index_name} isn't registered with with_query_index"
+            )));
+        };
+        if key_values.is_empty() || key_values.len() > key_attributes.len() {
+            return Err(DatabaseError::GenericSelectError(format!(
+                "index {index_name} takes 1 to {} key values, got {}",
+                key_attributes.len(),
+                key_values.len()
+            )));
+        }
+
+        let (projection, names) = self.key_projection(&[]);
+        let live = Filter::live(self.expires_attribute());
+
+        let mut conditions = Vec::new();
+        let mut request = self
+            .client
+            .query()
+            .table_name(self.full_table_name(table_name))
+            .index_name(index_name)
+            .projection_expression(projection)
+            .set_expression_attribute_names(Some(names))
+            .filter_expression(live.expression)
+            .consistent_read(false);
+
+        for (placeholder, name) in live.names {
+            request = request.expression_attribute_names(placeholder, name);
+        }
+        for (placeholder, value) in live.values {
+            request = request.expression_attribute_values(placeholder, value);
+        }
+        for (i, (name, value)) in key_attributes.iter().zip(key_values).enumerate() {
+            conditions.push(format!("#key{i} = :key{i}"));
+            request = request
+                .expression_attribute_names(format!("#key{i}"), name)
+                .expression_attribute_values(format!(":key{i}"), value.clone());
+        }
+        let request = request.key_condition_expression(conditions.join(" AND "));
+
+        let _permit = self.permit().await?;
+
+        let mut ids = Vec::new();
+        let mut start_key = None;
+
+        loop {
+            let page_request = request.clone().set_exclusive_start_key(start_key.take());
+            let page = self
+                .send(|| page_request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+            ids.extend(page.items().iter().filter_map(|item| self.item_id(item)));
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(ids);
+            }
+        }
+    }
+
+    /// Lists the ids stored in one partition under `KeyLayout::Composite`,
+    /// e.g. a tenant's, with a `Query` instead of the scan behind `get_ids`.
+    /// Like `get_ids`, this includes sessions that expired but haven't been
+    /// removed by TTL yet.
+    pub async fn get_ids_in_partition(
+        &self,
+        partition_value: &str,
+        table_name: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let KeyLayout::Composite { partition_key, .. } = &self.config.key_layout else {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "get_ids_in_partition requires KeyLayout::Composite".into(),
+            ));
+        };
+
+        let (projection, names) = self.key_projection(&[]);
+
+        let mut conditions = vec!["#partition = :partition"];
+        let mut request = self
+            .client
+            .query()
+            .table_name(self.full_table_name(table_name))
+            .projection_expression(projection)
+            .set_expression_attribute_names(Some(names))
+            .expression_attribute_names("#partition", partition_key)
+            .expression_attribute_values(":partition", AttributeValue::S(partition_value.into()));
+
+        if let Some(prefix) = &self.config.key_value_prefix {
+            conditions.push("begins_with(#sort, :prefix)");
+            request = request
+                .expression_attribute_names("#sort", self.id_attribute())
+                .expression_attribute_values(":prefix", AttributeValue::S(prefix.clone()));
+        }
+        let request = request.key_condition_expression(conditions.join(" AND "));
+
+        let _permit = self.permit().await?;
+
+        let mut ids = Vec::new();
+        let mut start_key = None;
+
+        loop {
+            let page_request = request.clone().set_exclusive_start_key(start_key.take());
+            let page = self
+                .send(|| page_request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+            ids.extend(page.items().iter().filter_map(|item| self.item_id(item)));
+
+            start_key = page.last_evaluated_key;
+            if start_key.is_none() {
+                return Ok(ids);
+            }
+        }
+    }
+
+    /// Counts the distinct users with at least one unexpired session stored
+    /// by `store_for_user`.
+    ///
+    /// This scans the whole table, so its cost grows with the number of
+    /// sessions, while memory only grows with the number of users.
+    /// Dashboards that can live with an approximate figure are better off
+    /// caching the result, or tracking logins as they happen.
+    pub async fn distinct_active_users(&self, table_name: &str) -> Result<i64, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self.scan_sessions(table_name).projection_expression("#user");
+        let request = expression::and_filter(
+            request,
+            Filter::new("attribute_exists(#user)").name("#user", USER_ATTRIBUTE),
+        );
+        let request = expression::and_filter(request, Filter::live(self.expires_attribute()));
+
+        let mut users = HashSet::new();
+        self.scan_pages(request, |page| {
+            for mut item in page.items.unwrap_or_default() {
+                if let Some(AttributeValue::S(user)) = item.remove(USER_ATTRIBUTE) {
+                    users.insert(user);
+                }
+            }
+        })
+        .await?;
+
+        Ok(users.len() as i64)
+    }
+
+    /// Tallies live sessions by how soon they expire, for monitoring.
+    /// `buckets` are upper bounds in seconds from now, e.g. `[300, 3600]`
+    /// for "within 5 minutes" and "within the hour"; each session counts
+    /// toward the first bound it expires before. Sessions expiring later,
+    /// and those without an expiry, are counted under a final `i64::MAX`
+    /// bound. Returns `(bound, count)` pairs in ascending order of bound.
+    pub async fn expiry_histogram(&self, buckets: &[i64], table_name: &str) -> Result<Vec<(i64, i64)>, DatabaseError> {
+        let mut bounds = buckets.to_vec();
+        bounds.push(i64::MAX);
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let _permit = self.permit().await?;
+
+        let request = self.scan_sessions(table_name).projection_expression("#expires");
+        let request = expression::and_filter(request, Filter::live(self.expires_attribute()));
+
+        let now = unix_now();
+        let mut counts = vec![0; bounds.len()];
+        self.scan_pages(request, |page| {
+            for item in page.items.unwrap_or_default() {
+                let remaining = self.item_expires(&item).map_or(i64::MAX, |expires| expires - now);
+                let bucket = bounds.partition_point(|&bound| bound <= remaining).min(bounds.len() - 1);
+                counts[bucket] += 1;
+            }
+        })
+        .await?;
+
+        Ok(bounds.into_iter().zip(counts).collect())
+    }
+
+    /// Lazily yields the ids of expired sessions TTL hasn't removed yet, one
+    /// scan page at a time, for callers that handle expiry themselves, e.g.
+    /// by sending a logout webhook per session. The stream ends after the
+    /// first error.
+    pub fn expired_ids_stream<'a>(
+        &'a self,
+        table_name: &str,
+    ) -> impl Stream<Item = Result<String, DatabaseError>> + 'a {
+        let request = expression::and_filter(self.scan_keys(table_name, &[]), Filter::expired(self.expires_attribute()));
+
+        self.scan_stream(request, ScannedItems::default())
+            .try_filter_map(move |item| async move { Ok(self.item_id(&item)) })
+    }
+
+    /// Lazily yields every session as `(id, payload, expires)`, one scan page
+    /// at a time, for tooling that rewrites sessions, e.g. to migrate their
+    /// format. Expired sessions TTL hasn't removed yet are included, and
+    /// sessions without an `expires` are reported with `i64::MAX`. A session
+    /// that fails to decode yields an error in its place, while a failed
+    /// scan ends the stream. Under `with_scan_segments` the segments are
+    /// scanned in parallel and their sessions interleaved.
+    pub fn sessions_stream<'a>(
+        &'a self,
+        table_name: &str,
+    ) -> impl Stream<Item = Result<(String, String, i64), DatabaseError>> + 'a {
+        self.segmented_scan_stream(self.scan_sessions(table_name))
+            .try_filter_map(move |item| async move {
+                let Some(id) = self.item_id(&item) else {
+                    return Ok(None);
+                };
+                let Some(session) = self.decode_session(&id, &item)? else {
+                    return Ok(None);
+                };
+                let expires = self.item_expires(&item).unwrap_or(i64::MAX);
+
+                Ok(Some((id, session, expires)))
+            })
+    }
+
+    /// Writes every session to `writer` as one JSON object per line,
+    /// `{"id": .., "session": .., "expires": ..}`, for backups or moving
+    /// sessions between environments. Sessions are read through
+    /// `sessions_stream`, so memory stays bounded by a scan page whatever the
+    /// size of the table. Pinned sessions are written with a `null` expiry.
+    /// Returns the number of sessions written.
+    pub async fn export_ndjson(
+        &self,
+        writer: impl AsyncWrite + Unpin,
+        table_name: &str,
+    ) -> Result<usize, DatabaseError> {
+        let mut writer = BufWriter::new(writer);
+        let mut sessions = std::pin::pin!(self.sessions_stream(table_name));
+        let mut exported = 0;
+
+        while let Some((id, session, expires)) = sessions.try_next().await? {
+            let expires = (expires != i64::MAX).then_some(expires);
+            let mut line = serde_json::json!({ "id": id, "session": session, "expires": expires }).to_string();
+            line.push('\n');
+
+            writer
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| DatabaseError::GenericSelectError(format!("failed to write export: {e}")))?;
+            exported += 1;
+        }
+
+        writer
+            .flush()
+            .await
+            .map_err(|e| DatabaseError::GenericSelectError(format!("failed to write export: {e}")))?;
+
+        Ok(exported)
+    }
+
+    /// Stores the sessions `export_ndjson` wrote to `reader`, in batches of
+    /// up to 25, replacing stored sessions with the same id. Sessions are
+    /// written like `store` writes them, and those exported with a `null`
+    /// expiry are stored pinned. Blank lines are skipped; a malformed line
+    /// fails the import, leaving the sessions before it stored. Returns the
+    /// number of sessions stored.
+    pub async fn import_ndjson(
+        &self,
+        reader: impl AsyncBufRead + Unpin,
+        table_name: &str,
+    ) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let mut lines = reader.lines();
+        let mut line_number = 0;
+        let mut pending = HashMap::new();
+        let mut imported = 0;
+
+        loop {
+            let line = lines
+                .next_line()
+                .await
+                .map_err(|e| DatabaseError::GenericInsertError(format!("failed to read import: {e}")))?;
+
+            if let Some(line) = &line {
+                line_number += 1;
+                if !line.trim().is_empty() {
+                    let (id, item) = self.ndjson_item(line).map_err(|e| {
+                        DatabaseError::GenericInsertError(format!("line {line_number} of import: {e}"))
+                    })?;
+                    pending.insert(id, item);
+                }
+            }
+
+            if pending.len() >= batch::MAX_BATCH_WRITE || (line.is_none() && !pending.is_empty()) {
+                let requests = std::mem::take(&mut pending)
+                    .into_values()
+                    .map(|item| {
+                        PutRequest::builder()
+                            .set_item(Some(item))
+                            .build()
+                            .map(|put| WriteRequest::builder().put_request(put).build())
+                            .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                imported += requests.len();
+                self.batch_write(table_name, requests).await?;
+            }
+
+            if line.is_none() {
+                return Ok(imported);
+            }
+        }
+    }
+
+    /// The id and item of one `export_ndjson` line.
+    fn ndjson_item(&self, line: &str) -> Result<(String, Item), String> {
+        let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+
+        let id = value["id"].as_str().ok_or("missing string id")?;
+        let session = value["session"].as_str().ok_or("missing string session")?;
+
+        let item = match &value["expires"] {
+            serde_json::Value::Null => self.pinned_item(self.session_item(id, session, 1).map_err(|e| e.to_string())?),
+            expires => {
+                let expires = expires.as_i64().ok_or("expires is not an integer")?;
+                self.session_item(id, session, expires).map_err(|e| e.to_string())?
+            }
+        };
+
+        Ok((id.to_string(), item))
+    }
+
+    /// Checks which of the given sessions exist with batched reads that skip
+    /// their payloads, e.g. to validate ids before serving them or to warm
+    /// DynamoDB's caches. Returns how many are live, as `load` would see them.
+    pub async fn warm(&self, ids: &[String], table_name: &str) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let mut keys = Vec::with_capacity(ids.len());
+        for id in ids.iter().collect::<HashSet<_>>() {
+            keys.push(self.key(id)?);
+        }
+
+        let (items, unprocessed) = self
+            .batch_get(table_name, keys, Some(self.key_projection(&[self.expires_attribute(), DELETED_ATTRIBUTE])))
+            .await?;
+
+        if !unprocessed.is_empty() {
+            return Err(DatabaseError::GenericSelectError(format!(
+                "batch get from {table_name} left {} keys unprocessed",
+                unprocessed.len()
+            )));
+        }
+
+        Ok(items.iter().filter(|item| self.is_live(item)).count())
+    }
+
+    /// Loads several sessions with batched reads of up to 100 ids each.
+    /// Missing, expired and soft-deleted sessions are left out of the result,
+    /// as are ids DynamoDB kept leaving unprocessed, which are listed
+    /// separately, or fail the call under `BatchFailureMode::Strict`.
+    pub async fn load_many(&self, ids: &[String], table_name: &str) -> Result<LoadMany, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        // Items and keys come back by their stored id, which differs from
+        // the caller's under `with_id_hashing`.
+        let mut stored_ids = HashMap::with_capacity(ids.len());
+        let mut keys = Vec::with_capacity(ids.len());
+        for id in ids.iter().collect::<HashSet<_>>() {
+            let key = self.key(id)?;
+            if let Some(stored_id) = self.item_id(&key) {
+                stored_ids.insert(stored_id, id.clone());
+            }
+            keys.push(key);
+        }
+
+        let (items, unprocessed) = self.batch_get(table_name, keys, None).await?;
+        let id_of = |item: &Item| stored_ids.get(&self.item_id(item)?).cloned();
+
+        let mut sessions = HashMap::with_capacity(items.len());
+        for item in items.iter().filter(|item| self.is_live(item)) {
+            let Some(id) = id_of(item) else {
+                continue;
+            };
+            if let Some(session) = self.decode_session(&id, item)? {
+                sessions.insert(id, session);
+            }
+        }
+
+        let unprocessed: Vec<_> = unprocessed.iter().filter_map(id_of).collect();
+        if self.config.batch_failure_mode == BatchFailureMode::Strict && !unprocessed.is_empty() {
+            return Err(unprocessed_error(&unprocessed));
+        }
+
+        Ok(LoadMany { sessions, unprocessed })
+    }
+
+    /// Loads several sessions like `load_many`, returning their payloads in
+    /// the order of `ids`, with `None` for missing, expired and soft-deleted
+    /// sessions.
+    /// Fails if any id stays unprocessed, as its session may exist.
+    pub async fn load_ordered(&self, ids: &[String], table_name: &str) -> Result<Vec<Option<String>>, DatabaseError> {
+        let LoadMany { sessions, unprocessed } = self.load_many(ids, table_name).await?;
+
+        if !unprocessed.is_empty() {
+            return Err(unprocessed_error(&unprocessed));
+        }
+
+        Ok(ids.iter().map(|id| sessions.get(id).cloned()).collect())
+    }
+
+    /// Loads sessions like `load_many`, `MAX_BATCH_GET` ids at a time,
+    /// yielding the `(id, payload)` pairs of each batch as it completes
+    /// instead of buffering them all. Missing, expired and soft-deleted
+    /// sessions are left out. Ids a batch leaves unprocessed once
+    /// `max_batch_retries` ran out are reported as an error in its place,
+    /// after its sessions.
+    pub fn load_many_stream<'a>(
+        &'a self,
+        ids: &'a [String],
+        table_name: &str,
+    ) -> impl Stream<Item = Result<(String, String), DatabaseError>> + 'a {
+        let table_name = table_name.to_string();
+
+        futures::stream::iter(ids.chunks(batch::MAX_BATCH_GET))
+            .then(move |chunk| {
+                let table_name = table_name.clone();
+                async move { self.load_many(chunk, &table_name).await }
+            })
+            .map_ok(|batch| {
+                let unprocessed = (!batch.unprocessed.is_empty()).then(|| Err(unprocessed_error(&batch.unprocessed)));
+                futures::stream::iter(batch.sessions.into_iter().map(Ok).chain(unprocessed))
+            })
+            .try_flatten()
+    }
+
+    /// Counts the sessions matching a caller supplied filter expression.
+    ///
+    /// Attribute names that are DynamoDB reserved words must be referenced
+    /// through `#name` placeholders mapped in `names`. Every placeholder used
+    /// in the expression has to be mapped, or an error is returned before any
+    /// request is made.
+    ///
+    /// The scan is charged for the full size of every item it reads, filter
+    /// or not, so there is no projection that would make it cheaper; under
+    /// `Select::Count` DynamoDB rejects a projection anyway.
+    pub async fn count_filtered(
+        &self,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<i64, DatabaseError> {
+        let filter = Filter::checked(filter_expression, names, values)?;
+
+        let _permit = self.permit().await?;
+
+        let request = expression::and_filter(self.scan_sessions(table_name).select(Select::Count), filter);
+
+        let mut count = 0;
+        self.scan_pages(request, |page| count += i64::from(page.count))
+            .await?;
+
+        Ok(count)
+    }
+}
+
+/// The error for ids a batch get still left unprocessed.
+fn unprocessed_error(ids: &[String]) -> DatabaseError {
+    DatabaseError::GenericSelectError(format!("batch get left sessions unprocessed: {}", ids.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{mock, session_pool::test_pool};
+
+    #[test]
+    fn copy_to_rejects_zero_max_items() {
+        let result = futures::executor::block_on(test_pool().copy_to("sessions", "copy", None, Some(0)));
+
+        assert!(matches!(result, Err(DatabaseError::GenericInsertError(_))));
+    }
+
+    #[test]
+    fn migrate_ttl_attribute_requires_the_new_attribute_while_ttl_is_managed() {
+        let result = futures::executor::block_on(test_pool().migrate_ttl_attribute("ttl", "sessions"));
+
+        assert!(matches!(result, Err(DatabaseError::GenericNotSupportedError(_))));
+    }
+
+    /// A pool answering puts with `respond`, given the number of the put,
+    /// along with the bodies of the puts it was sent.
+    fn put_pool(respond: fn(usize) -> (u16, Value)) -> (SessionDynamoDbPool, Arc<Mutex<Vec<Value>>>) {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&puts);
+
+        let pool = mock::mock_pool(move |operation, body| {
+            assert_eq!(operation, "PutItem");
+            let mut sent = sent.lock().unwrap();
+            sent.push(body.clone());
+            respond(sent.len())
+        });
+
+        (pool, puts)
+    }
+
+    #[test]
+    fn store_many_conditional_writes_like_store() {
+        let (pool, puts) = put_pool(|_| (200, json!({})));
+        let expires = unix_now() + 60;
+
+        let skipped = mock::block_on(pool.store_many_conditional(&[("a".into(), "{}".into(), expires)], "sessions")).unwrap();
+
+        assert!(skipped.is_empty());
+        let puts = puts.lock().unwrap();
+        assert_eq!(puts[0]["Item"]["expires"]["N"], expires.to_string());
+        assert_eq!(
+            puts[0]["ConditionExpression"],
+            "(attribute_not_exists(#pinned)) AND (attribute_not_exists(#id) OR #expires < :expires)"
+        );
+    }
+
+    /// A failed first put may be a pinned session's, which `store` rewrites
+    /// as pinned. The import's condition still applies to that rewrite, and
+    /// a pinned session has no expiry to be older than, so it is kept.
+    #[test]
+    fn store_many_conditional_never_unpins_sessions() {
+        let (pool, puts) = put_pool(|put| match put {
+            1 => mock::error("ConditionalCheckFailedException"),
+            _ => (200, json!({})),
+        });
+
+        let items = [("a".into(), "{}".into(), unix_now() + 60)];
+        let skipped = mock::block_on(pool.store_many_conditional(&items, "sessions")).unwrap();
+
+        assert!(skipped.is_empty());
+        let puts = puts.lock().unwrap();
+        assert_eq!(puts.len(), 2);
+        assert_eq!(puts[1]["Item"][PINNED_ATTRIBUTE]["BOOL"], true);
+        assert!(puts[1]["Item"].get("expires").is_none());
+        assert_eq!(puts[1]["ConditionExpression"], "attribute_not_exists(#id) OR #expires < :expires");
+    }
+
+    #[test]
+    fn store_many_conditional_reports_newer_sessions_as_skipped() {
+        let (pool, _) = put_pool(|_| mock::error("ConditionalCheckFailedException"));
+
+        let items = [("a".into(), "{}".into(), unix_now() + 60)];
+        let skipped = mock::block_on(pool.store_many_conditional(&items, "sessions")).unwrap();
+
+        assert_eq!(skipped, ["a"]);
+
+        let (pool, puts) = put_pool(|_| mock::error("ConditionalCheckFailedException"));
+        let pool = pool.with_store_mode(crate::StoreMode::RejectExisting);
+        let skipped = mock::block_on(pool.store_many_conditional(&items, "sessions")).unwrap();
+
+        assert_eq!(skipped, ["a"]);
+        assert_eq!(puts.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn partition_by_expiry_splits_at_the_given_time() {
+        let pool = test_pool();
+        let item = |id: &str, extra: &[(&str, AttributeValue)]| {
+            let mut item = Item::from([("id".to_string(), AttributeValue::S(id.into()))]);
+            item.extend(extra.iter().map(|(name, value)| (name.to_string(), value.clone())));
+            item
+        };
+        let items = [
+            item("live", &[("expires", AttributeValue::N("200".into()))]),
+            item("expired", &[("expires", AttributeValue::N("100".into()))]),
+            item("at_now", &[("expires", AttributeValue::N("150".into()))]),
+            item("pinned", &[(PINNED_ATTRIBUTE, AttributeValue::Bool(true))]),
+            item(
+                "deleted",
+                &[
+                    ("expires", AttributeValue::N("200".into())),
+                    (DELETED_ATTRIBUTE, AttributeValue::N("140".into())),
+                ],
+            ),
+        ];
+
+        let (active, expired) = pool.partition_items_by_expiry(&items, 150);
+
+        assert_eq!(active, ["live", "pinned"]);
+        assert_eq!(expired, ["expired", "at_now", "deleted"]);
+    }
+}
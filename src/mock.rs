@@ -0,0 +1,79 @@
+//! An offline DynamoDB for unit tests: a pool whose client hands every
+//! request to a closure instead of sending it.
+
+use std::{fmt, future::Future, sync::Arc};
+
+use aws_sdk_dynamodb::config::{
+    retry::RetryConfig, BehaviorVersion, Credentials, Region, StalledStreamProtectionConfig,
+};
+use aws_smithy_runtime_api::{
+    client::{
+        http::{http_client_fn, HttpConnector, HttpConnectorFuture, SharedHttpConnector},
+        orchestrator::HttpRequest,
+    },
+    http::{Response, StatusCode},
+};
+use aws_smithy_types::body::SdkBody;
+use serde_json::{json, Value};
+
+use crate::{DynamoDbClient, SessionDynamoDbPool};
+
+/// Answers a request, given its operation, e.g. `"PutItem"`, and its JSON
+/// body, with a status code and a JSON body.
+type Respond = dyn Fn(&str, &Value) -> (u16, Value) + Send + Sync;
+
+struct Connector(Arc<Respond>);
+
+impl fmt::Debug for Connector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Connector")
+    }
+}
+
+impl HttpConnector for Connector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let target = request.headers().get("x-amz-target").unwrap_or_default();
+        let operation = target.rsplit('.').next().unwrap_or_default();
+        let body = request
+            .body()
+            .bytes()
+            .and_then(|body| serde_json::from_slice(body).ok())
+            .unwrap_or(Value::Null);
+
+        let (status, body) = (self.0)(operation, &body);
+        let status = StatusCode::try_from(status).expect("valid status code");
+        HttpConnectorFuture::ready(Ok(Response::new(status, SdkBody::from(body.to_string()))))
+    }
+}
+
+/// A pool whose client answers every request with `respond`, under test
+/// credentials and without the SDK's own retries, so only the pool's
+/// retry policy applies.
+pub(crate) fn mock_pool(respond: impl Fn(&str, &Value) -> (u16, Value) + Send + Sync + 'static) -> SessionDynamoDbPool {
+    let connector = SharedHttpConnector::new(Connector(Arc::new(respond)));
+    let config = aws_sdk_dynamodb::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("test", "test", None, None, "mock"))
+        .retry_config(RetryConfig::disabled())
+        .stalled_stream_protection(StalledStreamProtectionConfig::disabled())
+        .http_client(http_client_fn(move |_, _| connector.clone()))
+        .build();
+    DynamoDbClient::from_conf(config).into()
+}
+
+/// The response DynamoDB sends for an error of type `code`, e.g.
+/// `"ThrottlingException"`.
+pub(crate) fn error(code: &str) -> (u16, Value) {
+    let status = if code == "InternalServerError" { 500 } else { 400 };
+    (status, json!({ "__type": format!("com.amazonaws.dynamodb.v20120810#{code}"), "message": code }))
+}
+
+/// Runs `future` to completion on a fresh Tokio runtime.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("tokio runtime")
+        .block_on(future)
+}
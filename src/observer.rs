@@ -0,0 +1,251 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use aws_sdk_dynamodb::{
+    config::{
+        interceptors::{
+            BeforeSerializationInterceptorContextMut, BeforeSerializationInterceptorContextRef,
+            FinalizerInterceptorContextRef,
+        },
+        ConfigBag, Intercept, RuntimeComponents,
+    },
+    operation::{
+        batch_get_item::BatchGetItemInput,
+        batch_write_item::{BatchWriteItemInput, BatchWriteItemOutput},
+        create_table::CreateTableInput,
+        delete_item::{DeleteItemInput, DeleteItemOutput},
+        delete_table::DeleteTableInput,
+        describe_table::DescribeTableInput,
+        describe_time_to_live::DescribeTimeToLiveInput,
+        get_item::GetItemInput,
+        put_item::{PutItemInput, PutItemOutput},
+        query::QueryInput,
+        scan::ScanInput,
+        transact_get_items::TransactGetItemsInput,
+        transact_write_items::{TransactWriteItemsInput, TransactWriteItemsOutput},
+        update_item::{UpdateItemInput, UpdateItemOutput},
+        update_table::UpdateTableInput,
+        update_time_to_live::UpdateTimeToLiveInput,
+    },
+    types::{ItemCollectionMetrics, ReturnItemCollectionMetrics},
+};
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    client::{interceptors::context::Input, orchestrator::Metadata},
+};
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+use tokio::time::Instant;
+
+/// Receives every DynamoDB call the pool makes, to feed metrics or traces
+/// into any telemetry system. Register one with `with_observer`. All hooks
+/// do nothing by default.
+///
+/// Hooks run inline with the request, so they should be quick. Each retry
+/// the pool makes under its `RetryPolicy` is observed as a call of its own.
+pub trait OperationObserver: Send + Sync + fmt::Debug {
+    /// Called before `op`, e.g. `"GetItem"`, is sent. `table` is the full
+    /// table name, or the first one for calls that span several tables, and
+    /// `None` for calls that target none, such as `ListTables`.
+    fn on_start(&self, op: &str, table: Option<&str>) {
+        let _ = (op, table);
+    }
+
+    /// Called once `op` completed or failed, `elapsed` after `on_start`.
+    fn on_finish(
+        &self,
+        op: &str,
+        table: Option<&str>,
+        result: Result<(), &(dyn std::error::Error + 'static)>,
+        elapsed: Duration,
+    ) {
+        let _ = (op, table, result, elapsed);
+    }
+
+    /// Called when the pool's `RetryPolicy` retries `op` after `delay`,
+    /// `attempt` being the number of the attempt that failed, with the
+    /// DynamoDB error `code` if there is one.
+    fn on_retry(&self, op: &str, attempt: u32, delay: Duration, code: Option<&str>) {
+        let _ = (op, attempt, delay, code);
+    }
+}
+
+/// Hands the SDK's execution hooks to an `OperationObserver`.
+#[derive(Debug)]
+pub(crate) struct ObserverInterceptor(pub(crate) Arc<dyn OperationObserver>);
+
+/// What `read_before_execution` remembers for `read_after_execution`.
+#[derive(Debug)]
+struct Started {
+    table: Option<String>,
+    at: Instant,
+}
+
+impl Storable for Started {
+    type Storer = StoreReplace<Self>;
+}
+
+impl Intercept for ObserverInterceptor {
+    fn name(&self) -> &'static str {
+        "OperationObserver"
+    }
+
+    fn read_before_execution(
+        &self,
+        context: &BeforeSerializationInterceptorContextRef<'_>,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let table = table_of(context.input());
+        self.0.on_start(operation_name(cfg), table.as_deref());
+
+        cfg.interceptor_state().store_put(Started {
+            table,
+            at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let (table, elapsed) = match cfg.load::<Started>() {
+            Some(started) => (started.table.as_deref(), started.at.elapsed()),
+            None => (None, Duration::ZERO),
+        };
+        let result = match context.output_or_error() {
+            Some(Err(err)) => Err(err as &(dyn std::error::Error + 'static)),
+            _ => Ok(()),
+        };
+
+        self.0.on_finish(operation_name(cfg), table, result, elapsed);
+        Ok(())
+    }
+}
+
+/// Name of the operation being executed, as the SDK records it.
+fn operation_name(cfg: &ConfigBag) -> &str {
+    cfg.load::<Metadata>().map_or("Unknown", |metadata| metadata.name())
+}
+
+/// The table an operation's input targets.
+fn table_of(input: &Input) -> Option<String> {
+    macro_rules! table_name {
+        ($($input:ty),*) => {
+            $(if let Some(input) = input.downcast_ref::<$input>() {
+                return input.table_name().map(str::to_string);
+            })*
+        };
+    }
+
+    table_name!(
+        GetItemInput,
+        PutItemInput,
+        UpdateItemInput,
+        DeleteItemInput,
+        QueryInput,
+        ScanInput,
+        CreateTableInput,
+        DescribeTableInput,
+        UpdateTableInput,
+        DeleteTableInput,
+        DescribeTimeToLiveInput,
+        UpdateTimeToLiveInput
+    );
+
+    if let Some(input) = input.downcast_ref::<BatchWriteItemInput>() {
+        return input.request_items().and_then(|items| items.keys().next()).cloned();
+    }
+    if let Some(input) = input.downcast_ref::<BatchGetItemInput>() {
+        return input.request_items().and_then(|items| items.keys().next()).cloned();
+    }
+    if let Some(input) = input.downcast_ref::<TransactWriteItemsInput>() {
+        return input.transact_items().iter().find_map(|item| {
+            let table = item
+                .put()
+                .map(|put| put.table_name())
+                .or_else(|| item.update().map(|update| update.table_name()))
+                .or_else(|| item.delete().map(|delete| delete.table_name()))
+                .or_else(|| item.condition_check().map(|check| check.table_name()));
+            table.map(str::to_string)
+        });
+    }
+    if let Some(input) = input.downcast_ref::<TransactGetItemsInput>() {
+        return input
+            .transact_items()
+            .iter()
+            .find_map(|item| item.get().map(|get| get.table_name().to_string()));
+    }
+
+    None
+}
+
+/// Receives the `ItemCollectionMetrics` DynamoDB returns for writes to
+/// tables with local secondary indexes, see `with_item_collection_metrics`.
+pub type ItemCollectionObserver = fn(&ItemCollectionMetrics);
+
+/// Asks for the item collection metrics of every write and hands them to an
+/// `ItemCollectionObserver`.
+#[derive(Debug)]
+pub(crate) struct ItemCollectionInterceptor(pub(crate) ItemCollectionObserver);
+
+impl Intercept for ItemCollectionInterceptor {
+    fn name(&self) -> &'static str {
+        "ItemCollectionMetrics"
+    }
+
+    fn modify_before_serialization(
+        &self,
+        context: &mut BeforeSerializationInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let input = context.input_mut();
+
+        macro_rules! return_item_collection_metrics {
+            ($($input:ty),*) => {
+                $(if let Some(input) = input.downcast_mut::<$input>() {
+                    input.return_item_collection_metrics.get_or_insert(ReturnItemCollectionMetrics::Size);
+                    return Ok(());
+                })*
+            };
+        }
+
+        return_item_collection_metrics!(
+            PutItemInput,
+            UpdateItemInput,
+            DeleteItemInput,
+            BatchWriteItemInput,
+            TransactWriteItemsInput
+        );
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let Some(Ok(output)) = context.output_or_error() else {
+            return Ok(());
+        };
+
+        macro_rules! single {
+            ($($output:ty),*) => {
+                $(if let Some(metrics) = output.downcast_ref::<$output>().and_then(|output| output.item_collection_metrics()) {
+                    (self.0)(metrics);
+                })*
+            };
+        }
+        single!(PutItemOutput, UpdateItemOutput, DeleteItemOutput);
+
+        if let Some(output) = output.downcast_ref::<BatchWriteItemOutput>() {
+            output.item_collection_metrics().into_iter().flatten().flat_map(|(_, metrics)| metrics).for_each(self.0);
+        }
+        if let Some(output) = output.downcast_ref::<TransactWriteItemsOutput>() {
+            output.item_collection_metrics().into_iter().flatten().flat_map(|(_, metrics)| metrics).for_each(self.0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,526 @@
+use std::{
+    any::TypeId,
+    collections::VecDeque,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use aws_sdk_dynamodb::{
+    error::{ErrorMetadata, ProvideErrorMetadata, SdkError},
+    operation::{
+        batch_get_item::BatchGetItemError,
+        describe_table::DescribeTableError,
+        describe_time_to_live::DescribeTimeToLiveError,
+        get_item::GetItemError,
+        list_tables::ListTablesError,
+        query::QueryError,
+        scan::{builders::ScanFluentBuilder, ScanError, ScanOutput},
+        transact_get_items::TransactGetItemsError,
+    },
+};
+use axum_session::DatabaseError;
+use futures::{Stream, StreamExt};
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::{error, Item, SessionDynamoDbPool, SessionKey};
+
+/// Error codes DynamoDB returns for transient conditions worth retrying.
+const RETRYABLE_CODES: &[&str] = &[
+    "ProvisionedThroughputExceededException",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "InternalServerError",
+    "ServiceUnavailable",
+];
+
+/// Decides whether an error outside the built-in retryable cases should be
+/// retried, given its code and message.
+pub type RetryClassifier = fn(&ErrorMetadata) -> bool;
+
+/// Retries the pool performs on top of the SDK's own retry strategy.
+///
+/// By default no additional attempts are made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts per request, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further one.
+    pub base_delay: Duration,
+    /// Upper bound on the delay before any single retry.
+    pub max_backoff: Option<Duration>,
+    /// Total time a request may spend across all its attempts. An attempt
+    /// still running when it passes times out, and no retry is started
+    /// whose delay would end past it; the last error is returned instead.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(50),
+            max_backoff: None,
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with full jitter before the retry that follows
+    /// attempt number `attempt`, counted from 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff_ceiling(attempt)
+            .mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    /// Longest delay `backoff` may pick: `base_delay` before the first
+    /// retry, doubled on every further one, at most `max_backoff`.
+    fn backoff_ceiling(&self, attempt: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+        self.max_backoff.map_or(ceiling, |max| ceiling.min(max))
+    }
+}
+
+/// Whether `E`, the error type of a DynamoDB operation, belongs to one
+/// that only reads, which `with_read_retry_policy` applies to.
+fn is_read_operation<E: 'static>() -> bool {
+    let error = TypeId::of::<E>();
+    [
+        TypeId::of::<GetItemError>(),
+        TypeId::of::<BatchGetItemError>(),
+        TypeId::of::<QueryError>(),
+        TypeId::of::<ScanError>(),
+        TypeId::of::<TransactGetItemsError>(),
+        TypeId::of::<DescribeTableError>(),
+        TypeId::of::<DescribeTimeToLiveError>(),
+        TypeId::of::<ListTablesError>(),
+    ]
+    .contains(&error)
+}
+
+/// Whether `E` belongs to a scan or query, which `with_scan_timeout`
+/// applies to rather than `with_operation_timeout`.
+fn is_scan_operation<E: 'static>() -> bool {
+    let error = TypeId::of::<E>();
+    error == TypeId::of::<ScanError>() || error == TypeId::of::<QueryError>()
+}
+
+/// Time an attempt may take: the configured `timeout`, cut short to what
+/// is left until the retry policy's `deadline`.
+fn attempt_timeout(timeout: Option<Duration>, deadline: Option<Instant>, now: Instant) -> Option<Duration> {
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(now));
+    match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
+    }
+}
+
+/// Items read so far by one logical scan, shared by all its pages and
+/// segments, which `with_max_scanned_items` caps.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScannedItems(Arc<AtomicUsize>);
+
+impl ScannedItems {
+    fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, page: &ScanOutput) {
+        self.0.fetch_add(page.scanned_count().max(0) as usize, Ordering::Relaxed);
+    }
+}
+
+/// Name of the operation whose error type is `E`, e.g. `"GetItem"` for
+/// `GetItemError`, matching the names the SDK records.
+fn operation_name<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    let name = name.rsplit("::").next().unwrap_or(name);
+    name.strip_suffix("Error").unwrap_or(name)
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    fn is_retryable<E: ProvideErrorMetadata, R>(&self, err: &SdkError<E, R>) -> bool {
+        match err {
+            SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => true,
+            _ => {
+                err.code().is_some_and(|code| RETRYABLE_CODES.contains(&code))
+                    || self.config.retry_classifier.is_some_and(|classify| classify(err.meta()))
+            }
+        }
+    }
+
+    /// Sends a request, retrying it per the retry policy configured for
+    /// its class of operation.
+    pub(crate) async fn send<T, E, R, Fut>(&self, mut send: impl FnMut() -> Fut) -> Result<T, SdkError<E, R>>
+    where
+        E: ProvideErrorMetadata + 'static,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        let policy = if is_read_operation::<E>() {
+            self.config.read_retry_policy
+        } else {
+            self.config.write_retry_policy
+        }
+        .unwrap_or(self.config.retry_policy);
+        let deadline = policy.deadline.map(|deadline| Instant::now() + deadline);
+        let timeout = if is_scan_operation::<E>() {
+            self.config.scan_timeout
+        } else {
+            self.config.operation_timeout
+        };
+        let mut attempt = 1;
+
+        loop {
+            let result = match attempt_timeout(timeout, deadline, Instant::now()) {
+                Some(timeout) => tokio::time::timeout(timeout, send()).await.unwrap_or_else(|_| {
+                    Err(SdkError::timeout_error(format!(
+                        "{} timed out after {timeout:?}",
+                        operation_name::<E>()
+                    )))
+                }),
+                None => send().await,
+            };
+
+            match result {
+                Err(err) if attempt < policy.max_attempts && self.is_retryable(&err) => {
+                    let delay = policy.backoff(attempt);
+                    if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                        return Err(err);
+                    }
+
+                    let op = operation_name::<E>();
+                    tracing::warn!(op, attempt, ?delay, code = err.code(), "retrying DynamoDB request");
+                    for observer in &self.config.observers {
+                        observer.on_retry(op, attempt, delay, err.code());
+                    }
+
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Runs a scan to completion, handing every page to `on_page`, unless it
+    /// reads more than `with_max_scanned_items` items first.
+    pub(crate) async fn scan_pages(
+        &self,
+        request: ScanFluentBuilder,
+        mut on_page: impl FnMut(ScanOutput),
+    ) -> Result<(), DatabaseError> {
+        let scanned = ScannedItems::default();
+        let mut start_key = None;
+
+        loop {
+            let mut page = self.scan_page(&request, start_key.take(), &scanned).await?;
+
+            start_key = page.last_evaluated_key.take();
+            on_page(page);
+
+            if start_key.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Fails a scan that has read `with_max_scanned_items` items or more
+    /// and is about to fetch another page.
+    fn check_scan_limit(&self, request: &ScanFluentBuilder, scanned: usize) -> Result<(), DatabaseError> {
+        match self.config.max_scanned_items {
+            Some(max) if scanned >= max => Err(DatabaseError::GenericSelectError(format!(
+                "{}: scan of {} stopped after {scanned} items, the limit is {max}",
+                error::SCAN_LIMIT_EXCEEDED,
+                request.get_table_name().as_deref().unwrap_or_default()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fetches the page of a scan that starts at `start_key`, counting its
+    /// items into `scanned`, which the scan's other pages and segments
+    /// share. Fails instead if the scan already read `with_max_scanned_items`
+    /// items.
+    pub(crate) async fn scan_page(
+        &self,
+        request: &ScanFluentBuilder,
+        start_key: Option<Item>,
+        scanned: &ScannedItems,
+    ) -> Result<ScanOutput, DatabaseError> {
+        self.check_scan_limit(request, scanned.get())?;
+
+        let page_request = request.clone().set_exclusive_start_key(start_key);
+
+        match self.send(|| page_request.clone().send()).await {
+            Ok(page) => {
+                scanned.add(&page);
+                Ok(page)
+            }
+            Err(e) if e.code() == Some("ResourceNotFoundException") => {
+                let table_name = request.get_table_name().as_deref().unwrap_or_default();
+                Err(self.resource_not_found(table_name, e.to_string()).await)
+            }
+            Err(e) => Err(error::map_sdk_error(e, DatabaseError::GenericSelectError)),
+        }
+    }
+
+    /// Lazily runs a scan, yielding its items as pages arrive. A permit is
+    /// held while each page is fetched. The stream ends after the first error,
+    /// including exceeding `with_max_scanned_items`, counted in `scanned`.
+    pub(crate) fn scan_stream(
+        &self,
+        request: ScanFluentBuilder,
+        scanned: ScannedItems,
+    ) -> impl Stream<Item = Result<Item, DatabaseError>> + '_ {
+        let state = (VecDeque::new(), None, false);
+
+        futures::stream::unfold(state, move |(mut pending, mut start_key, mut done)| {
+            let request = request.clone();
+            let scanned = scanned.clone();
+
+            async move {
+                loop {
+                    if let Some(item) = pending.pop_front() {
+                        return Some((Ok(item), (pending, start_key, done)));
+                    }
+                    if done {
+                        return None;
+                    }
+
+                    let page = match self.permit().await {
+                        Ok(_permit) => self.scan_page(&request, start_key.take(), &scanned).await,
+                        Err(e) => Err(e),
+                    };
+
+                    let page = match page {
+                        Ok(page) => page,
+                        Err(e) => return Some((Err(e), (pending, None, true))),
+                    };
+
+                    pending.extend(page.items.unwrap_or_default());
+                    start_key = page.last_evaluated_key;
+                    done = start_key.is_none();
+                }
+            }
+        })
+    }
+
+    /// One request per `with_scan_segments` segment of a scan, or the scan
+    /// itself without segments.
+    pub(crate) fn segment_requests(&self, request: &ScanFluentBuilder) -> Vec<ScanFluentBuilder> {
+        let segments = self.config.scan_segments;
+        match segments {
+            1 => vec![request.clone()],
+            _ => (0..segments)
+                .map(|segment| request.clone().segment(segment).total_segments(segments))
+                .collect(),
+        }
+    }
+
+    /// `scan_stream` split into the `with_scan_segments` parallel segments,
+    /// whose items are interleaved as they arrive. The segments share one
+    /// count towards `with_max_scanned_items`, and the stream ends after the
+    /// first error of any segment.
+    pub(crate) fn segmented_scan_stream(
+        &self,
+        request: ScanFluentBuilder,
+    ) -> impl Stream<Item = Result<Item, DatabaseError>> + '_ {
+        let scanned = ScannedItems::default();
+        let streams = self
+            .segment_requests(&request)
+            .into_iter()
+            .map(|request| Box::pin(self.scan_stream(request, scanned.clone())));
+
+        futures::stream::select_all(streams).scan(false, |failed, item| {
+            let item = (!*failed).then_some(item);
+            *failed = item.as_ref().is_some_and(Result::is_err);
+            futures::future::ready(item)
+        })
+    }
+
+    /// Runs a scan to completion and collects every returned item.
+    pub(crate) async fn scan_items(&self, request: ScanFluentBuilder) -> Result<Vec<Item>, DatabaseError> {
+        let mut items = Vec::new();
+        self.scan_pages(request, |page| items.extend(page.items.unwrap_or_default()))
+            .await?;
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use aws_sdk_dynamodb::operation::get_item::GetItemOutput;
+    use aws_smithy_runtime_api::{client::orchestrator::HttpResponse, http::StatusCode};
+    use aws_smithy_types::body::SdkBody;
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{mock, session_pool::test_pool};
+
+    /// A `GetItem` service error with the given code, as `send` sees it.
+    fn service_error(code: &str) -> SdkError<GetItemError, HttpResponse> {
+        let error = GetItemError::generic(ErrorMetadata::builder().code(code).build());
+        let response = HttpResponse::new(StatusCode::try_from(400).unwrap(), SdkBody::empty());
+        SdkError::service_error(error, response)
+    }
+
+    /// Sends a request that fails once with `code` and then succeeds through
+    /// `pool`, returning the result and the number of attempts made.
+    fn fail_once(pool: &SessionDynamoDbPool, code: &str) -> (Result<GetItemOutput, SdkError<GetItemError, HttpResponse>>, u32) {
+        let attempts = Cell::new(0);
+        let result = mock::block_on(pool.send(|| {
+            attempts.set(attempts.get() + 1);
+            let result = match attempts.get() {
+                1 => Err(service_error(code)),
+                _ => Ok(GetItemOutput::builder().build()),
+            };
+            async move { result }
+        }));
+        (result, attempts.get())
+    }
+
+    #[test]
+    fn retry_classifier_makes_fatal_errors_retryable() {
+        let pool = test_pool().with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        });
+
+        let (result, attempts) = fail_once(&pool, "AccountBlip");
+        assert_eq!(result.unwrap_err().code(), Some("AccountBlip"));
+        assert_eq!(attempts, 1);
+
+        let pool = pool.with_retry_classifier(|meta| meta.code() == Some("AccountBlip"));
+        let (result, attempts) = fail_once(&pool, "AccountBlip");
+        assert!(result.is_ok());
+        assert_eq!(attempts, 2);
+
+        let (result, attempts) = fail_once(&pool, "ValidationException");
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn backoff_ceiling_starts_at_base_delay_and_doubles() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(50),
+            ..RetryPolicy::default()
+        };
+
+        let ceilings: Vec<_> = (1..=4).map(|attempt| policy.backoff_ceiling(attempt)).collect();
+
+        assert_eq!(ceilings, [50, 100, 200, 400].map(Duration::from_millis));
+        assert_eq!(policy.backoff_ceiling(100), Duration::from_millis(50) * (1 << 16));
+    }
+
+    #[test]
+    fn backoff_is_clamped_to_max_backoff() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(50),
+            max_backoff: Some(Duration::from_millis(120)),
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(policy.backoff_ceiling(2), Duration::from_millis(100));
+        assert_eq!(policy.backoff_ceiling(3), Duration::from_millis(120));
+        assert!((1..=20).all(|attempt| policy.backoff(attempt) <= Duration::from_millis(120)));
+    }
+
+    #[test]
+    fn send_gives_up_at_the_deadline_with_the_last_error() {
+        let pool = test_pool().with_retry_policy(RetryPolicy {
+            max_attempts: 1000,
+            base_delay: Duration::from_millis(100),
+            max_backoff: Some(Duration::from_secs(1)),
+            deadline: Some(Duration::from_secs(5)),
+        });
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        let attempts = Cell::new(0);
+
+        let (result, elapsed) = runtime.block_on(async {
+            let start = Instant::now();
+            let result = pool
+                .send(|| {
+                    attempts.set(attempts.get() + 1);
+                    async { Err::<GetItemOutput, _>(service_error("ThrottlingException")) }
+                })
+                .await;
+            (result, start.elapsed())
+        });
+
+        assert_eq!(result.unwrap_err().code(), Some("ThrottlingException"));
+        assert!(attempts.get() > 1);
+        assert!(elapsed >= Duration::from_secs(4) && elapsed < Duration::from_secs(5), "{elapsed:?}");
+    }
+
+    /// A pool over an endless table, whose pages of 10 scanned items hold
+    /// no sessions, limited to 25 scanned items and one request at a time,
+    /// along with the number of pages fetched.
+    fn endless_scan_pool() -> (SessionDynamoDbPool, Arc<AtomicUsize>) {
+        let pages = Arc::new(AtomicUsize::new(0));
+        let fetched = Arc::clone(&pages);
+
+        let pool = mock::mock_pool(move |operation, _| {
+            assert_eq!(operation, "Scan");
+            fetched.fetch_add(1, Ordering::Relaxed);
+            (200, json!({ "Items": [], "Count": 0, "ScannedCount": 10, "LastEvaluatedKey": { "id": { "S": "next" } } }))
+        })
+        .with_max_scanned_items(25)
+        .with_max_concurrent_requests(1);
+
+        (pool, pages)
+    }
+
+    #[test]
+    fn scan_limit_is_shared_by_all_segments() {
+        for segments in [1, 4] {
+            let (pool, pages) = endless_scan_pool();
+            let pool = pool.with_scan_segments(segments);
+
+            let err = mock::block_on(pool.sessions_stream("sessions").try_collect::<Vec<_>>()).unwrap_err();
+
+            assert!(error::is_scan_limit_exceeded(&err), "{err:?}");
+            assert!(err.to_string().contains("stopped after 30 items"), "{err}");
+            assert_eq!(pages.load(Ordering::Relaxed), 3, "{segments} segments");
+        }
+    }
+
+    #[test]
+    fn scan_limit_applies_to_paged_scans() {
+        let (pool, pages) = endless_scan_pool();
+
+        let err = mock::block_on(pool.delete_by_expiry_paged(1000, "sessions")).unwrap_err();
+
+        assert!(error::is_scan_limit_exceeded(&err), "{err:?}");
+        assert_eq!(pages.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn attempt_timeout_is_cut_to_the_deadline() {
+        let now = Instant::now();
+        let deadline = Some(now + Duration::from_secs(2));
+
+        assert_eq!(attempt_timeout(None, None, now), None);
+        assert_eq!(attempt_timeout(Some(Duration::from_secs(5)), None, now), Some(Duration::from_secs(5)));
+        assert_eq!(attempt_timeout(None, deadline, now), Some(Duration::from_secs(2)));
+        assert_eq!(attempt_timeout(Some(Duration::from_secs(5)), deadline, now), Some(Duration::from_secs(2)));
+        assert_eq!(attempt_timeout(Some(Duration::from_secs(1)), deadline, now), Some(Duration::from_secs(1)));
+        assert_eq!(
+            attempt_timeout(Some(Duration::from_secs(1)), deadline, now + Duration::from_secs(3)),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum_session::DatabaseError;
+
+use crate::{error, SessionDynamoDbPool, SessionKey};
+
+/// Attribute marking an item as a revocation tombstone.
+const REVOKED_ATTRIBUTE: &str = "revoked";
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Denies `id` until `until_unix` by writing a tombstone, for ids that
+    /// can't simply be deleted, e.g. tokens held by clients. TTL removes the
+    /// tombstone once it expires.
+    ///
+    /// Tombstones are keyed like sessions, so `table_name` should be a
+    /// dedicated table, set up with `initiate`, rather than the session
+    /// table, where a tombstone would replace the session with the same id.
+    pub async fn revoke(&self, id: &str, until_unix: i64, table_name: &str) -> Result<(), DatabaseError> {
+        let mut item = self.key(id)?;
+        item.insert(REVOKED_ATTRIBUTE.into(), AttributeValue::Bool(true));
+        item.insert(self.expires_attribute().into(), AttributeValue::N(until_unix.to_string()));
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(table_name))
+            .set_item(Some(item));
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError))?;
+        Ok(())
+    }
+
+    /// Returns `true` if `id` has a tombstone written by `revoke` that
+    /// hasn't expired yet.
+    pub async fn is_revoked(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let item = self
+            .get_item(id, self.config.default_read_consistency, table_name)
+            .await?;
+
+        Ok(item.is_some_and(|item| {
+            item.get(REVOKED_ATTRIBUTE).and_then(|r| r.as_bool().ok()) == Some(&true) && !self.is_expired(&item)
+        }))
+    }
+}
@@ -1,115 +1,2916 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use async_trait::async_trait;
-use aws_sdk_dynamodb::types::{AttributeDefinition, AttributeValue, KeySchemaElement, KeyType, ScalarAttributeType, Select, TimeToLiveSpecification};
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::{
+    client::Waiters,
+    config::AppName,
+    error::{BuildError, ProvideErrorMetadata, SdkError},
+    operation::{
+        get_item::{builders::GetItemFluentBuilder, GetItemOutput},
+        update_item::builders::UpdateItemFluentBuilder,
+    },
+    types::{
+        AttributeDefinition, AttributeValue, BillingMode, CreateGlobalSecondaryIndexAction, GlobalSecondaryIndex,
+        GlobalSecondaryIndexUpdate, IndexStatus, KeySchemaElement, KeyType, Projection,
+        ProjectionType, ProvisionedThroughput, ReturnValue, ReturnValuesOnConditionCheckFailure, ScalarAttributeType, Select, TimeToLiveSpecification, TimeToLiveStatus,
+    },
+};
 use axum_session::{DatabaseError, DatabasePool};
+use chrono::DateTime;
+use rand::Rng;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    audit,
+    capacity::{CapacityInterceptor, CapacityTotals},
+    coalesce::LoadCoalescer,
+    codec, error,
+    expression::Filter,
+    observer::{ItemCollectionInterceptor, ObserverInterceptor},
+    AuditMode, CompressionAlgorithm, DynamoDbClient, ItemCollectionObserver, KeyLayout, OperationObserver,
+    RetryClassifier, RetryPolicy, SessionKey, StringCodec,
+};
+
+/// Largest `expires` accepted while TTL is managed, in the year 5138. Unix
+/// timestamps in milliseconds are far above it.
+const MAX_PLAUSIBLE_EXPIRES: i64 = 100_000_000_000;
+
+/// A DynamoDB item, keyed by attribute name.
+pub type Item = HashMap<String, AttributeValue>;
+
+/// Mints the ids of the sessions `create` stores, see `with_id_generator`.
+pub type IdGenerator = fn() -> String;
+
+/// How many ids `create` tries before giving up on collisions.
+const CREATE_ATTEMPTS: u32 = 3;
+
+/// Attribute holding the constant partition value of the expiry index.
+pub(crate) const EXPIRY_PARTITION_KEY: &str = "expiry_partition";
+
+/// Value of `EXPIRY_PARTITION_KEY` on every session item.
+pub(crate) const EXPIRY_PARTITION_VALUE: &str = "session";
+
+/// Attribute holding the user a session was stored for by `store_for_user`.
+pub(crate) const USER_ATTRIBUTE: &str = "user_id";
+
+/// Attribute holding the payload version written under `with_payload_version`.
+const PAYLOAD_VERSION_ATTRIBUTE: &str = "payload_version";
+
+/// Attribute holding the time a session was first stored, see `with_created_at`.
+const CREATED_ATTRIBUTE: &str = "created_at";
+
+/// Attribute holding a session's expiry unless `with_expires_attribute`
+/// names another.
+pub(crate) const DEFAULT_EXPIRES_ATTRIBUTE: &str = "expires";
+
+/// Attribute marking a session pinned by `pin`, which never expires.
+pub(crate) const PINNED_ATTRIBUTE: &str = "pinned";
+
+/// Attributes `session_item` writes depending on the pool's options, or
+/// leaves out for pinned sessions, besides the expiry attribute, whose name
+/// is configurable, see `expires_attribute`.
+const MANAGED_ATTRIBUTES: [&str; 6] = [
+    PAYLOAD_VERSION_ATTRIBUTE,
+    "expires_iso",
+    EXPIRY_PARTITION_KEY,
+    "checksum",
+    LAST_MODIFIED_ATTRIBUTE,
+    DELETED_ATTRIBUTE,
+];
+
+/// Attribute holding the time a session was soft deleted, see
+/// `with_soft_delete`.
+pub(crate) const DELETED_ATTRIBUTE: &str = "deleted_at";
+
+/// Attribute holding the time a session was last written, see
+/// `with_last_modified`.
+pub(crate) const LAST_MODIFIED_ATTRIBUTE: &str = "last_modified";
+
+/// Attribute holding the header written by `store_with_header`.
+const HEADER_ATTRIBUTE: &str = "header";
+
+/// How long `initiate` waits for an index added to an existing table to
+/// backfill and become ACTIVE, and how often it checks.
+const INDEX_ACTIVE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const INDEX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Length bounds DynamoDB puts on table names.
+const MIN_TABLE_NAME_LEN: usize = 3;
+const MAX_TABLE_NAME_LEN: usize = 255;
+
+/// How long `initiate` waits for a newly created table to become ACTIVE.
+const TABLE_ACTIVE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Session pool backed by a DynamoDB table, with ids represented as `K`.
+///
+/// Cloning is cheap: clones share the client, with its resolved endpoint,
+/// credentials cache and connection pool, as well as the concurrency limit.
+/// Build one pool at startup and clone it wherever a pool is needed rather
+/// than building a new one per store or request.
+#[derive(Clone, Debug)]
+pub struct SessionDynamoDbPool<K: SessionKey = String> {
+    pub(crate) client: DynamoDbClient,
+    pub(crate) config: PoolConfig,
+    /// Gates every DynamoDB call when `max_concurrent_requests` is set.
+    pub(crate) semaphore: Option<Arc<Semaphore>>,
+    /// Tables `prepare` has checked, shared by the pool's clones.
+    prepared: Arc<Mutex<HashSet<String>>>,
+    key: PhantomData<K>,
+}
+
+/// Options set through the pool's `with_*` methods.
+#[derive(Clone, Debug)]
+pub(crate) struct PoolConfig {
+    pub(crate) checksum: bool,
+    pub(crate) session_as_map: bool,
+    pub(crate) string_codec: StringCodec,
+    pub(crate) compression_threshold_bytes: usize,
+    pub(crate) compression_algorithm: CompressionAlgorithm,
+    pub(crate) large_payload: Option<(usize, LargePayloadStrategy)>,
+    pub(crate) ttl_jitter_seconds: i64,
+    pub(crate) default_read_consistency: ReadConsistency,
+    pub(crate) consistency_fallback: bool,
+    pub(crate) read_after_write_retry: Option<(u32, Duration)>,
+    pub(crate) permit_timeout: Option<Duration>,
+    pub(crate) operation_timeout: Option<Duration>,
+    pub(crate) scan_timeout: Option<Duration>,
+    pub(crate) capacity: Capacity,
+    pub(crate) index_capacity: HashMap<String, (i64, i64)>,
+    pub(crate) capacity_totals: Option<Arc<CapacityTotals>>,
+    pub(crate) manage_ttl: bool,
+    pub(crate) expires_attribute: String,
+    pub(crate) strict_expires_check: bool,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) read_retry_policy: Option<RetryPolicy>,
+    pub(crate) write_retry_policy: Option<RetryPolicy>,
+    pub(crate) retry_classifier: Option<RetryClassifier>,
+    pub(crate) id_generator: IdGenerator,
+    /// Observers registered with `with_observer`, told about retries.
+    pub(crate) observers: Vec<Arc<dyn OperationObserver>>,
+    pub(crate) key_layout: KeyLayout,
+    pub(crate) id_salt: Option<String>,
+    pub(crate) key_value_prefix: Option<String>,
+    pub(crate) expiry_index: Option<String>,
+    pub(crate) user_index: Option<String>,
+    pub(crate) missing_attributes: MissingAttributes,
+    pub(crate) exists_marker: Option<String>,
+    pub(crate) query_indexes: HashMap<String, Vec<String>>,
+    pub(crate) create_user_index: bool,
+    pub(crate) add_missing_indexes: bool,
+    pub(crate) ensure_ttl: bool,
+    pub(crate) schema_check_timeout: Duration,
+    pub(crate) expected_sse: Option<ExpectedSse>,
+    pub(crate) expires_iso: bool,
+    pub(crate) last_modified: bool,
+    pub(crate) monotonic_touch: bool,
+    pub(crate) table_prefix: String,
+    pub(crate) max_delete_guard: Option<usize>,
+    pub(crate) allow_drop_table: bool,
+    pub(crate) max_batch_retries: u32,
+    pub(crate) batch_failure_mode: BatchFailureMode,
+    pub(crate) scan_segments: i32,
+    pub(crate) delete_workers: usize,
+    pub(crate) write_shards: Option<u8>,
+    pub(crate) max_scanned_items: Option<usize>,
+    pub(crate) load_grace_seconds: i64,
+    pub(crate) payload_version: Option<u8>,
+    pub(crate) upsert: bool,
+    pub(crate) store_mode: StoreMode,
+    pub(crate) created_at: bool,
+    pub(crate) ignore_missing_table_on_delete: bool,
+    pub(crate) soft_delete: Option<Duration>,
+    pub(crate) deletion_log: Option<(String, Duration)>,
+    pub(crate) treat_missing_as_empty: bool,
+    pub(crate) audit_table: Option<String>,
+    pub(crate) audit_mode: AuditMode,
+    pub(crate) activity_summary: bool,
+    #[cfg(feature = "encryption")]
+    pub(crate) encryption: Option<crate::EncryptionKeys>,
+    #[cfg(feature = "batching")]
+    pub(crate) write_batching: Option<Arc<crate::batcher::WriteBatcher>>,
+    pub(crate) load_coalescing: Option<Arc<LoadCoalescer>>,
+    #[cfg(feature = "autoscaling")]
+    pub(crate) auto_scaling: Option<crate::AutoScaling>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            checksum: false,
+            session_as_map: false,
+            string_codec: StringCodec::default(),
+            compression_threshold_bytes: 0,
+            compression_algorithm: CompressionAlgorithm::default(),
+            large_payload: None,
+            ttl_jitter_seconds: 0,
+            default_read_consistency: ReadConsistency::default(),
+            consistency_fallback: false,
+            read_after_write_retry: None,
+            permit_timeout: None,
+            operation_timeout: None,
+            scan_timeout: None,
+            capacity: Capacity::default(),
+            index_capacity: HashMap::new(),
+            capacity_totals: None,
+            manage_ttl: true,
+            expires_attribute: DEFAULT_EXPIRES_ATTRIBUTE.into(),
+            strict_expires_check: false,
+            retry_policy: RetryPolicy::default(),
+            read_retry_policy: None,
+            write_retry_policy: None,
+            retry_classifier: None,
+            id_generator: random_uuid,
+            observers: Vec::new(),
+            key_layout: KeyLayout::default(),
+            id_salt: None,
+            key_value_prefix: None,
+            expiry_index: None,
+            user_index: None,
+            missing_attributes: MissingAttributes::default(),
+            exists_marker: None,
+            query_indexes: HashMap::new(),
+            create_user_index: true,
+            add_missing_indexes: false,
+            ensure_ttl: false,
+            schema_check_timeout: Duration::from_secs(3),
+            expected_sse: None,
+            expires_iso: false,
+            last_modified: false,
+            monotonic_touch: false,
+            table_prefix: String::new(),
+            max_delete_guard: None,
+            allow_drop_table: false,
+            max_batch_retries: 8,
+            batch_failure_mode: BatchFailureMode::default(),
+            scan_segments: 1,
+            delete_workers: 1,
+            write_shards: None,
+            max_scanned_items: None,
+            load_grace_seconds: 0,
+            payload_version: None,
+            upsert: false,
+            store_mode: StoreMode::default(),
+            created_at: false,
+            ignore_missing_table_on_delete: false,
+            soft_delete: None,
+            deletion_log: None,
+            treat_missing_as_empty: false,
+            audit_table: None,
+            audit_mode: AuditMode::default(),
+            activity_summary: false,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "batching")]
+            write_batching: None,
+            load_coalescing: None,
+            #[cfg(feature = "autoscaling")]
+            auto_scaling: None,
+        }
+    }
+}
+
+/// Capacity mode of tables created by `initiate`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Capacity {
+    /// Pay-per-request billing.
+    #[default]
+    OnDemand,
+    /// Provisioned read and write capacity units.
+    Provisioned { read: i64, write: i64 },
+}
+
+/// How `store` treats an id that already has a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoreMode {
+    /// The new session replaces the stored one.
+    #[default]
+    Overwrite,
+    /// The store fails with a [`SESSION_EXISTS`](crate::SESSION_EXISTS)
+    /// error, for apps that treat an id collision as a bug.
+    RejectExisting,
+}
+
+/// What `load_many` does with keys DynamoDB still left unprocessed once
+/// `max_batch_retries` ran out, set with `with_batch_failure_mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BatchFailureMode {
+    /// The sessions read are returned, with the unprocessed ids listed in
+    /// `LoadMany::unprocessed`.
+    #[default]
+    Lenient,
+    /// The call fails, so a result is never partial.
+    Strict,
+}
+
+/// What `store` does with a payload over the limit set with
+/// `with_large_payload_strategy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LargePayloadStrategy {
+    /// The store fails.
+    #[default]
+    Reject,
+    /// The payload is stored as with `StringCodec::GzipBase64`, whatever
+    /// the configured codec, and rejected if it is still too large.
+    Compress,
+}
+
+/// How optional attributes without a value, such as the fields of a
+/// `SessionContext`, are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingAttributes {
+    /// The attribute is left out, so `attribute_not_exists` matches it.
+    #[default]
+    Omit,
+    /// The attribute is stored as NULL, so `attribute_exists` matches it
+    /// and `attribute_type(#name, :null)` with `:null` set to `"NULL"`
+    /// tells it apart.
+    Null,
+}
+
+/// Server-side encryption `initiate` requires of an existing table, see
+/// `with_expected_sse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpectedSse {
+    /// Encrypted with the AWS owned key, DynamoDB's default.
+    AwsOwned,
+    /// Encrypted with a KMS key, the AWS managed one or a customer managed
+    /// one. If given, `key_arn` must be the ARN of the key in use.
+    Kms { key_arn: Option<String> },
+}
+
+/// Result of `validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionValidity {
+    /// The session is live, with its decoded payload.
+    Valid(String),
+    /// The session is stored but has expired, and TTL hasn't removed it yet.
+    Expired,
+    /// No session is stored under the id.
+    Missing,
+    /// The payload doesn't match its stored checksum, see `with_checksum`.
+    CorruptChecksum,
+}
+
+/// Consistency of the reads issued by the pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadConsistency {
+    #[default]
+    Eventual,
+    Strong,
+}
+
+impl ReadConsistency {
+    pub(crate) fn is_strong(self) -> bool {
+        self == Self::Strong
+    }
+}
+
+/// App name the pool adds to the SDK's user agent unless the client has one.
+const DEFAULT_APP_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
+
+impl From<DynamoDbClient> for SessionDynamoDbPool {
+    /// Wraps the client, tagging its requests with this crate's name and
+    /// version in the user agent unless the client sets its own app name.
+    fn from(client: DynamoDbClient) -> Self {
+        let client = match client.config().app_name() {
+            Some(_) => client,
+            None => with_app_name(&client, AppName::new(DEFAULT_APP_NAME).expect("crate name is a valid app name")),
+        };
+
+        Self{
+            client,
+            config: PoolConfig::default(),
+            semaphore: None,
+            prepared: Arc::default(),
+            key: PhantomData,
+        }
+    }
+}
+
+impl SessionDynamoDbPool {
+    /// Builds a pool from a named profile in `~/.aws/config` and
+    /// `~/.aws/credentials`, using both its credentials and its region.
+    pub async fn from_profile(name: &str) -> Self {
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .profile_name(name)
+            .load()
+            .await;
+
+        DynamoDbClient::new(&config).into()
+    }
+
+    /// Builds a pool that authenticates with a web identity token, as on EKS
+    /// with IAM roles for service accounts, rather than whatever the default
+    /// provider chain finds first. The token provider reads:
+    ///
+    /// - `AWS_WEB_IDENTITY_TOKEN_FILE` (required): the path of the token file.
+    /// - `AWS_ROLE_ARN` (required): the role to assume.
+    /// - `AWS_ROLE_SESSION_NAME`: the name of the assumed role session.
+    ///
+    /// The region comes from the default region chain, e.g. `AWS_REGION`.
+    /// The variables are read when credentials are first needed, so missing
+    /// ones fail the first request rather than this call.
+    pub async fn from_web_identity() -> Self {
+        let shared = aws_config::defaults(BehaviorVersion::latest()).load().await;
+
+        let mut provider_config = aws_config::provider_config::ProviderConfig::default();
+        if let Some(region) = shared.region() {
+            provider_config = provider_config.with_region(Some(region.clone()));
+        }
+
+        let credentials = aws_config::web_identity_token::WebIdentityTokenCredentialsProvider::builder()
+            .configure(&provider_config)
+            .build();
+
+        let config = aws_sdk_dynamodb::config::Builder::from(&shared)
+            .credentials_provider(credentials)
+            .build();
+
+        DynamoDbClient::from_conf(config).into()
+    }
+
+    /// Builds a pool from environment variables:
+    ///
+    /// - `SESSION_DYNAMODB_REGION` (required): the AWS region.
+    /// - `SESSION_DYNAMODB_ENDPOINT`: an endpoint override, e.g. for DynamoDB Local.
+    /// - `SESSION_DYNAMODB_TABLE_PREFIX`: a prefix for every table name, see `with_table_prefix`.
+    /// - `SESSION_DYNAMODB_BILLING_MODE`: `PAY_PER_REQUEST` (the default) or
+    ///   `PROVISIONED`, which also requires `SESSION_DYNAMODB_READ_CAPACITY`
+    ///   and `SESSION_DYNAMODB_WRITE_CAPACITY`.
+    ///
+    /// Credentials come from the default AWS provider chain. Fails listing
+    /// every missing required variable, or naming the first invalid one.
+    pub async fn from_env() -> Result<Self, DatabaseError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let mut missing = Vec::new();
+
+        let region = var("SESSION_DYNAMODB_REGION");
+        if region.is_none() {
+            missing.push("SESSION_DYNAMODB_REGION");
+        }
+
+        let capacity_units = |name: &'static str, missing: &mut Vec<&str>| match var(name) {
+            Some(units) => units.parse::<i64>().map(Some).map_err(|e| {
+                DatabaseError::GenericCreateError(format!("invalid {name} {units:?}: {e}"))
+            }),
+            None => {
+                missing.push(name);
+                Ok(None)
+            }
+        };
+
+        let capacity = match var("SESSION_DYNAMODB_BILLING_MODE").as_deref() {
+            None | Some("PAY_PER_REQUEST") => Some(Capacity::OnDemand),
+            Some("PROVISIONED") => {
+                let read = capacity_units("SESSION_DYNAMODB_READ_CAPACITY", &mut missing)?;
+                let write = capacity_units("SESSION_DYNAMODB_WRITE_CAPACITY", &mut missing)?;
+                read.zip(write).map(|(read, write)| Capacity::Provisioned { read, write })
+            }
+            Some(other) => {
+                return Err(DatabaseError::GenericCreateError(format!(
+                    "invalid SESSION_DYNAMODB_BILLING_MODE {other:?}, expected PAY_PER_REQUEST or PROVISIONED"
+                )))
+            }
+        };
+
+        let (Some(region), Some(capacity)) = (region, capacity) else {
+            return Err(DatabaseError::GenericCreateError(format!(
+                "missing environment variables: {}",
+                missing.join(", ")
+            )));
+        };
+
+        let mut loader = aws_config::defaults(BehaviorVersion::latest()).region(Region::new(region));
+        if let Some(endpoint) = var("SESSION_DYNAMODB_ENDPOINT") {
+            loader = loader.endpoint_url(endpoint);
+        }
+
+        let pool = Self::from(DynamoDbClient::new(&loader.load().await)).with_capacity(capacity);
+
+        Ok(match var("SESSION_DYNAMODB_TABLE_PREFIX") {
+            Some(prefix) => pool.with_table_prefix(prefix),
+            None => pool,
+        })
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Switches the representation of session ids, e.g. to `Uuid` for ids
+    /// stored as Binary or to `i64` for numeric ids.
+    pub fn with_key_type<T: SessionKey>(self) -> SessionDynamoDbPool<T> {
+        SessionDynamoDbPool {
+            client: self.client,
+            config: self.config,
+            semaphore: self.semaphore,
+            prepared: self.prepared,
+            key: PhantomData,
+        }
+    }
+
+    /// The DynamoDB client the pool sends its requests with, shared by all of
+    /// its clones. Cloning it is cheap too, e.g. to build a second pool with
+    /// other options through `From<DynamoDbClient>` without resolving the
+    /// AWS config again.
+    pub fn client(&self) -> &DynamoDbClient {
+        &self.client
+    }
+
+    /// Replaces the app name the SDK appends to its user agent, by default
+    /// this crate's name and version, so the pool's traffic can be told
+    /// apart in CloudTrail and service logs. App names are limited to ASCII
+    /// alphanumerics and ``!#$%&'*+-.^_`|~``; an invalid one is logged and
+    /// ignored.
+    pub fn with_user_agent_suffix(mut self, suffix: impl Into<String>) -> Self {
+        match AppName::new(suffix.into()) {
+            Ok(app_name) => self.client = with_app_name(&self.client, app_name),
+            Err(e) => tracing::warn!("ignoring user agent suffix: {e}"),
+        }
+        self
+    }
+
+    /// Reports every DynamoDB call the pool makes to `observer`, e.g. to
+    /// record latencies and errors per operation and table.
+    pub fn with_observer(mut self, observer: impl OperationObserver + 'static) -> Self {
+        let observer: Arc<dyn OperationObserver> = Arc::new(observer);
+        self.config.observers.push(observer.clone());
+
+        let interceptor = ObserverInterceptor(observer);
+        self.client = DynamoDbClient::from_conf(self.client.config().to_builder().interceptor(interceptor).build());
+        self
+    }
+
+    /// Has every write to a table with local secondary indexes report the
+    /// size of the item collection it wrote to, and hands it to `observer`,
+    /// to watch partitions grow toward the 10 GB limit. Writes that would
+    /// exceed it fail with an
+    /// [`ITEM_COLLECTION_LIMIT_EXCEEDED`](crate::ITEM_COLLECTION_LIMIT_EXCEEDED)
+    /// error either way.
+    pub fn with_item_collection_metrics(mut self, observer: ItemCollectionObserver) -> Self {
+        let interceptor = ItemCollectionInterceptor(observer);
+        self.client = DynamoDbClient::from_conf(self.client.config().to_builder().interceptor(interceptor).build());
+        self
+    }
+
+    /// Has every call report the capacity it consumed and keeps running
+    /// totals of it, read with `capacity_stats`, for a lifetime view of the
+    /// pool's cost without a metrics backend.
+    pub fn with_capacity_stats(mut self) -> Self {
+        let totals = Arc::new(CapacityTotals::default());
+        let interceptor = CapacityInterceptor(totals.clone());
+        self.client = DynamoDbClient::from_conf(self.client.config().to_builder().interceptor(interceptor).build());
+        self.config.capacity_totals = Some(totals);
+        self
+    }
+
+    /// Prepends `prefix` to every table name passed to the pool, e.g. to keep
+    /// the tables of several environments apart in one account.
+    pub fn with_table_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.table_prefix = prefix.into();
+        self
+    }
+
+    /// Returns a handle to the same pool that uses table names as given,
+    /// bypassing the table prefix, for one-off calls such as migrations
+    /// between environments. It shares the pool's client and concurrency
+    /// limit, so it is cheap to create per call, as in
+    /// `pool.unprefixed().count("legacy_sessions")`.
+    pub fn unprefixed(&self) -> Self {
+        self.clone().with_table_prefix("")
+    }
+
+    /// The name of the DynamoDB table behind `table_name`.
+    pub(crate) fn full_table_name(&self, table_name: &str) -> String {
+        format!("{}{table_name}", self.config.table_prefix)
+    }
+
+    /// Checks the name of the DynamoDB table behind `table_name` against
+    /// DynamoDB's naming rules, which the SDK would otherwise report as a
+    /// bare validation error.
+    fn validate_table_name(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let full_name = self.full_table_name(table_name);
+
+        if !(MIN_TABLE_NAME_LEN..=MAX_TABLE_NAME_LEN).contains(&full_name.len()) {
+            return Err(DatabaseError::GenericCreateError(format!(
+                "table name {full_name:?} must be {MIN_TABLE_NAME_LEN} to {MAX_TABLE_NAME_LEN} characters long"
+            )));
+        }
+
+        if let Some(c) = full_name
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '_' | '-' | '.'))
+        {
+            return Err(DatabaseError::GenericCreateError(format!(
+                "table name {full_name:?} contains {c:?}; only a-z, A-Z, 0-9, '_', '-' and '.' are allowed"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Makes `delete_all` and `delete_all_stale` fail without deleting
+    /// anything when they would delete more than `max` sessions, as a rail
+    /// against accidental mass deletion. `delete_all_forced` bypasses it.
+    pub fn with_max_delete_guard(mut self, max: usize) -> Self {
+        self.config.max_delete_guard = Some(max);
+        self
+    }
+
+    /// Allows `drop_table`, which fails by default so a pool can't delete
+    /// a table, sessions and all, by accident.
+    pub fn with_allow_drop_table(mut self, allow: bool) -> Self {
+        self.config.allow_drop_table = allow;
+        self
+    }
+
+    /// Tags every stored session with the version of its payload format,
+    /// which `load_versioned` reports, to tell old payloads from new ones
+    /// during a rolling deploy. Sessions tagged with a version newer than
+    /// `version` fail to load instead of reaching deserialization.
+    pub fn with_payload_version(mut self, version: u8) -> Self {
+        self.config.payload_version = Some(version);
+        self
+    }
+
+    /// Lets `load` keep returning a session for up to `seconds` after it
+    /// expired, e.g. for audit reads of a just-expired session. Expired
+    /// sessions aren't returned at all by default. TTL may still remove a
+    /// session within the grace window.
+    pub fn with_load_grace_seconds(mut self, seconds: i64) -> Self {
+        self.config.load_grace_seconds = seconds;
+        self
+    }
+
+    /// Makes `store` update sessions in place with `upsert` instead of
+    /// replacing the whole item, so attributes written alongside the
+    /// session, e.g. by `store_with_context` or `store_for_user`, survive
+    /// later stores. Recommended whenever such attributes are used.
+    pub fn with_upsert(mut self, upsert: bool) -> Self {
+        self.config.upsert = upsert;
+        self
+    }
+
+    /// Selects whether `store` may replace an existing session. Note that
+    /// axum_session stores a session again whenever it changes, so
+    /// `StoreMode::RejectExisting` only suits apps that write each session
+    /// once.
+    pub fn with_store_mode(mut self, mode: StoreMode) -> Self {
+        self.config.store_mode = mode;
+        self
+    }
+
+    /// Records when each session was first stored in a `created_at`
+    /// attribute, which `delete_created_before` filters on. Later stores
+    /// keep the original time, so this implies `with_upsert(true)`.
+    pub fn with_created_at(mut self, created_at: bool) -> Self {
+        self.config.created_at = created_at;
+        self
+    }
+
+    /// Makes `delete_one_by_id` and `delete_all` succeed without doing
+    /// anything when the table doesn't exist, e.g. in cleanup that may run
+    /// after the table was dropped. Such calls fail by default.
+    pub fn with_ignore_missing_table_on_delete(mut self, ignore: bool) -> Self {
+        self.config.ignore_missing_table_on_delete = ignore;
+        self
+    }
+
+    /// Makes `delete_one_by_id` keep the session's item for `retention`,
+    /// e.g. to audit recent logouts: it records the time in a `deleted_at`
+    /// attribute and lets TTL remove the item once `retention` is over.
+    /// `load`, `exists` and `validate` treat soft deleted sessions as
+    /// missing, while scans such as `count` still see them until then.
+    pub fn with_soft_delete(mut self, retention: Duration) -> Self {
+        self.config.soft_delete = Some(retention);
+        self
+    }
+
+    /// Makes `delete_one_by_id` first record the deletion in `table_name`,
+    /// with the session's id, the `deleted_at` time and the reason given to
+    /// `delete_with_reason`, for `recent_deletions` to list until TTL
+    /// removes the record after `retention`. A delete fails, leaving the
+    /// session in place, if its record can't be written.
+    ///
+    /// Records are keyed like sessions, so `table_name` should be a
+    /// dedicated table, set up with `initiate`, and can serve several
+    /// session tables.
+    pub fn with_deletion_log(mut self, table_name: impl Into<String>, retention: Duration) -> Self {
+        self.config.deletion_log = Some((table_name.into(), retention));
+        self
+    }
+
+    /// Makes `count` return 0 when the table doesn't exist, e.g. for apps
+    /// polling it while the table is still being set up. By default such a
+    /// count fails with a [`TABLE_NOT_FOUND`](crate::TABLE_NOT_FOUND) error.
+    pub fn with_treat_missing_as_empty(mut self, treat_missing_as_empty: bool) -> Self {
+        self.config.treat_missing_as_empty = treat_missing_as_empty;
+        self
+    }
+
+    /// Records every `store`, `delete_one_by_id` and `expire_now` in
+    /// `table_name`, an append-only audit log of session lifecycles. Each
+    /// record holds the session `id`, the `at` time in microseconds and the
+    /// `event`: `store`, `delete` or `expire`.
+    ///
+    /// The table isn't created by `initiate`; it needs a String partition
+    /// key `id` and a Number sort key `at`, and usually no TTL.
+    pub fn with_audit_table(mut self, table_name: impl Into<String>, mode: AuditMode) -> Self {
+        self.config.audit_table = Some(table_name.into());
+        self.config.audit_mode = mode;
+        self
+    }
+
+    /// Keeps running counts of created and deleted sessions in an extra
+    /// `__stats__` item of each table, read with `activity_summary`, for
+    /// lightweight analytics. Each counted store or delete costs one more
+    /// write. Scans over the sessions skip the item.
+    ///
+    /// Creates are told apart from overwrites by the write's return values,
+    /// so they aren't counted for writes under `AuditMode::Transactional`.
+    /// A batched write can't return them, so `store` doesn't batch while
+    /// the summary is kept.
+    /// Needs ids that can hold `"__stats__"`, i.e. String ids.
+    pub fn with_activity_summary(mut self, enabled: bool) -> Self {
+        self.config.activity_summary = enabled;
+        self
+    }
+
+    /// Sets how many times items and keys a batch request left unprocessed,
+    /// typically under throttling, are resubmitted. Batched writes fail once
+    /// the retries run out, while batched reads such as `load_many` report
+    /// the keys they couldn't read. Resubmissions back off from 100ms,
+    /// doubling up to 5 seconds between attempts. Defaults to 8.
+    pub fn with_max_batch_retries(mut self, retries: u32) -> Self {
+        self.config.max_batch_retries = retries;
+        self
+    }
+
+    /// Sets whether `load_many` fails when keys are still unprocessed once
+    /// `max_batch_retries` ran out, rather than reporting them next to the
+    /// sessions it read. Defaults to `BatchFailureMode::Lenient`.
+    pub fn with_batch_failure_mode(mut self, mode: BatchFailureMode) -> Self {
+        self.config.batch_failure_mode = mode;
+        self
+    }
+
+    /// Splits the scan behind `sessions_stream` into `segments` parallel
+    /// segments, for faster exports of large tables. Sessions then arrive
+    /// in no particular order. `delete_all` and its variants scan this way
+    /// too, unless `with_max_delete_guard` has them count matches first.
+    /// Values below 1 are treated as 1.
+    pub fn with_scan_segments(mut self, segments: i32) -> Self {
+        self.config.scan_segments = segments.max(1);
+        self
+    }
+
+    /// Lets `delete_all` and its variants keep up to `workers` batch
+    /// deletes in flight, to truncate large tables faster. Like the scan
+    /// feeding them, they run under the call's single permit rather than
+    /// one each. Values below 1 are treated as 1, the default.
+    pub fn with_delete_workers(mut self, workers: usize) -> Self {
+        self.config.delete_workers = workers.max(1);
+        self
+    }
+
+    /// Spreads the sessions written with `store_sharded` over `shards`
+    /// partition key values, each write picking one at random, so a hot
+    /// session doesn't concentrate its writes on one partition.
+    /// `load_sharded` reads all `shards` to find the newest write, so
+    /// every load costs that many reads. Values below 1 are treated as 1.
+    pub fn with_write_shards(mut self, shards: u8) -> Self {
+        self.config.write_shards = Some(shards.max(1));
+        self
+    }
+
+    /// Stops scans that read more than `max` items, such as those behind
+    /// `count`, `get_ids` and the listing methods, with a
+    /// [`SCAN_LIMIT_EXCEEDED`](crate::SCAN_LIMIT_EXCEEDED) error, so a table
+    /// that grew far past expectations can't keep a call scanning for hours.
+    /// Items are counted before filters apply, and across all segments of a
+    /// scan under `with_scan_segments`. Unlimited by default.
+    pub fn with_max_scanned_items(mut self, max: usize) -> Self {
+        self.config.max_scanned_items = Some(max);
+        self
+    }
+
+    /// Buffers `store` calls for up to `window`, or until 25 are waiting,
+    /// and writes them with a single `BatchWriteItem`, trading latency for
+    /// fewer requests under high write rates. Each `store` returns once its
+    /// session is written, or with the batch's error. Stores still waiting
+    /// are flushed when the last clone of the pool is dropped.
+    ///
+    /// Batched writes are plain puts, so they bypass the pinned session
+    /// check; `store` writes directly when `with_upsert`, `with_created_at`,
+    /// `StoreMode::RejectExisting`, an audit table or `with_activity_summary`
+    /// is configured.
+    #[cfg(feature = "batching")]
+    pub fn with_write_batching(mut self, window: Duration) -> Self {
+        self.config.write_batching = Some(crate::batcher::WriteBatcher::new(window));
+        self
+    }
+
+    /// Coalesces concurrent `load` calls for the same id, on this pool and
+    /// its clones, into a single read whose result they all receive, e.g.
+    /// to spare capacity when many requests of one session arrive at once.
+    /// Only loads overlapping in time are coalesced: nothing is cached once
+    /// the read completes. Off by default.
+    pub fn with_load_coalescing(mut self, enabled: bool) -> Self {
+        self.config.load_coalescing = enabled.then(Default::default);
+        self
+    }
+
+    /// Limits how many DynamoDB requests the pool (and its clones) may have
+    /// in flight at once. Further calls wait for a free slot, smoothing
+    /// bursts into the table's provisioned capacity.
+    pub fn with_max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.semaphore = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// Selects how session items are keyed. Under `KeyLayout::Composite`,
+    /// sessions are stored under a constant partition key value with the id
+    /// as the sort key, so the table can be shared in a single-table design.
+    pub fn with_key_layout(mut self, layout: KeyLayout) -> Self {
+        self.config.key_layout = layout;
+        self
+    }
+
+    /// Stores a salted SHA-256 of each session id instead of the id itself,
+    /// for deployments that must not keep raw ids at rest. Ids are hashed
+    /// before every call, so `load`, `exists` and `delete_one_by_id` work
+    /// unchanged, but `get_ids` and the listing methods return the hashes.
+    ///
+    /// Hashes are stored as Strings whatever the key type. Changing the salt
+    /// orphans every existing session.
+    pub fn with_id_hashing(mut self, salt: impl Into<String>) -> Self {
+        self.config.id_salt = Some(salt.into());
+        self
+    }
+
+    /// Prepends `prefix` to every stored session id, e.g. `SESSION#`, so
+    /// sessions share a key space with other entity types in a single-table
+    /// design. The prefix is stripped from the ids the pool returns, and
+    /// scans skip items without it.
+    ///
+    /// Prefixed ids are stored as Strings whatever the key type.
+    pub fn with_key_value_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.config.key_value_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Maintains a global secondary index, named `index_name`, with every
+    /// session under one constant partition value and sorted by `expires`,
+    /// which `ids_by_expiry` and `count_active_via_index` query. `initiate`
+    /// creates the index along with the table; existing tables need it added
+    /// by hand.
+    ///
+    /// Every session write is also written to the index, doubling write
+    /// costs, and all sessions share the index's single partition, so its
+    /// write throughput is bounded by one partition's.
+    pub fn with_expiry_index(mut self, index_name: impl Into<String>) -> Self {
+        self.config.expiry_index = Some(index_name.into());
+        self
+    }
+
+    /// Maintains a global secondary index, named `index_name`, on the
+    /// `user_id` attribute written by `store_for_user`, which
+    /// `count_for_user` queries. `initiate` creates the index along with the
+    /// table; existing tables need it added by hand.
+    pub fn with_user_index(mut self, index_name: impl Into<String>) -> Self {
+        self.config.user_index = Some(index_name.into());
+        self
+    }
+
+    /// Selects whether unset optional attributes are left out of stored
+    /// items (the default) or stored as NULL, which matters to filters and
+    /// tools reading the table. The pool reads both forms. Index keys such as
+    /// `user_id` are always left out, as DynamoDB rejects NULL index keys.
+    /// A filter matching an unset attribute under either setting is
+    /// `attribute_not_exists(#name) OR attribute_type(#name, :null)`.
+    pub fn with_missing_attributes(mut self, missing: MissingAttributes) -> Self {
+        self.config.missing_attributes = missing;
+        self
+    }
+
+    /// Makes `exists` also read the `attribute` marker, e.g. one set by
+    /// `try_set_flag` once a session is confirmed, and report only sessions
+    /// carrying it, unless it is NULL or `false`. The marker is read in the
+    /// same projected request, at no extra cost.
+    pub fn with_exists_marker(mut self, attribute: impl Into<String>) -> Self {
+        self.config.exists_marker = Some(attribute.into());
+        self
+    }
+
+    /// Registers a global secondary index, named `index_name` and keyed by
+    /// `key_attributes` (partition key first, then any sort key), for
+    /// `query_index`. The index is managed outside the pool, like any
+    /// attribute it is keyed by.
+    pub fn with_query_index(mut self, index_name: impl Into<String>, key_attributes: &[&str]) -> Self {
+        self.config.query_indexes.insert(
+            index_name.into(),
+            key_attributes.iter().map(|attribute| attribute.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Controls whether `initiate` creates the `with_user_index` index along
+    /// with the table (the default). Turn it off when the index is managed
+    /// elsewhere, e.g. by infrastructure as code; per-user queries still use it.
+    pub fn with_create_user_index(mut self, enabled: bool) -> Self {
+        self.config.create_user_index = enabled;
+        self
+    }
+
+    /// Makes `initiate` add the indexes it would create with a new table,
+    /// from `with_expiry_index` and `with_user_index`, to an existing table
+    /// that lacks them, waiting until they finish backfilling. Off by
+    /// default, as tables managed by infrastructure-as-code tools must not
+    /// change behind their back.
+    pub fn with_add_missing_indexes(mut self, enabled: bool) -> Self {
+        self.config.add_missing_indexes = enabled;
+        self
+    }
+
+    /// Also writes each session's expiry as an RFC 3339 string in an
+    /// `expires_iso` attribute, for people and tools reading the table
+    /// directly. The pool itself only ever uses the numeric `expires`.
+    pub fn with_expires_iso(mut self, enabled: bool) -> Self {
+        self.config.expires_iso = enabled;
+        self
+    }
+
+    /// Records when each session was last written, by `store`, `upsert` or
+    /// `touch`, as a unix timestamp in a `last_modified` attribute, for
+    /// `ids_modified_since`. Off by default.
+    pub fn with_last_modified(mut self, enabled: bool) -> Self {
+        self.config.last_modified = enabled;
+        self
+    }
+
+    /// Makes `touch` only ever extend a session's expiry: a touch that would
+    /// move it earlier, e.g. a short sliding expiration on a long "remember
+    /// me" session, leaves it as it is and returns `false`. Off by default.
+    pub fn with_monotonic_touch(mut self, enabled: bool) -> Self {
+        self.config.monotonic_touch = enabled;
+        self
+    }
+
+    /// Makes `initiate` check TTL on a table that already exists and enable
+    /// it on `expires` if it is disabled, so sessions in tables created
+    /// elsewhere expire too. Only applies while TTL is managed.
+    pub fn with_ensure_ttl(mut self, enabled: bool) -> Self {
+        self.config.ensure_ttl = enabled;
+        self
+    }
+
+    /// Bounds how long `initiate` keeps describing an existing table whose
+    /// description is still incomplete, as can happen right after another
+    /// process created it, before reporting a key schema mismatch. Three
+    /// seconds by default.
+    pub fn with_schema_check_timeout(mut self, timeout: Duration) -> Self {
+        self.config.schema_check_timeout = timeout;
+        self
+    }
+
+    /// Makes `initiate` fail on an existing table that isn't encrypted as
+    /// `expected`, before any session is stored in it. Tables `initiate`
+    /// creates get DynamoDB's default encryption and aren't checked.
+    pub fn with_expected_sse(mut self, expected: ExpectedSse) -> Self {
+        self.config.expected_sse = Some(expected);
+        self
+    }
+
+    /// Bounds how long a call waits for a free slot under
+    /// `with_max_concurrent_requests` before failing. Waits indefinitely by default.
+    pub fn with_permit_timeout(mut self, timeout: Duration) -> Self {
+        self.config.permit_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds each attempt of every DynamoDB call other than scans and
+    /// queries, such as point reads and writes, batches and transactions.
+    /// An attempt that runs out fails as a timeout, which `RetryPolicy`
+    /// retries. Unbounded by default, leaving it to the SDK's own timeouts.
+    pub fn with_operation_timeout(mut self, timeout: Duration) -> Self {
+        self.config.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// Like `with_operation_timeout` for each scan or query page, which can
+    /// legitimately take far longer than a point read.
+    pub fn with_scan_timeout(mut self, timeout: Duration) -> Self {
+        self.config.scan_timeout = Some(timeout);
+        self
+    }
+
+    /// Controls whether the pool manages DynamoDB TTL on the `expires`
+    /// attribute, see `with_expires_attribute` (the default): `initiate` enables TTL on the tables it
+    /// creates and `store` rejects expiries that aren't plausible unix
+    /// timestamps in seconds, such as milliseconds passed by mistake.
+    pub fn with_manage_ttl(mut self, enabled: bool) -> Self {
+        self.config.manage_ttl = enabled;
+        self
+    }
+
+    /// Keeps session expiries in the `name` attribute rather than `expires`,
+    /// e.g. for a table whose TTL is on another attribute: every read, write
+    /// and scan filter of the pool uses it, as do the expiry sorted indexes
+    /// and the TTL `initiate` enables. The docs keep calling it `expires`.
+    pub fn with_expires_attribute(mut self, name: impl Into<String>) -> Self {
+        self.config.expires_attribute = name.into();
+        self
+    }
+
+    /// Checks, in release builds too, that every session item the pool
+    /// writes while TTL is managed carries a valid numeric `expires`, unless
+    /// it is pinned. A write that would leave a session to live forever
+    /// then fails with an error `is_missing_expires` recognizes instead of
+    /// being sent. Debug builds always check.
+    pub fn with_strict_expires_check(mut self, enabled: bool) -> Self {
+        self.config.strict_expires_check = enabled;
+        self
+    }
+
+    /// Retries transient failures (throttling, timeouts, dispatch failures)
+    /// per the policy, on top of the SDK's own retries.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry_policy = policy;
+        self
+    }
+
+    /// Retries reads per `policy` instead of `with_retry_policy`. Reads are
+    /// `GetItem`, `BatchGetItem`, `Query`, `Scan`, `TransactGetItems` and
+    /// the table descriptions, which are safe to retry freely.
+    pub fn with_read_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.read_retry_policy = Some(policy);
+        self
+    }
+
+    /// Retries every other call, the writes, per `policy` instead of
+    /// `with_retry_policy`. A retried write may apply twice, or fail its
+    /// condition after the first attempt did apply, so writes usually
+    /// warrant a more cautious policy than reads.
+    pub fn with_write_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.write_retry_policy = Some(policy);
+        self
+    }
+
+    /// Treats errors the classifier returns `true` for as retryable, in
+    /// addition to the built-in cases, e.g. an account specific error code.
+    /// Retries still only happen within the configured retry policy.
+    ///
+    /// The classifier gets the error's `ErrorMetadata`, its code and
+    /// message, rather than the `SdkError`: that is generic over each
+    /// operation's error type, which a single `fn` can't be. Timeouts and
+    /// dispatch failures carry no code and are always retried.
+    pub fn with_retry_classifier(mut self, classifier: RetryClassifier) -> Self {
+        self.config.retry_classifier = Some(classifier);
+        self
+    }
+
+    /// Mints the ids of the sessions `create` stores with `generator`
+    /// instead of as random UUIDs, e.g. for `i64` keys, which UUIDs don't fit.
+    pub fn with_id_generator(mut self, generator: IdGenerator) -> Self {
+        self.config.id_generator = generator;
+        self
+    }
+
+    /// Sets the capacity mode of the table created by `initiate`.
+    pub fn with_capacity(mut self, capacity: Capacity) -> Self {
+        self.config.capacity = capacity;
+        self
+    }
+
+    /// Gives the index `index_name`, one of those set up with
+    /// `with_user_index` or `with_expiry_index`, its own read and write
+    /// capacity when `initiate` creates it under `Capacity::Provisioned`.
+    /// Indexes default to the table's capacity; on-demand tables ignore this.
+    pub fn with_index_capacity(mut self, index_name: impl Into<String>, read: i64, write: i64) -> Self {
+        self.config.index_capacity.insert(index_name.into(), (read, write));
+        self
+    }
+
+    /// Registers Application Auto Scaling targets and target-tracking
+    /// policies once `initiate` has created a provisioned table.
+    /// Ignored for on-demand tables.
+    #[cfg(feature = "autoscaling")]
+    pub fn with_auto_scaling(mut self, auto_scaling: crate::AutoScaling) -> Self {
+        self.config.auto_scaling = Some(auto_scaling);
+        self
+    }
+
+    /// Waits for a request slot when the concurrency limit is enabled. The
+    /// returned permit must be held for the duration of the DynamoDB call.
+    pub(crate) async fn permit(&self) -> Result<Option<OwnedSemaphorePermit>, DatabaseError> {
+        let Some(semaphore) = &self.semaphore else {
+            return Ok(None);
+        };
+
+        let acquire = semaphore.clone().acquire_owned();
+        let permit = match self.config.permit_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, acquire)
+                .await
+                .map_err(|_| DatabaseError::GenericAquire("timed out waiting for a request slot".into()))?,
+            None => acquire.await,
+        };
+
+        permit
+            .map(Some)
+            .map_err(|e| DatabaseError::GenericAquire(e.to_string()))
+    }
+
+    /// Stores a SHA-256 of every session payload in a `checksum` attribute
+    /// and verifies it on `load`, so truncated or corrupted sessions are
+    /// reported as an error instead of being handed to deserialization.
+    ///
+    /// Items written without a checksum still load when this is enabled.
+    pub fn with_checksum(mut self, enabled: bool) -> Self {
+        self.config.checksum = enabled;
+        self
+    }
+
+    /// Stores JSON session payloads as a native DynamoDB map instead of an
+    /// opaque string, so filter expressions can reach into session contents.
+    ///
+    /// Payloads that aren't a JSON object are still stored as a string.
+    pub fn with_session_as_map(mut self, enabled: bool) -> Self {
+        self.config.session_as_map = enabled;
+        self
+    }
+
+    /// Sets how payloads are encoded in the String `session` attribute, e.g.
+    /// gzip followed by base64 to shrink large sessions while keeping the
+    /// attribute a String. Payloads written by any codec load regardless of
+    /// this setting.
+    pub fn with_string_codec(mut self, codec: StringCodec) -> Self {
+        self.config.string_codec = codec;
+        self
+    }
+
+    /// Leaves payloads of up to `bytes` uncompressed under a compressing
+    /// `with_string_codec`, as compressing small sessions costs CPU and can
+    /// grow them. Compressed payloads carry a marker, so both forms load.
+    /// Plain payloads that happen to start with the marker are always
+    /// compressed, so they can't be mistaken for compressed ones.
+    pub fn with_compression_threshold_bytes(mut self, bytes: usize) -> Self {
+        self.config.compression_threshold_bytes = bytes;
+        self
+    }
+
+    /// Picks the algorithm compressing payloads under
+    /// `StringCodec::GzipBase64` and `LargePayloadStrategy::Compress`. Each
+    /// payload's marker names its algorithm, so changing this leaves
+    /// existing sessions readable.
+    pub fn with_compression_algorithm(mut self, algorithm: CompressionAlgorithm) -> Self {
+        self.config.compression_algorithm = algorithm;
+        self
+    }
+
+    /// Encrypts String payloads client-side with AES-256-GCM under the
+    /// primary key of `keys`, after any compression. Payloads encrypted
+    /// under a retired key still load; `reencrypt_all` moves them to the
+    /// primary key. Map payloads (`with_session_as_map`) aren't encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, keys: crate::EncryptionKeys) -> Self {
+        self.config.encryption = Some(keys);
+        self
+    }
+
+    /// Applies `strategy` to String payloads that are larger than
+    /// `max_bytes` once encoded. Payloads that still don't fit are rejected
+    /// with a `GenericInsertError` instead of failing at DynamoDB's 400 KB
+    /// item limit. `load` reads back every form a strategy stores.
+    pub fn with_large_payload_strategy(mut self, max_bytes: usize, strategy: LargePayloadStrategy) -> Self {
+        self.config.large_payload = Some((max_bytes, strategy));
+        self
+    }
+
+    /// Adds a random offset of up to `seconds` to every stored `expires`, so a
+    /// burst of sessions created together doesn't expire in the same instant.
+    ///
+    /// Effective session lifetimes vary by up to the jitter window.
+    pub fn with_ttl_jitter_seconds(mut self, seconds: i64) -> Self {
+        self.config.ttl_jitter_seconds = seconds.max(0);
+        self
+    }
+
+    /// Sets the read consistency used by `load`, `exists` and `count`.
+    ///
+    /// Strongly consistent reads see every write acknowledged before them but
+    /// consume twice the read capacity units of eventually consistent ones.
+    /// The `*_with_consistency` methods override this for a single call.
+    pub fn with_default_read_consistency(mut self, consistency: ReadConsistency) -> Self {
+        self.config.default_read_consistency = consistency;
+        self
+    }
+
+    /// Makes a strongly consistent `load` or `exists` that is still
+    /// throttled once its retries are exhausted try once more with an
+    /// eventually consistent read, which costs half as much, instead of
+    /// failing. Such a read may miss writes from the last second or so, so
+    /// only enable this where a briefly stale session beats an error.
+    pub fn with_consistency_fallback(mut self, enabled: bool) -> Self {
+        self.config.consistency_fallback = enabled;
+        self
+    }
+
+    /// Makes an eventually consistent `load` that finds no session try
+    /// again up to `retries` times, `delay` apart, the last time with a
+    /// strongly consistent read, so a load right after the store isn't
+    /// lost to replication lag. Loads of ids that really have no session
+    /// take that much longer, up to `retries * delay`.
+    pub fn with_read_after_write_retry(mut self, retries: u32, delay: Duration) -> Self {
+        self.config.read_after_write_retry = Some((retries, delay)).filter(|(retries, _)| *retries > 0);
+        self
+    }
+
+    /// Loads a session using the given read consistency instead of the pool's default.
+    pub async fn load_with_consistency(
+        &self,
+        id: &str,
+        consistency: ReadConsistency,
+        table_name: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        let mut item = self.live_item(id, consistency, table_name).await?;
+
+        if let (None, false, Some((retries, delay))) =
+            (&item, consistency.is_strong(), self.config.read_after_write_retry)
+        {
+            for retry in 1..=retries {
+                tokio::time::sleep(delay).await;
+
+                let consistency = if retry == retries { ReadConsistency::Strong } else { consistency };
+                item = self.live_item(id, consistency, table_name).await?;
+                if item.is_some() {
+                    break;
+                }
+            }
+        }
+
+        match item {
+            Some(item) => self.decode_session(id, &item),
+            None => Ok(None),
+        }
+    }
+
+    /// Loads a session along with the payload version it was stored with
+    /// under `with_payload_version`, so callers can migrate older payloads.
+    /// Sessions stored without a version report version 0.
+    pub async fn load_versioned(&self, id: &str, table_name: &str) -> Result<Option<(u8, String)>, DatabaseError> {
+        let Some(item) = self
+            .live_item(id, self.config.default_read_consistency, table_name)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let version = payload_version(&item);
+        Ok(self.decode_session(id, &item)?.map(|session| (version, session)))
+    }
+
+    /// Loads a session and tells apart why it can't be used, for middleware
+    /// that treats each case differently. The checksum is verified when
+    /// `with_checksum` is enabled. Other failures, such as a payload
+    /// encrypted with an unknown key, are still returned as errors.
+    pub async fn validate(&self, id: &str, table_name: &str) -> Result<SessionValidity, DatabaseError> {
+        let Some(item) = self
+            .get_item(id, self.config.default_read_consistency, table_name)
+            .await?
+        else {
+            return Ok(SessionValidity::Missing);
+        };
+
+        if is_soft_deleted(&item) {
+            return Ok(SessionValidity::Missing);
+        }
+
+        if self.is_expired_at(&item, unix_now() - self.config.load_grace_seconds) {
+            return Ok(SessionValidity::Expired);
+        }
+
+        match self.decode_session(id, &item) {
+            Ok(Some(session)) => Ok(SessionValidity::Valid(session)),
+            Ok(None) => Ok(SessionValidity::Missing),
+            Err(e) if error::is_checksum_mismatch(&e) => Ok(SessionValidity::CorruptChecksum),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a session's item unless it has expired.
+    pub(crate) async fn live_item(
+        &self,
+        id: &str,
+        consistency: ReadConsistency,
+        table_name: &str,
+    ) -> Result<Option<Item>, DatabaseError> {
+        let item = self.get_item(id, consistency, table_name).await?;
+        Ok(item.filter(|item| self.is_live(item)))
+    }
+
+    /// Whether a read item is a live session: not soft deleted, and not
+    /// expired more than `with_load_grace_seconds` ago. TTL deletes expired
+    /// items lazily, so they can still be read for a while.
+    pub(crate) fn is_live(&self, item: &Item) -> bool {
+        !self.is_expired_at(item, unix_now() - self.config.load_grace_seconds) && !is_soft_deleted(item)
+    }
+
+    /// Stores a session by updating only the attributes the pool manages,
+    /// creating the item if it doesn't exist yet. Unlike `store`, which
+    /// replaces the item, other attributes of an existing item are kept.
+    pub async fn upsert(&self, id: &str, session: &str, expires: i64, table_name: &str) -> Result<(), DatabaseError> {
+        let item = self.session_item(id, session, expires)?;
+        self.write_session(id, item, true, false, table_name).await?;
+        Ok(())
+    }
+
+    /// Writes an item built by `session_item`, as an update when `update`
+    /// is set and as a put otherwise. A session pinned with `pin` stays
+    /// pinned: its item is rewritten without an expiry. With `return_old`
+    /// set, returns the item the write replaced, if any.
+    ///
+    /// Under `StoreMode::RejectExisting` the write is conditional on no
+    /// session existing under the id, pinned or not.
+    async fn write_session(
+        &self,
+        id: &str,
+        item: Item,
+        update: bool,
+        return_old: bool,
+        table_name: &str,
+    ) -> Result<Option<Item>, DatabaseError> {
+        self.write_session_if(id, item, update, None, return_old, table_name).await
+    }
+
+    /// `write_session`, only writing when `condition` holds too. A failed
+    /// `condition` is reported as a
+    /// [`CONDITION_FAILED`](crate::CONDITION_FAILED) error, or as a
+    /// [`SESSION_EXISTS`](crate::SESSION_EXISTS) one under
+    /// `StoreMode::RejectExisting`.
+    pub(crate) async fn write_session_if(
+        &self,
+        id: &str,
+        item: Item,
+        update: bool,
+        condition: Option<Filter>,
+        return_old: bool,
+        table_name: &str,
+    ) -> Result<Option<Item>, DatabaseError> {
+        let with_condition = |filter: Filter| match condition.clone() {
+            Some(condition) => filter.and(condition),
+            None => filter,
+        };
+
+        if self.config.store_mode == StoreMode::RejectExisting {
+            let condition = with_condition(Filter::new("attribute_not_exists(#id)").name("#id", self.id_attribute()));
+
+            return match self.write_session_item(id, item, update, Some(condition), return_old, table_name).await {
+                Err(e) if error::is_condition_failed(&e) => Err(DatabaseError::GenericInsertError(format!(
+                    "{}: {id}{}",
+                    error::SESSION_EXISTS,
+                    error::current_item_suffix(&e)
+                ))),
+                result => result,
+            };
+        }
+
+        let unpinned = with_condition(Filter::new("attribute_not_exists(#pinned)").name("#pinned", PINNED_ATTRIBUTE));
+
+        // Failing `condition` fails the rewrite as a pinned session too.
+        match self.write_session_item(id, item.clone(), update, Some(unpinned), return_old, table_name).await {
+            Err(e) if error::is_condition_failed(&e) => {
+                self.write_session_item(id, self.pinned_item(item), update, condition, return_old, table_name).await
+            }
+            result => result,
+        }
+    }
+
+    /// Writes a session item, if given only when `condition` holds. With
+    /// `return_old` set, returns the item the write replaced, if any; a
+    /// transactionally audited write can't return it and fails instead.
+    async fn write_session_item(
+        &self,
+        id: &str,
+        item: Item,
+        update: bool,
+        condition: Option<Filter>,
+        return_old: bool,
+        table_name: &str,
+    ) -> Result<Option<Item>, DatabaseError> {
+        let (condition, condition_names, condition_values) = match condition {
+            Some(filter) => (
+                Some(filter.expression),
+                Some(filter.names),
+                (!filter.values.is_empty()).then_some(filter.values),
+            ),
+            None => (None, None, None),
+        };
+
+        self.check_expires(id, &item)?;
+
+        if return_old && self.audits_transactionally() {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "a transactionally audited store can't return the item it replaced".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let old = if update {
+            let request = self
+                .session_update(id, item, table_name)?
+                .set_condition_expression(condition)
+                .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld);
+            let request = condition_names.into_iter().flatten().fold(request, |request, (placeholder, name)| {
+                request.expression_attribute_names(placeholder, name)
+            });
+            let request = condition_values.into_iter().flatten().fold(request, |request, (placeholder, value)| {
+                request.expression_attribute_values(placeholder, value)
+            });
+
+            if self.audits_transactionally() {
+                let write = audit::update_write(&request);
+                return self
+                    .transact_audited(write, id, "store", DatabaseError::GenericInsertError)
+                    .await
+                    .map(|()| None);
+            }
+
+            // Only attributes that were already there come back, so a new
+            // item comes back with none.
+            let request = match (return_old, self.config.activity_summary) {
+                (true, _) => request.return_values(ReturnValue::AllOld),
+                (false, true) => request.return_values(ReturnValue::UpdatedOld),
+                (false, false) => request,
+            };
+
+            self.send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_conditional_error(e, DatabaseError::GenericInsertError))?
+                .attributes
+        } else {
+            let request = self
+                .client
+                .put_item()
+                .table_name(self.full_table_name(table_name))
+                .set_item(Some(item))
+                .set_condition_expression(condition)
+                .set_expression_attribute_names(condition_names)
+                .set_expression_attribute_values(condition_values)
+                .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld);
+
+            if self.audits_transactionally() {
+                let write = audit::put_write(&request);
+                return self
+                    .transact_audited(write, id, "store", DatabaseError::GenericInsertError)
+                    .await
+                    .map(|()| None);
+            }
+
+            let request = match return_old || self.config.activity_summary {
+                true => request.return_values(ReturnValue::AllOld),
+                false => request,
+            };
+
+            self.send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_conditional_error(e, DatabaseError::GenericInsertError))?
+                .attributes
+        };
+
+        if old.is_none() {
+            self.record_activity("creates", table_name).await;
+        }
+
+        self.audit(id, "store").await;
+        Ok(old)
+    }
+
+    /// Whether `name` is an attribute the pool manages itself, which callers
+    /// can't write as a custom attribute: one of the session's `key`, the
+    /// payload, the expiry, the pinned marker, `created_at`, or one of the
+    /// attributes `session_item` writes depending on the options.
+    fn is_reserved_attribute(&self, key: &Item, name: &str) -> bool {
+        key.contains_key(name)
+            || ["session", self.expires_attribute(), PINNED_ATTRIBUTE, CREATED_ATTRIBUTE].contains(&name)
+            || MANAGED_ATTRIBUTES.contains(&name)
+    }
+
+    /// An `update_item` request writing the attributes of a session item,
+    /// and removing managed attributes the item no longer has.
+    fn session_update(&self, id: &str, mut item: Item, table_name: &str) -> Result<UpdateItemFluentBuilder, DatabaseError> {
+        let key = self.key(id)?;
+        item.retain(|name, _| !key.contains_key(name));
+
+        // Drop managed attributes the current options no longer write, such
+        // as a checksum that would no longer match the payload.
+        let stale: Vec<_> = MANAGED_ATTRIBUTES
+            .into_iter()
+            .chain([self.expires_attribute()])
+            .filter(|name| !item.contains_key(*name))
+            .collect();
+
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(key));
+
+        let mut set = Vec::new();
+        for (i, (name, value)) in item.into_iter().enumerate() {
+            set.push(format!("#set{i} = :set{i}"));
+            request = request
+                .expression_attribute_names(format!("#set{i}"), name)
+                .expression_attribute_values(format!(":set{i}"), value);
+        }
+
+        if self.config.created_at {
+            set.push("#created = if_not_exists(#created, :created)".to_string());
+            request = request
+                .expression_attribute_names("#created", CREATED_ATTRIBUTE)
+                .expression_attribute_values(":created", AttributeValue::N(unix_now().to_string()));
+        }
+
+        let mut remove = Vec::new();
+        for (i, name) in stale.into_iter().enumerate() {
+            remove.push(format!("#remove{i}"));
+            request = request.expression_attribute_names(format!("#remove{i}"), name);
+        }
+
+        let mut update = format!("SET {}", set.join(", "));
+        if !remove.is_empty() {
+            update.push_str(&format!(" REMOVE {}", remove.join(", ")));
+        }
+        Ok(request.update_expression(update))
+    }
+
+    /// Pins a session so it never expires, e.g. for a service account: its
+    /// `expires` is removed, so TTL never reaps it, and `store` keeps it
+    /// pinned. Returns `false` if there is no such session.
+    pub async fn pin(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .update_expression("SET #pinned = :pinned REMOVE #expires, #expires_iso")
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#pinned", PINNED_ATTRIBUTE)
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_names("#expires_iso", "expires_iso")
+            .expression_attribute_values(":pinned", AttributeValue::Bool(true));
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Undoes `pin`, making the session expire at `expires` like any other.
+    /// Returns `false` if there is no such session.
+    pub async fn unpin(&self, id: &str, expires: i64, table_name: &str) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let mut set = "SET #expires = :expires".to_string();
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#pinned", PINNED_ATTRIBUTE)
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_values(":expires", AttributeValue::N(expires.to_string()));
+
+        if self.config.expires_iso {
+            if let Some(time) = DateTime::from_timestamp(expires, 0) {
+                set.push_str(", #expires_iso = :expires_iso");
+                request = request
+                    .expression_attribute_names("#expires_iso", "expires_iso")
+                    .expression_attribute_values(":expires_iso", AttributeValue::S(time.to_rfc3339()));
+            }
+        }
+
+        let request = request.update_expression(format!("{set} REMOVE #pinned"));
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Moves the expiry of a live session to `delta_seconds` from now, for
+    /// sliding expiration. Sessions that expired but haven't been reaped by
+    /// TTL yet are left dead, and pinned sessions keep never expiring: both
+    /// return `false`, like missing ones. So do soft deleted sessions, and
+    /// sessions that already expire later under `with_monotonic_touch`.
+    pub async fn touch(&self, id: &str, delta_seconds: i64, table_name: &str) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let now = unix_now();
+        let expires = now + delta_seconds;
+
+        let mut condition = "attribute_exists(#id) AND attribute_not_exists(#deleted) AND #expires > :now".to_string();
+        if self.config.monotonic_touch {
+            condition.push_str(" AND #expires < :expires");
+        }
+
+        let mut set = "SET #expires = :expires".to_string();
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .condition_expression(condition)
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#deleted", DELETED_ATTRIBUTE)
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .expression_attribute_values(":expires", AttributeValue::N(expires.to_string()));
+
+        if self.config.expires_iso {
+            if let Some(time) = DateTime::from_timestamp(expires, 0) {
+                set.push_str(", #expires_iso = :expires_iso");
+                request = request
+                    .expression_attribute_names("#expires_iso", "expires_iso")
+                    .expression_attribute_values(":expires_iso", AttributeValue::S(time.to_rfc3339()));
+            }
+        }
+
+        if self.config.last_modified {
+            set.push_str(", #modified = :now");
+            request = request.expression_attribute_names("#modified", LAST_MODIFIED_ATTRIBUTE);
+        }
+
+        let request = request.update_expression(set);
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores a session like `store`, along with custom attributes in the
+    /// same item. Attributes the pool manages itself, such as `id`,
+    /// `session`, `expires`, `pinned` or `deleted_at`, can't be overridden,
+    /// whether or not the pool's options currently write them.
+    pub async fn store_with_attributes(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        extra: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let mut item = self.session_item(id, session, expires)?;
+
+        let key = self.key(id)?;
+        if let Some(name) = extra.keys().find(|name| self.is_reserved_attribute(&key, name)) {
+            return Err(DatabaseError::GenericInsertError(format!(
+                "attribute {name} is reserved by the session store"
+            )));
+        }
+        item.extend(extra);
+
+        let update = self.config.upsert || self.config.created_at;
+        self.write_session(id, item, update, false, table_name).await?;
+        Ok(())
+    }
+
+    /// Stores a session like `store`, returning the payload it replaced, if
+    /// any. Not supported while audits are written transactionally, which
+    /// can't return the replaced item.
+    pub async fn store_returning_old(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        let item = self.session_item(id, session, expires)?;
+        let update = self.config.upsert || self.config.created_at;
+
+        match self.write_session(id, item, update, true, table_name).await? {
+            Some(old) => self.decode_session(id, &old),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores a new session under an id the pool mints, a random UUID
+    /// unless `with_id_generator` is set, and returns the id. The write
+    /// only succeeds if the id is unused, and a collision is retried with a
+    /// fresh id, up to three times.
+    pub async fn create(&self, session: &str, expires: i64, table_name: &str) -> Result<String, DatabaseError> {
+        let mut attempt = 1;
+
+        loop {
+            let id = (self.config.id_generator)();
+            let item = self.session_item(&id, session, expires)?;
+            let unused = Filter::new("attribute_not_exists(#id)").name("#id", self.id_attribute());
+
+            match self.write_session_item(&id, item, false, Some(unused), false, table_name).await {
+                Ok(_) => return Ok(id),
+                Err(e) if error::is_condition_failed(&e) && attempt < CREATE_ATTEMPTS => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Stores a session like `store`, but only if its current payload is
+    /// `expected_current`, or, for `None`, if there is no live session yet,
+    /// for optimistic updates of session contents without a lock. An
+    /// expired or soft-deleted session counts as absent and is overwritten.
+    /// Returns `false`, writing nothing, if the payload doesn't match.
+    ///
+    /// Stored payloads may be compressed or encrypted differently on every
+    /// write, so the current item is read strongly and compared once
+    /// decoded; the write then only applies if the stored payload hasn't
+    /// changed since. A pinned session stays pinned.
+    pub async fn store_if(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        expected_current: Option<String>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        let item = self.session_item(id, session, expires)?;
+
+        let (item, condition) = match expected_current {
+            // Anything `is_live` rejects counts as absent, and the put
+            // replaces it whole, soft deletion and pin included.
+            None => (
+                item,
+                Filter::new("attribute_not_exists(#id) OR #expires <= :now OR attribute_exists(#deleted)")
+                    .name("#id", self.id_attribute())
+                    .name("#expires", self.expires_attribute())
+                    .name("#deleted", DELETED_ATTRIBUTE)
+                    .value(":now", AttributeValue::N((unix_now() - self.config.load_grace_seconds).to_string())),
+            ),
+            Some(expected) => {
+                // Read before the write takes its permit, so a pool limited
+                // to one request at a time doesn't wait on itself.
+                let Some(current) = self.live_item(id, ReadConsistency::Strong, table_name).await? else {
+                    return Ok(false);
+                };
+                if self.decode_session(id, &current)?.as_ref() != Some(&expected) {
+                    return Ok(false);
+                }
+                let Some(stored) = current.get("session") else {
+                    return Ok(false);
+                };
+
+                let item = match current.contains_key(PINNED_ATTRIBUTE) {
+                    true => self.pinned_item(item),
+                    false => item,
+                };
+                let condition = Filter::new("attribute_exists(#id) AND #session = :stored")
+                    .name("#id", self.id_attribute())
+                    .name("#session", "session")
+                    .value(":stored", stored.clone());
+                (item, condition)
+            }
+        };
+
+        match self.write_session_item(id, item, false, Some(condition), false, table_name).await {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores a session whose payload is split in two: a small `header`
+    /// that `load_header` reads on its own, and the `body` that `load`
+    /// returns as the session.
+    pub async fn store_with_header(
+        &self,
+        id: &str,
+        header: &str,
+        body: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let extra = HashMap::from([(HEADER_ATTRIBUTE.to_string(), AttributeValue::S(header.into()))]);
+        self.store_with_attributes(id, body, expires, extra, table_name)
+            .await
+    }
+
+    /// Stores a session like `store`, recording the user it belongs to in a
+    /// `user_id` attribute for the index set up by `with_user_index`.
+    pub async fn store_for_user(
+        &self,
+        id: &str,
+        user_id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let extra = HashMap::from([(USER_ATTRIBUTE.to_string(), AttributeValue::S(user_id.into()))]);
+        self.store_with_attributes(id, session, expires, extra, table_name)
+            .await
+    }
+
+    /// Reads only the header written by `store_with_header`, leaving the
+    /// body out of the read to save capacity on large sessions.
+    pub async fn load_header(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .get_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .projection_expression("#header, #expires, #deleted")
+            .expression_attribute_names("#header", HEADER_ATTRIBUTE)
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_names("#deleted", DELETED_ATTRIBUTE)
+            .consistent_read(self.config.default_read_consistency.is_strong());
+
+        let output = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+        let Some(item) = output.item.filter(|item| self.is_live(item)) else {
+            return Ok(None);
+        };
+
+        Ok(item.get(HEADER_ATTRIBUTE).and_then(|h| h.as_s().ok()).cloned())
+    }
+
+    /// Returns the whole item stored for a session, exactly as DynamoDB
+    /// returns it, including `expires` and any custom attributes.
+    pub async fn load_raw(&self, id: &str, table_name: &str) -> Result<Option<Item>, DatabaseError> {
+        self.get_item(id, self.config.default_read_consistency, table_name)
+            .await
+    }
+
+    pub(crate) async fn get_item(
+        &self,
+        id: &str,
+        consistency: ReadConsistency,
+        table_name: &str,
+    ) -> Result<Option<Item>, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .get_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .consistent_read(consistency.is_strong());
+
+        Ok(self.send_get(request).await?.item)
+    }
+
+    /// Sends a `GetItem`, retrying a strongly consistent one that stayed
+    /// throttled as an eventually consistent read under
+    /// `with_consistency_fallback`.
+    async fn send_get(&self, request: GetItemFluentBuilder) -> Result<GetItemOutput, DatabaseError> {
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError));
+
+        match result {
+            Err(e)
+                if self.config.consistency_fallback
+                    && request.get_consistent_read() == &Some(true)
+                    && error::is_throttled(&e) =>
+            {
+                tracing::warn!("strongly consistent read throttled, falling back to an eventually consistent one");
+
+                let request = request.consistent_read(false);
+                self.send(|| request.clone().send())
+                    .await
+                    .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))
+            }
+            result => result,
+        }
+    }
+
+    /// Deletes every session like `delete_all`, regardless of
+    /// `with_max_delete_guard`. Returns the number of deleted sessions.
+    pub async fn delete_all_forced(&self, table_name: &str) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let deleted = self.scan_delete(table_name, None, true).await?;
+        Ok(deleted.len())
+    }
+
+    /// Deletes every session whose `expires` is before `before_unix`.
+    ///
+    /// Unlike `delete_all`, this is safe to run while serving traffic: pass
+    /// the sweep's start time plus the session lifetime to remove every
+    /// session that existed when the sweep started, while sessions created
+    /// during the sweep expire later and survive it. Returns the number of
+    /// deleted sessions.
+    pub async fn delete_all_stale(&self, before_unix: i64, table_name: &str) -> Result<usize, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let filter = Filter::new("#expires < :before")
+            .name("#expires", self.expires_attribute())
+            .value(":before", AttributeValue::N(before_unix.to_string()));
+        let deleted = self.scan_delete(table_name, Some(filter), false).await?;
+
+        Ok(deleted.len())
+    }
+
+    /// Deletes every session first stored before `cutoff_unix`, e.g. all
+    /// sessions issued before a password change, and returns their ids.
+    /// Requires `with_created_at`; sessions stored without it are kept.
+    pub async fn delete_created_before(&self, cutoff_unix: i64, table_name: &str) -> Result<Vec<String>, DatabaseError> {
+        if !self.config.created_at {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "delete_created_before requires with_created_at".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let filter = Filter::new("#created < :cutoff")
+            .name("#created", CREATED_ATTRIBUTE)
+            .value(":cutoff", AttributeValue::N(cutoff_unix.to_string()));
+        let deleted = self.scan_delete(table_name, Some(filter), false).await?;
+
+        Ok(deleted.iter().filter_map(|key| self.item_id(key)).collect())
+    }
+
+    /// Reads and deletes a session in one request, for one-time sessions
+    /// such as magic links: of several concurrent calls for the same id,
+    /// only one gets the payload. Returns `None` if there was no live
+    /// session, deleting an expired one that TTL hasn't removed yet.
+    ///
+    /// Always deletes the item, even under `with_soft_delete`. Transactions
+    /// can't return the deleted item, so this fails under
+    /// `AuditMode::Transactional`.
+    pub async fn consume(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        if self.audits_transactionally() {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "consume can't be audited transactionally".into(),
+            ));
+        }
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .delete_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .return_values(ReturnValue::AllOld);
+
+        let Some(item) = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericDeleteError))?
+            .attributes
+        else {
+            return Ok(None);
+        };
+
+        self.record_activity("deletes", table_name).await;
+        self.audit(id, "delete").await;
+
+        if is_soft_deleted(&item) || self.is_expired_at(&item, unix_now() - self.config.load_grace_seconds) {
+            return Ok(None);
+        }
+        self.decode_session(id, &item)
+    }
+
+    /// Marks a session as expired without deleting it, leaving the item for
+    /// TTL to reap. `load` treats it as gone immediately.
+    ///
+    /// Returns `false` if there was no such session.
+    pub async fn expire_now(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .update_expression("SET #expires = :past")
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_values(":past", AttributeValue::N((unix_now() - 1).to_string()));
+
+        let result = if self.audits_transactionally() {
+            let write = audit::update_write(&request);
+            self.transact_audited(write, id, "expire", DatabaseError::GenericInsertError).await
+        } else {
+            let result = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+            if result.is_ok() {
+                self.audit(id, "expire").await;
+            }
+            result.map(drop)
+        };
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Marks a session deleted and makes it expire after `retention`, in
+    /// place of deleting it. A pinned session stops being pinned. Fails
+    /// with a failed condition if there is no such session.
+    async fn soft_delete(&self, id: &str, retention: Duration, table_name: &str) -> Result<(), DatabaseError> {
+        let now = unix_now();
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .update_expression("SET #deleted = :now, #expires = :expires REMOVE #pinned")
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#expires", self.expires_attribute())
+            .expression_attribute_names("#deleted", DELETED_ATTRIBUTE)
+            .expression_attribute_names("#pinned", PINNED_ATTRIBUTE)
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .expression_attribute_values(
+                ":expires",
+                AttributeValue::N((now + retention.as_secs() as i64).to_string()),
+            );
+
+        if self.audits_transactionally() {
+            let write = audit::update_write(&request);
+            return self.transact_audited(write, id, "delete", DatabaseError::GenericDeleteError).await;
+        }
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericDeleteError))?;
+
+        self.record_activity("deletes", table_name).await;
+        self.audit(id, "delete").await;
+        Ok(())
+    }
+
+    /// Sets the boolean attribute `attribute` on a session unless it is
+    /// already set, e.g. to show onboarding only once. The check and the
+    /// write are one conditional update, so of several concurrent calls
+    /// exactly one returns `true`. Also returns `false` if there is no such
+    /// session. Like any extra attribute, the flag only survives later
+    /// stores under `with_upsert`.
+    pub async fn try_set_flag(&self, id: &str, attribute: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        let key = self.key(id)?;
+        if self.is_reserved_attribute(&key, attribute) {
+            return Err(DatabaseError::GenericInsertError(format!(
+                "attribute {attribute} is reserved by the session store"
+            )));
+        }
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .update_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(key))
+            .update_expression("SET #flag = :set")
+            .condition_expression("attribute_exists(#id) AND attribute_not_exists(#flag)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#flag", attribute)
+            .expression_attribute_values(":set", AttributeValue::Bool(true));
+
+        let result = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError));
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if error::is_condition_failed(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks whether a session exists using the given read consistency instead of the pool's default.
+    /// Under `with_exists_marker`, the session also has to carry the marker.
+    pub async fn exists_with_consistency(
+        &self,
+        id: &str,
+        consistency: ReadConsistency,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let mut request = self
+            .client
+            .get_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?))
+            .projection_expression("#id, #deleted")
+            .expression_attribute_names("#id", self.id_attribute())
+            .expression_attribute_names("#deleted", DELETED_ATTRIBUTE)
+            .consistent_read(consistency.is_strong());
+
+        if let Some(marker) = &self.config.exists_marker {
+            request = request
+                .projection_expression("#id, #deleted, #marker")
+                .expression_attribute_names("#marker", marker);
+        }
+
+        let res = self.send_get(request).await?;
+
+        Ok(match (res.item.filter(|item| !is_soft_deleted(item)), &self.config.exists_marker) {
+            (Some(item), Some(marker)) => {
+                !matches!(item.get(marker), None | Some(AttributeValue::Null(_) | AttributeValue::Bool(false)))
+            }
+            (item, _) => item.is_some(),
+        })
+    }
+
+    /// Returns `true` if the session exists and matches a caller supplied
+    /// filter expression, e.g. `#tenant = :tenant` to check that it belongs
+    /// to a tenant. The filter is evaluated by DynamoDB on a single item
+    /// query, so the session itself is not returned.
+    ///
+    /// Placeholders follow the rules of `count_filtered`; the `#key*` and
+    /// `:key*` names are taken by the key condition.
+    pub async fn exists_where(
+        &self,
+        id: &str,
+        filter_expression: &str,
+        names: HashMap<String, String>,
+        values: HashMap<String, AttributeValue>,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        let filter = Filter::checked(filter_expression, names, values)?;
+        let mut request = self
+            .client
+            .query()
+            .table_name(self.full_table_name(table_name))
+            .filter_expression(filter.expression)
+            .set_expression_attribute_names(Some(filter.names))
+            .set_expression_attribute_values(Some(filter.values))
+            .select(Select::Count)
+            .consistent_read(self.config.default_read_consistency.is_strong());
+
+        let mut conditions = Vec::new();
+        for (i, (name, value)) in self.key(id)?.into_iter().enumerate() {
+            conditions.push(format!("#key{i} = :key{i}"));
+            request = request
+                .expression_attribute_names(format!("#key{i}"), name)
+                .expression_attribute_values(format!(":key{i}"), value);
+        }
+        let request = request.key_condition_expression(conditions.join(" AND "));
+
+        let _permit = self.permit().await?;
+
+        let res = self
+            .send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericSelectError))?;
+
+        Ok(res.count > 0)
+    }
+
+    /// Counts sessions using the given read consistency instead of the pool's default.
+    pub async fn count_with_consistency(
+        &self,
+        consistency: ReadConsistency,
+        table_name: &str,
+    ) -> Result<i64, DatabaseError> {
+        let _permit = self.permit().await?;
+
+        let request = self
+            .scan_sessions(table_name)
+            .select(Select::Count)
+            .consistent_read(consistency.is_strong());
+
+        let mut count = 0;
+        match self.scan_pages(request, |page| count += i64::from(page.count)).await {
+            Ok(()) => Ok(count),
+            Err(e) if self.config.treat_missing_as_empty && error::is_table_not_found(&e) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Rejects a session item without a valid `expires` while TTL is
+    /// managed, in debug builds or under `with_strict_expires_check`.
+    pub(crate) fn check_expires(&self, id: &str, item: &Item) -> Result<(), DatabaseError> {
+        if !self.config.manage_ttl
+            || !(cfg!(debug_assertions) || self.config.strict_expires_check)
+            || item.contains_key(PINNED_ATTRIBUTE)
+        {
+            return Ok(());
+        }
+
+        let expires = item.get(self.expires_attribute()).and_then(|value| value.as_n().ok());
+        match expires.and_then(|expires| expires.parse::<i64>().ok()) {
+            Some(expires) if expires > 0 => Ok(()),
+            _ => Err(DatabaseError::GenericInsertError(format!(
+                "{}: session {id} would be stored without a valid expires while TTL is managed",
+                error::MISSING_EXPIRES
+            ))),
+        }
+    }
+
+    /// Deletes a session, recording `reason` under `with_deletion_log`.
+    pub(crate) async fn delete_session(
+        &self,
+        id: &str,
+        reason: Option<&str>,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let _permit = self.permit().await?;
+
+        self.log_deletion(id, reason, table_name).await?;
+
+        if let Some(retention) = self.config.soft_delete {
+            return match self.soft_delete(id, retention, table_name).await {
+                Ok(()) => Ok(()),
+                Err(e) if error::is_condition_failed(&e) => Ok(()),
+                Err(e) => self.ignoring_missing_table(e),
+            };
+        }
+
+        let request = self
+            .client
+            .delete_item()
+            .table_name(self.full_table_name(table_name))
+            .set_key(Some(self.key(id)?));
+
+        if self.audits_transactionally() {
+            let write = audit::delete_write(&request);
+            return match self.transact_audited(write, id, "delete", DatabaseError::GenericDeleteError).await {
+                Ok(()) => Ok(()),
+                Err(e) => self.ignoring_missing_table(e),
+            };
+        }
+
+        let request = match self.config.activity_summary {
+            true => request.return_values(ReturnValue::AllOld),
+            false => request,
+        };
+
+        let output = match self.send(|| request.clone().send()).await {
+            Ok(output) => output,
+            Err(e) => return self.ignoring_missing_table(error::map_sdk_error(e, DatabaseError::GenericDeleteError)),
+        };
+        if output.attributes.is_some() {
+            self.record_activity("deletes", table_name).await;
+        }
+
+        self.audit(id, "delete").await;
+        Ok(())
+    }
+
+    /// Name of the attribute holding a session's expiry, see
+    /// `with_expires_attribute`.
+    pub(crate) fn expires_attribute(&self) -> &str {
+        &self.config.expires_attribute
+    }
+
+    /// The expiry of an item as a unix timestamp, if it has one.
+    pub(crate) fn item_expires(&self, item: &Item) -> Option<i64> {
+        item.get(self.expires_attribute()).and_then(expires_value)
+    }
+
+    /// Returns `true` if the item's expiry is in the past.
+    ///
+    /// Items without an expiry, such as sessions meant never to expire, are
+    /// always live. Legacy items holding `expires` as a String are judged
+    /// by its value, like `migrate_expires_to_number` would store it.
+    pub(crate) fn is_expired(&self, item: &Item) -> bool {
+        self.is_expired_at(item, unix_now())
+    }
+
+    /// `is_expired` as of the unix timestamp `now`.
+    pub(crate) fn is_expired_at(&self, item: &Item, now: i64) -> bool {
+        self.item_expires(item).is_some_and(|expires| expires <= now)
+    }
+
+    /// Turns an item built by `session_item` into that of a pinned session,
+    /// dropping its expiry, see `pin`.
+    pub(crate) fn pinned_item(&self, mut item: Item) -> Item {
+        for attribute in [self.expires_attribute(), "expires_iso", EXPIRY_PARTITION_KEY] {
+            item.remove(attribute);
+        }
+        item.insert(PINNED_ATTRIBUTE.into(), AttributeValue::Bool(true));
+        item
+    }
+
+    /// Builds the item `store` writes for a session.
+    pub(crate) fn session_item(&self, id: &str, session: &str, expires: i64) -> Result<Item, DatabaseError> {
+        if self.config.manage_ttl && !(1..=MAX_PLAUSIBLE_EXPIRES).contains(&expires) {
+            return Err(DatabaseError::GenericInsertError(format!(
+                "expires {expires} for session {id} is not a unix timestamp in seconds"
+            )));
+        }
+
+        let (session, stored) = self.session_attribute(session)?;
+
+        let expires = if self.config.ttl_jitter_seconds > 0 {
+            expires + rand::thread_rng().gen_range(0..=self.config.ttl_jitter_seconds)
+        } else {
+            expires
+        };
+
+        let mut item = self.key(id)?;
+        item.insert("session".into(), session);
+        item.insert(self.expires_attribute().into(), AttributeValue::N(expires.to_string()));
+
+        if let Some(version) = self.config.payload_version {
+            item.insert(PAYLOAD_VERSION_ATTRIBUTE.into(), AttributeValue::N(version.to_string()));
+        }
+
+        if self.config.expires_iso {
+            if let Some(time) = DateTime::from_timestamp(expires, 0) {
+                item.insert("expires_iso".into(), AttributeValue::S(time.to_rfc3339()));
+            }
+        }
+
+        if self.config.expiry_index.is_some() {
+            item.insert(EXPIRY_PARTITION_KEY.into(), AttributeValue::S(EXPIRY_PARTITION_VALUE.into()));
+        }
+
+        if self.config.checksum {
+            item.insert("checksum".into(), AttributeValue::S(codec::checksum(&stored)));
+        }
 
-use crate::DynamoDbClient;
+        if self.config.last_modified {
+            item.insert(LAST_MODIFIED_ATTRIBUTE.into(), AttributeValue::N(unix_now().to_string()));
+        }
 
-#[derive(Clone, Debug)]
-pub struct SessionDynamoDbPool {
-    client: DynamoDbClient,
-}
+        Ok(item)
+    }
 
-impl From<DynamoDbClient> for SessionDynamoDbPool {
-    fn from(client: DynamoDbClient) -> Self {
-        Self{
-            client
+    /// Swallows a missing table error from a delete when
+    /// `with_ignore_missing_table_on_delete` is set.
+    fn ignoring_missing_table(&self, err: DatabaseError) -> Result<(), DatabaseError> {
+        if self.config.ignore_missing_table_on_delete && error::is_table_not_found(&err) {
+            return Ok(());
         }
+        Err(err)
     }
-}
 
-impl SessionDynamoDbPool {
+    /// Reads the session payload back out of a stored item, verifying its
+    /// checksum when enabled. Expiry is left to the caller.
+    pub(crate) fn decode_session(&self, id: &str, item: &Item) -> Result<Option<String>, DatabaseError> {
+        if let Some(supported) = self.config.payload_version {
+            let version = payload_version(item);
+            if version > supported {
+                return Err(DatabaseError::GenericSelectError(format!(
+                    "session {id} has payload version {version}, newer than the supported {supported}"
+                )));
+            }
+        }
+
+        let session = match item.get("session") {
+            Some(AttributeValue::S(session)) => {
+                #[cfg(feature = "encryption")]
+                let session = &self.decrypt_payload(id, session)?;
+                codec::decode_string(session)?
+            }
+            Some(AttributeValue::Null(true)) => String::new(),
+            Some(map @ AttributeValue::M(_)) => match codec::attribute_value_to_json(map) {
+                Some(value) => value.to_string(),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        if self.config.checksum {
+            if let Some(expected) = item.get("checksum").and_then(|c| c.as_s().ok()) {
+                if codec::checksum(&session) != *expected {
+                    return Err(error::checksum_mismatch(id));
+                }
+            }
+        }
+
+        Ok(Some(session))
+    }
+
+    /// Returns the attribute to store along with the payload `load` will
+    /// read back from it, which is what the checksum is computed over.
+    fn session_attribute(&self, session: &str) -> Result<(AttributeValue, String), DatabaseError> {
+        if self.config.session_as_map {
+            if let Ok(value @ serde_json::Value::Object(_)) = serde_json::from_str(session) {
+                return Ok((codec::json_to_attribute_value(&value), value.to_string()));
+            }
+        }
+
+        // Empty payloads are stored as NULL, as DynamoDB rejects empty
+        // Strings in some places, such as index keys and older emulators.
+        let codec = if session.len() > self.config.compression_threshold_bytes
+            || codec::has_compression_prefix(session)
+        {
+            self.config.string_codec
+        } else {
+            StringCodec::Plain
+        };
+        let mut encoded = self.seal(codec, session)?;
+
+        if let Some((max_bytes, strategy)) = self.config.large_payload {
+            if encoded.len() > max_bytes && strategy == LargePayloadStrategy::Compress {
+                encoded = self.seal(StringCodec::GzipBase64, session)?;
+            }
+            if encoded.len() > max_bytes {
+                return Err(DatabaseError::GenericInsertError(format!(
+                    "session payload of {} bytes exceeds the {max_bytes} byte limit",
+                    encoded.len()
+                )));
+            }
+        }
+
+        if encoded.is_empty() {
+            return Ok((AttributeValue::Null(true), session.into()));
+        }
+
+        Ok((AttributeValue::S(encoded), session.into()))
+    }
+
+    /// Encodes a String payload with `codec`, then encrypts it if
+    /// `with_encryption` is set. Empty results stay empty.
+    fn seal(&self, codec: StringCodec, session: &str) -> Result<String, DatabaseError> {
+        let encoded = codec.encode(self.config.compression_algorithm, session)?;
+
+        #[cfg(feature = "encryption")]
+        if !encoded.is_empty() {
+            return self.encrypt_payload(encoded);
+        }
+
+        Ok(encoded)
+    }
   
-    async fn create_tables(&self, table_name: &str)->Result<(), aws_sdk_dynamodb::Error> {
+    /// Creates the table unless it already exists, returning whether it did.
+    async fn create_tables(&self, table_name: &str)->Result<bool, aws_sdk_dynamodb::Error> {
+        let request = self.client.list_tables();
+
         let res = self
-        .client
-        .list_tables()
-        .send()
-        .await?;
+            .send(|| request.clone().send())
+            .await?;
 
 
         if res
             .table_names
             .unwrap_or_default()
             .into_iter()
-            .any(|s| s == table_name)
+            .any(|s| s == self.full_table_name(table_name))
         {
+            return Ok(false);
+        }
+
+        let schema = self.describe_intended_schema(table_name);
+
+        let mut request = self.client.create_table().table_name(schema.table_name);
+
+        for (attribute_name, key_type) in schema.key_schema {
+            request = request.key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(attribute_name)
+                    .key_type(key_type)
+                    .build()?,
+            );
+        }
+
+        // DynamoDB only accepts definitions for attributes used in the key
+        // schema of the table or one of its indexes.
+        for (attribute_name, attribute_type) in schema.attribute_definitions {
+            request = request.attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name(attribute_name)
+                    .attribute_type(attribute_type)
+                    .build()?,
+            );
+        }
+
+        for (index_name, partition_key) in self.managed_indexes() {
+            request = request.global_secondary_indexes(self.expiry_sorted_index(index_name, partition_key)?);
+        }
+
+        request = match schema.capacity {
+            Capacity::OnDemand => request.billing_mode(BillingMode::PayPerRequest),
+            Capacity::Provisioned { read, write } => request
+                .billing_mode(BillingMode::Provisioned)
+                .provisioned_throughput(
+                    ProvisionedThroughput::builder()
+                        .read_capacity_units(read)
+                        .write_capacity_units(write)
+                        .build()?,
+                ),
+        };
+
+        self.send(|| request.clone().send()).await?;
+
+        Ok(true)
+    }
+
+    /// Gets a table ready in one go at startup: creates it like `initiate`
+    /// if needed, waits until it is ACTIVE and, while TTL is managed,
+    /// enables TTL on it if it is disabled. Once this succeeds, `initiate`
+    /// and `prepare` skip the table on this pool and its clones.
+    pub async fn prepare(&self, table_name: &str) -> Result<(), DatabaseError> {
+        if self.is_prepared(table_name) {
             return Ok(());
         }
 
-        let id: &str = "id";
-        let session: &str = "session";
-        let expires: &str = "expires";
+        self.initiate(table_name).await?;
 
-        let id_ad = AttributeDefinition::builder()
-            .attribute_name(id)
-            .attribute_type(ScalarAttributeType::S)
-            .build()?;
+        let _permit = self.permit().await?;
+        self.wait_until_active(table_name).await?;
 
-        let session_ad = AttributeDefinition::builder()
-            .attribute_name(session)
-            .attribute_type(ScalarAttributeType::S)
-            .build()?;
+        if self.config.manage_ttl {
+            self.ensure_ttl_enabled(table_name).await?;
+        }
 
-        let expires_ad = AttributeDefinition::builder()
-            .attribute_name(expires)
-            .attribute_type(ScalarAttributeType::N)
-            .build()?;
+        self.prepared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(self.full_table_name(table_name));
+        Ok(())
+    }
 
-        let ks = KeySchemaElement::builder()
-            .attribute_name(id)
-            .key_type(KeyType::Hash)
-            .build()?;
+    /// Deletes the table along with every session in it, e.g. to tear down
+    /// an integration test or decommission the store. With `wait`, returns
+    /// only once the table is gone. Requires `with_allow_drop_table`.
+    pub async fn drop_table(&self, table_name: &str, wait: bool) -> Result<(), DatabaseError> {
+        if !self.config.allow_drop_table {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "drop_table requires with_allow_drop_table".into(),
+            ));
+        }
 
-        self.client
-            .create_table()
-            .table_name(table_name)
-            .key_schema(ks)
-            .attribute_definitions(id_ad)
-            .attribute_definitions(session_ad)
-            .attribute_definitions(expires_ad)
-            .send()
-            .await?;
+        let _permit = self.permit().await?;
+
+        let full_name = self.full_table_name(table_name);
+        let request = self.client.delete_table().table_name(&full_name);
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericDeleteError))?;
+
+        self.prepared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&full_name);
+
+        if wait {
+            self.client
+                .wait_until_table_not_exists()
+                .table_name(&full_name)
+                .wait(TABLE_ACTIVE_TIMEOUT)
+                .await
+                .map_err(|e| DatabaseError::GenericDeleteError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    fn is_prepared(&self, table_name: &str) -> bool {
+        self.prepared
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&self.full_table_name(table_name))
+    }
 
+    /// Waits until a table is ACTIVE, which it must be before TTL or auto
+    /// scaling can be configured.
+    async fn wait_until_active(&self, table_name: &str) -> Result<(), DatabaseError> {
         self.client
+            .wait_until_table_exists()
+            .table_name(self.full_table_name(table_name))
+            .wait(TABLE_ACTIVE_TIMEOUT)
+            .await
+            .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// The indexes `initiate` creates, as index names with the attribute
+    /// they are partitioned on.
+    pub(crate) fn managed_indexes(&self) -> Vec<(&str, &str)> {
+        let user_index = self.config.user_index.as_ref().filter(|_| self.config.create_user_index);
+
+        [
+            (self.config.expiry_index.as_ref(), EXPIRY_PARTITION_KEY),
+            (user_index, USER_ATTRIBUTE),
+        ]
+        .into_iter()
+        .filter_map(|(index_name, partition_key)| Some((index_name?.as_str(), partition_key)))
+        .collect()
+    }
+
+    /// Adds the indexes `initiate` would have created along with the table
+    /// to an existing table that lacks them, one at a time as DynamoDB
+    /// requires, waiting for each to become ACTIVE.
+    async fn add_missing_indexes(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let full_name = self.full_table_name(table_name);
+        let describe = self.client.describe_table().table_name(&full_name);
+
+        let existing: HashSet<String> = self
+            .send(|| describe.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?
+            .table
+            .map(|table| {
+                table
+                    .global_secondary_indexes()
+                    .iter()
+                    .filter_map(|index| index.index_name.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for (index_name, partition_key) in self.managed_indexes() {
+            if existing.contains(index_name) {
+                continue;
+            }
+            tracing::info!("adding missing index {index_name} to table {full_name}");
+
+            let index = self
+                .expiry_sorted_index(index_name, partition_key)
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+            let create = CreateGlobalSecondaryIndexAction::builder()
+                .index_name(index.index_name)
+                .set_key_schema(Some(index.key_schema))
+                .set_projection(index.projection)
+                .set_provisioned_throughput(index.provisioned_throughput)
+                .build()
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+
+            let definitions = [(partition_key, ScalarAttributeType::S), (self.expires_attribute(), ScalarAttributeType::N)]
+                .into_iter()
+                .map(|(name, attribute_type)| {
+                    AttributeDefinition::builder()
+                        .attribute_name(name)
+                        .attribute_type(attribute_type)
+                        .build()
+                        .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let request = self
+                .client
+                .update_table()
+                .table_name(&full_name)
+                .set_attribute_definitions(Some(definitions))
+                .global_secondary_index_updates(GlobalSecondaryIndexUpdate::builder().create(create).build());
+
+            self.send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?;
+
+            self.wait_until_index_active(table_name, index_name).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Polls the table until `index_name` is ACTIVE, which for an index
+    /// added to an existing table means backfilling finished.
+    async fn wait_until_index_active(&self, table_name: &str, index_name: &str) -> Result<(), DatabaseError> {
+        let request = self.client.describe_table().table_name(self.full_table_name(table_name));
+        let deadline = tokio::time::Instant::now() + INDEX_ACTIVE_TIMEOUT;
+
+        loop {
+            let status = self
+                .send(|| request.clone().send())
+                .await
+                .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericCreateError))?
+                .table
+                .and_then(|table| {
+                    table
+                        .global_secondary_indexes
+                        .unwrap_or_default()
+                        .into_iter()
+                        .find(|index| index.index_name.as_deref() == Some(index_name))
+                })
+                .and_then(|index| index.index_status);
+
+            if status == Some(IndexStatus::Active) {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(DatabaseError::GenericCreateError(format!(
+                    "index {index_name} didn't become ACTIVE within {INDEX_ACTIVE_TIMEOUT:?}, last status {status:?}"
+                )));
+            }
+
+            tokio::time::sleep(INDEX_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Capacity of the index `index_name`, the table's unless
+    /// `with_index_capacity` overrides it.
+    pub(crate) fn index_capacity(&self, index_name: &str) -> Capacity {
+        match (self.config.capacity, self.config.index_capacity.get(index_name)) {
+            (Capacity::Provisioned { .. }, Some(&(read, write))) => Capacity::Provisioned { read, write },
+            (capacity, _) => capacity,
+        }
+    }
+
+    /// Describes a keys-only global secondary index partitioned on
+    /// `partition_key` and sorted by `expires`.
+    fn expiry_sorted_index(
+        &self,
+        index_name: &str,
+        partition_key: &str,
+    ) -> Result<GlobalSecondaryIndex, BuildError> {
+        let mut index = GlobalSecondaryIndex::builder()
+            .index_name(index_name)
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(partition_key)
+                    .key_type(KeyType::Hash)
+                    .build()?,
+            )
+            .key_schema(
+                KeySchemaElement::builder()
+                    .attribute_name(self.expires_attribute())
+                    .key_type(KeyType::Range)
+                    .build()?,
+            )
+            .projection(Projection::builder().projection_type(ProjectionType::KeysOnly).build());
+
+        if let Capacity::Provisioned { read, write } = self.index_capacity(index_name) {
+            index = index.provisioned_throughput(
+                ProvisionedThroughput::builder()
+                    .read_capacity_units(read)
+                    .write_capacity_units(write)
+                    .build()?,
+            );
+        }
+
+        index.build()
+    }
+
+    /// Enables TTL on an existing table unless it is already enabled or
+    /// being enabled.
+    async fn ensure_ttl_enabled(&self, table_name: &str) -> Result<(), DatabaseError> {
+        let status = self.describe_ttl(table_name).await?;
+
+        match status.status {
+            TimeToLiveStatus::Enabled | TimeToLiveStatus::Enabling => Ok(()),
+            TimeToLiveStatus::Disabling => Err(DatabaseError::GenericCreateError(format!(
+                "TTL on {table_name} is being disabled and can't be enabled until that completes"
+            ))),
+            _ => self
+                .enable_ttl(table_name)
+                .await
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string())),
+        }
+    }
+
+    pub(crate) async fn enable_ttl(&self, table_name: &str) -> Result<(), aws_sdk_dynamodb::Error> {
+        let request = self
+            .client
             .update_time_to_live()
-            .table_name(table_name)
+            .table_name(self.full_table_name(table_name))
             .time_to_live_specification(
                 TimeToLiveSpecification::builder()
                     .enabled(true)
-                    .attribute_name("expires")
+                    .attribute_name(self.expires_attribute())
                     .build()?,
-            )
-            .send()
-            .await?;
+            );
+
+        match self.send(|| request.clone().send()).await {
+            Ok(_) => Ok(()),
+            // Another process, or an earlier `initiate`, got there first.
+            Err(e) if ttl_already_enabled(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Returns `true` if `UpdateTimeToLive` was rejected because TTL already is,
+/// or is being, enabled.
+fn ttl_already_enabled<E: ProvideErrorMetadata, R>(err: &SdkError<E, R>) -> bool {
+    let message = err.message().unwrap_or_default().to_ascii_lowercase();
+    err.code() == Some("ValidationException")
+        && (message.contains("already enabled") || message.contains("in progress"))
+}
 
-            Ok(())
+/// Returns `true` if the item was soft deleted, see `with_soft_delete`.
+pub(crate) fn is_soft_deleted(item: &Item) -> bool {
+    item.contains_key(DELETED_ATTRIBUTE)
+}
+
+/// A client like `client`, with `app_name` in its user agent.
+fn with_app_name(client: &DynamoDbClient, app_name: AppName) -> DynamoDbClient {
+    DynamoDbClient::from_conf(client.config().to_builder().app_name(app_name).build())
+}
+
+/// The payload version of a stored session, 0 if it has none.
+fn payload_version(item: &Item) -> u8 {
+    item.get(PAYLOAD_VERSION_ATTRIBUTE)
+        .and_then(|v| v.as_n().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Parses an `expires` attribute stored as either a Number or a String.
+pub(crate) fn expires_value(value: &AttributeValue) -> Option<i64> {
+    match value {
+        AttributeValue::N(n) => n.parse().ok(),
+        AttributeValue::S(s) => s.trim().parse().ok(),
+        _ => None,
     }
 }
 
+/// The default `IdGenerator`, a version 4 UUID.
+fn random_uuid() -> String {
+    uuid::Builder::from_random_bytes(rand::random()).into_uuid().to_string()
+}
+
+/// Current unix timestamp in seconds, the unit of the `expires` attribute.
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
 #[async_trait]
-impl DatabasePool for SessionDynamoDbPool{
+impl<K: SessionKey> DatabasePool for SessionDynamoDbPool<K> {
      /// This is called to create the table in the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn initiate(&self, table_name: &str) -> Result<(), DatabaseError> {
-        
-        self.create_tables(table_name).await
-            .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))
+        if self.is_prepared(table_name) {
+            return Ok(());
+        }
+
+        self.validate_table_name(table_name)?;
+
+        let _permit = self.permit().await?;
+
+        let created = self.create_tables(table_name).await
+            .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+
+        if !created {
+            self.validate_key_schema(table_name).await?;
+
+            if let Some(expected) = &self.config.expected_sse {
+                self.verify_sse(table_name, expected).await?;
+            }
+
+            if self.config.add_missing_indexes {
+                self.add_missing_indexes(table_name).await?;
+            }
+
+            if self.config.manage_ttl && self.config.ensure_ttl {
+                self.ensure_ttl_enabled(table_name).await?;
+            }
+            return Ok(());
+        }
+
+        self.wait_until_active(table_name).await?;
+
+        if self.config.manage_ttl {
+            self.enable_ttl(table_name).await
+                .map_err(|e| DatabaseError::GenericCreateError(e.to_string()))?;
+        }
+
+        #[cfg(feature = "autoscaling")]
+        if let (Capacity::Provisioned { .. }, Some(auto_scaling)) =
+            (self.config.capacity, &self.config.auto_scaling)
+        {
+            auto_scaling.register(&self.full_table_name(table_name)).await?;
+        }
+
+        Ok(())
 
     }
 
     /// This is called to receive the session count in the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn count(&self, table_name: &str) -> Result<i64, DatabaseError> {
-        let res = self
-            .client
-            .query()
-            .select(Select::Count)
-            .table_name(table_name)
-            .send()
+        self.count_with_consistency(self.config.default_read_consistency, table_name)
             .await
-            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
-
-        Ok(res.count.into())
     }
 
     /// This is called to store a session in the database using the given table name.
@@ -124,64 +2925,46 @@ impl DatabasePool for SessionDynamoDbPool{
         expires: i64,
         table_name: &str,
     ) -> Result<(), DatabaseError> {
-        self.client
-            .put_item()
-            .table_name(table_name)
-            .item("id", AttributeValue::S(id.into()))
-            .item("session", AttributeValue::S(session.into()))
-            .item("expires", AttributeValue::N(expires.to_string()))
-            .send()
-            .await
-            .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))?;
+        let item = self.session_item(id, session, expires)?;
+        let update = self.config.upsert || self.config.created_at;
+
+        #[cfg(feature = "batching")]
+        if let Some(batcher) = &self.config.write_batching {
+            if !update
+                && self.config.store_mode == StoreMode::Overwrite
+                && self.config.audit_table.is_none()
+                && !self.config.activity_summary
+            {
+                return self.store_batched(batcher, id, item, table_name).await;
+            }
+        }
+
+        self.write_session(id, item, update, false, table_name).await?;
         Ok(())
     }
 
     /// This is called to receive the session from the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn load(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
-        let output = self
-            .client
-            .get_item()
-            .table_name(table_name)
-            .key("id", AttributeValue::S(id.into()))
-            .send()
-            .await
-            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+        if let Some(coalescer) = &self.config.load_coalescing {
+            return self.load_coalesced(coalescer, id, table_name).await;
+        }
 
-        Ok(output
-            .item
-            .map(|i| i["session"].as_s().ok().cloned())
-            .flatten())
+        self.load_with_consistency(id, self.config.default_read_consistency, table_name)
+            .await
     }
 
     /// This is called to delete one session from the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn delete_one_by_id(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
-        self.client
-            .delete_item()
-            .table_name(table_name)
-            .key("id", AttributeValue::S(id.into()))
-            .send()
-            .await
-            .map_err(|e| DatabaseError::GenericDeleteError(e.to_string()))?;
-        Ok(())
+        self.delete_session(id, None, table_name).await
     }
 
     /// This is called to check if the id exists in the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn exists(&self, id: &str, table_name: &str) -> Result<bool, DatabaseError> {
-        let res = self
-            .client
-            .query()
-            .table_name(table_name)
-            .key_condition_expression("id = :id")
-            .expression_attribute_values(":id", AttributeValue::S(id.into()))
-            .select(Select::Count)
-            .send()
+        self.exists_with_consistency(id, self.config.default_read_consistency, table_name)
             .await
-            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
-
-        Ok(res.count > 0)
     }
 
     /// This is called to delete all sessions that expired from the database using the given table name.
@@ -194,38 +2977,319 @@ impl DatabasePool for SessionDynamoDbPool{
     /// This is called to delete all sessions from the database using the given table name.
     /// if an error occurs it should be propagated to the caller.
     async fn delete_all(&self, table_name: &str) -> Result<(), DatabaseError> {
-        self.client
-            .delete_item()
-            .table_name(table_name)
-            .send()
-            .await
-            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
-        Ok(())
+        let _permit = self.permit().await?;
+
+        match self.scan_delete(table_name, None, false).await {
+            Ok(_) => Ok(()),
+            Err(e) => self.ignoring_missing_table(e),
+        }
     }
 
     /// This is called to get all id's in the database from the last run.
     /// if an error occurs it should be propagated to the caller.
     async fn get_ids(&self, table_name: &str) -> Result<Vec<String>, DatabaseError> {
-        let res = self
-        .client
-        .query()
-        .table_name(table_name)            
-        .projection_expression("id")    
-        .select(Select::SpecificAttributes)        
-        .send()
-        .await
-        .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
-
-        let ids = res.items.map(|items|
-            items.into_iter()
-                .map(|item|
-                    item["id"].as_s().ok().cloned()
-                ).flatten().collect()
-        ).unwrap_or_default();
-        Ok(ids)
+        let _permit = self.permit().await?;
+
+        let request = self.scan_keys(table_name, &[]);
+
+        let items = self.scan_items(request).await?;
+
+        Ok(items.iter().filter_map(|item| self.item_id(item)).collect())
     }
 
     fn auto_handles_expiry(&self) -> bool {
         true
     }
 }
+
+/// A pool for unit tests, whose client is never meant to send a request.
+#[cfg(test)]
+pub(crate) fn test_pool() -> SessionDynamoDbPool {
+    let config = aws_sdk_dynamodb::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .build();
+    DynamoDbClient::from_conf(config).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_item_drops_every_expiry_attribute() {
+        let pool = test_pool();
+        let item = Item::from([
+            ("id".to_string(), AttributeValue::S("a".into())),
+            ("session".to_string(), AttributeValue::S("{}".into())),
+            ("expires".to_string(), AttributeValue::N("100".into())),
+            ("expires_iso".to_string(), AttributeValue::S("1970-01-01T00:01:40+00:00".into())),
+            (EXPIRY_PARTITION_KEY.to_string(), AttributeValue::S(EXPIRY_PARTITION_VALUE.into())),
+        ]);
+
+        let pinned = pool.pinned_item(item);
+
+        assert_eq!(pinned.get(PINNED_ATTRIBUTE), Some(&AttributeValue::Bool(true)));
+        assert!(["expires", "expires_iso", EXPIRY_PARTITION_KEY]
+            .iter()
+            .all(|attribute| !pinned.contains_key(*attribute)));
+        assert!(pinned.contains_key("id") && pinned.contains_key("session"));
+        assert!(!pool.is_expired(&pinned));
+    }
+
+    #[test]
+    fn expiry_lives_in_the_configured_attribute() {
+        let pool = test_pool().with_expires_attribute("ttl");
+
+        let item = pool.session_item("a", "{}", 100).unwrap();
+
+        assert_eq!(item.get("ttl"), Some(&AttributeValue::N("100".into())));
+        assert!(!item.contains_key("expires"));
+        assert_eq!(pool.item_expires(&item), Some(100));
+        assert!(pool.is_expired(&item));
+        assert!(!pool.pinned_item(item).contains_key("ttl"));
+    }
+
+    #[test]
+    fn store_returning_old_rejects_transactional_audits() {
+        let pool = test_pool().with_audit_table("audit", AuditMode::Transactional);
+
+        let result = futures::executor::block_on(pool.store_returning_old("a", "{}", unix_now() + 60, "sessions"));
+
+        assert!(matches!(result, Err(DatabaseError::GenericNotSupportedError(_))));
+    }
+
+    #[test]
+    fn store_with_attributes_rejects_managed_attributes() {
+        let pool = test_pool();
+        let reserved = [
+            "id",
+            "session",
+            "expires",
+            PINNED_ATTRIBUTE,
+            DELETED_ATTRIBUTE,
+            "checksum",
+            CREATED_ATTRIBUTE,
+            LAST_MODIFIED_ATTRIBUTE,
+            PAYLOAD_VERSION_ATTRIBUTE,
+            "expires_iso",
+            EXPIRY_PARTITION_KEY,
+        ];
+
+        for name in reserved {
+            let extra = HashMap::from([(name.to_string(), AttributeValue::Bool(true))]);
+            let result = futures::executor::block_on(pool.store_with_attributes("a", "{}", unix_now() + 60, extra, "sessions"));
+            assert!(matches!(result, Err(DatabaseError::GenericInsertError(message)) if message.contains("reserved")), "{name}");
+
+            let result = futures::executor::block_on(pool.try_set_flag("a", name, "sessions"));
+            assert!(matches!(result, Err(DatabaseError::GenericInsertError(message)) if message.contains("reserved")), "{name}");
+        }
+
+        let ttl = test_pool().with_expires_attribute("ttl");
+        let extra = HashMap::from([("ttl".to_string(), AttributeValue::N("1".into()))]);
+        let result = futures::executor::block_on(ttl.store_with_attributes("a", "{}", unix_now() + 60, extra, "sessions"));
+        assert!(matches!(result, Err(DatabaseError::GenericInsertError(_))));
+    }
+
+    #[test]
+    fn is_live_honours_grace_and_soft_deletion() {
+        let pool = test_pool().with_load_grace_seconds(60);
+        let expired_at = |expires: i64| Item::from([("expires".to_string(), AttributeValue::N(expires.to_string()))]);
+
+        assert!(pool.is_live(&expired_at(unix_now() + 60)));
+        assert!(pool.is_live(&expired_at(unix_now() - 30)));
+        assert!(!pool.is_live(&expired_at(unix_now() - 120)));
+        assert!(pool.is_live(&Item::new()));
+
+        let mut deleted = expired_at(unix_now() + 60);
+        deleted.insert(DELETED_ATTRIBUTE.into(), AttributeValue::N(unix_now().to_string()));
+        assert!(!pool.is_live(&deleted));
+    }
+
+    #[test]
+    fn checksum_round_trips_and_catches_tampering() {
+        let pool = test_pool().with_checksum(true);
+        let mut item = pool.session_item("a", r#"{"user":1}"#, unix_now() + 60).unwrap();
+
+        assert_eq!(item.get("checksum"), Some(&AttributeValue::S(codec::checksum(r#"{"user":1}"#))));
+        assert_eq!(pool.decode_session("a", &item).unwrap().as_deref(), Some(r#"{"user":1}"#));
+
+        item.insert("session".into(), AttributeValue::S(r#"{"user":2}"#.into()));
+        let err = pool.decode_session("a", &item).unwrap_err();
+        assert!(error::is_checksum_mismatch(&err));
+
+        item.remove("checksum");
+        assert_eq!(pool.decode_session("a", &item).unwrap().as_deref(), Some(r#"{"user":2}"#));
+    }
+
+    #[test]
+    fn session_item_rejects_implausible_expiries_while_ttl_is_managed() {
+        let pool = test_pool();
+        let millis = unix_now() * 1000;
+
+        for expires in [0, -5, millis] {
+            let err = pool.session_item("a", "{}", expires).unwrap_err();
+            assert!(matches!(&err, DatabaseError::GenericInsertError(message) if message.contains("unix timestamp")));
+        }
+        assert!(pool.session_item("a", "{}", unix_now() - 60).is_ok());
+
+        let unmanaged = test_pool().with_manage_ttl(false);
+        assert!(unmanaged.session_item("a", "{}", millis).is_ok());
+    }
+
+    #[test]
+    fn check_expires_rejects_items_without_a_valid_expiry() {
+        let pool = test_pool().with_strict_expires_check(true);
+        let mut item = pool.session_item("a", "{}", unix_now() + 60).unwrap();
+        assert!(pool.check_expires("a", &item).is_ok());
+
+        item.insert("expires".into(), AttributeValue::N("0".into()));
+        assert!(error::is_missing_expires(&pool.check_expires("a", &item).unwrap_err()));
+
+        item.remove("expires");
+        assert!(error::is_missing_expires(&pool.check_expires("a", &item).unwrap_err()));
+
+        let pinned = pool.pinned_item(item);
+        assert!(pool.check_expires("a", &pinned).is_ok());
+        assert!(test_pool().with_manage_ttl(false).check_expires("a", &Item::new()).is_ok());
+    }
+
+    #[test]
+    fn validate_table_name_enforces_length_and_charset() {
+        let pool = test_pool();
+        assert!(pool.validate_table_name("abc").is_ok());
+        assert!(pool.validate_table_name(&"a".repeat(MAX_TABLE_NAME_LEN)).is_ok());
+        assert!(pool.validate_table_name("My_sessions-v1.2").is_ok());
+
+        for name in ["ab", &"a".repeat(MAX_TABLE_NAME_LEN + 1), "sessions table", "sessions/v1", "séssions"] {
+            assert!(matches!(pool.validate_table_name(name), Err(DatabaseError::GenericCreateError(_))), "{name:?}");
+        }
+
+        let prefixed = test_pool().with_table_prefix("app_");
+        assert!(prefixed.validate_table_name("a").is_ok());
+        assert!(prefixed.validate_table_name(&"a".repeat(MAX_TABLE_NAME_LEN - 3)).is_err());
+    }
+
+    /// Runs `from_env` with exactly the given `SESSION_DYNAMODB_*` variables
+    /// set. Every case runs inside one test, so the process-wide variables
+    /// can't race.
+    fn from_env_with(vars: &[(&str, &str)]) -> Result<SessionDynamoDbPool, DatabaseError> {
+        const NAMES: [&str; 6] = [
+            "SESSION_DYNAMODB_REGION",
+            "SESSION_DYNAMODB_ENDPOINT",
+            "SESSION_DYNAMODB_TABLE_PREFIX",
+            "SESSION_DYNAMODB_BILLING_MODE",
+            "SESSION_DYNAMODB_READ_CAPACITY",
+            "SESSION_DYNAMODB_WRITE_CAPACITY",
+        ];
+
+        for name in NAMES {
+            std::env::remove_var(name);
+        }
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let pool = runtime.block_on(SessionDynamoDbPool::from_env());
+
+        for name in NAMES {
+            std::env::remove_var(name);
+        }
+        pool
+    }
+
+    fn create_error(result: Result<SessionDynamoDbPool, DatabaseError>) -> String {
+        match result {
+            Err(DatabaseError::GenericCreateError(message)) => message,
+            other => panic!("expected a create error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn from_env_parses_and_rejects_variables() {
+        let message = create_error(from_env_with(&[
+            ("SESSION_DYNAMODB_BILLING_MODE", "PROVISIONED"),
+            ("SESSION_DYNAMODB_READ_CAPACITY", "5"),
+        ]));
+        assert_eq!(
+            message,
+            "missing environment variables: SESSION_DYNAMODB_REGION, SESSION_DYNAMODB_WRITE_CAPACITY"
+        );
+
+        let message = create_error(from_env_with(&[("SESSION_DYNAMODB_REGION", "")]));
+        assert_eq!(message, "missing environment variables: SESSION_DYNAMODB_REGION");
+
+        let message = create_error(from_env_with(&[
+            ("SESSION_DYNAMODB_REGION", "us-east-1"),
+            ("SESSION_DYNAMODB_BILLING_MODE", "on_demand"),
+        ]));
+        assert!(message.contains("invalid SESSION_DYNAMODB_BILLING_MODE \"on_demand\""));
+
+        let message = create_error(from_env_with(&[
+            ("SESSION_DYNAMODB_REGION", "us-east-1"),
+            ("SESSION_DYNAMODB_BILLING_MODE", "PROVISIONED"),
+            ("SESSION_DYNAMODB_READ_CAPACITY", "five"),
+            ("SESSION_DYNAMODB_WRITE_CAPACITY", "5"),
+        ]));
+        assert!(message.starts_with("invalid SESSION_DYNAMODB_READ_CAPACITY \"five\""));
+
+        let pool = from_env_with(&[("SESSION_DYNAMODB_REGION", "us-east-1")]).unwrap();
+        assert_eq!(pool.config.capacity, Capacity::OnDemand);
+        assert_eq!(pool.full_table_name("sessions"), "sessions");
+
+        let pool = from_env_with(&[
+            ("SESSION_DYNAMODB_REGION", "eu-west-1"),
+            ("SESSION_DYNAMODB_ENDPOINT", "http://localhost:8000"),
+            ("SESSION_DYNAMODB_TABLE_PREFIX", "test_"),
+            ("SESSION_DYNAMODB_BILLING_MODE", "PROVISIONED"),
+            ("SESSION_DYNAMODB_READ_CAPACITY", "5"),
+            ("SESSION_DYNAMODB_WRITE_CAPACITY", "10"),
+        ])
+        .unwrap();
+        assert_eq!(pool.config.capacity, Capacity::Provisioned { read: 5, write: 10 });
+        assert_eq!(pool.full_table_name("sessions"), "test_sessions");
+        assert_eq!(pool.client.config().region().map(|r| r.as_ref()), Some("eu-west-1"));
+    }
+
+    #[test]
+    fn store_if_absent_overwrites_soft_deleted_sessions() {
+        let puts = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&puts);
+        let pool = crate::mock::mock_pool(move |operation, body| {
+            assert_eq!(operation, "PutItem");
+            sent.lock().unwrap().push(body.clone());
+            (200, serde_json::json!({}))
+        });
+
+        let stored = crate::mock::block_on(pool.store_if("a", "{}", unix_now() + 60, None, "sessions")).unwrap();
+
+        assert!(stored);
+        let puts = puts.lock().unwrap();
+        let condition = puts[0]["ConditionExpression"].as_str().unwrap();
+        assert!(condition.contains("attribute_exists(#deleted)"), "{condition}");
+        assert_eq!(puts[0]["ExpressionAttributeNames"]["#deleted"], DELETED_ATTRIBUTE);
+        assert!(puts[0]["Item"].get(DELETED_ATTRIBUTE).is_none());
+        assert!(puts[0]["Item"].get(PINNED_ATTRIBUTE).is_none());
+    }
+
+    #[cfg(feature = "batching")]
+    #[test]
+    fn activity_summary_bypasses_write_batching() {
+        let operations = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&operations);
+        let pool = crate::mock::mock_pool(move |operation, _| {
+            sent.lock().unwrap().push(operation.to_string());
+            (200, serde_json::json!({}))
+        })
+        .with_write_batching(Duration::from_millis(10))
+        .with_activity_summary(true);
+
+        crate::mock::block_on(pool.store("a", "{}", unix_now() + 60, "sessions")).unwrap();
+
+        assert_eq!(*operations.lock().unwrap(), ["PutItem", "UpdateItem"]);
+    }
+}
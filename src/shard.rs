@@ -0,0 +1,142 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, WriteRequest};
+use axum_session::DatabaseError;
+use rand::Rng;
+
+use crate::{
+    error,
+    session_pool::is_soft_deleted,
+    Item, KeyLayout, SessionDynamoDbPool, SessionKey,
+};
+
+/// Attribute recording when a shard was written, in unix microseconds, so
+/// `load_sharded` can tell the newest shard apart.
+const SHARD_WRITTEN_ATTRIBUTE: &str = "shard_written";
+
+/// Separates a session id from its shard number in the key.
+const SHARD_SEPARATOR: &str = "#shard";
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Stores a session like `store`, under one of the `with_write_shards`
+    /// shard keys picked at random, spreading the writes of a hot session,
+    /// e.g. one shared by a service, over several partitions. Sharded
+    /// sessions are read with `load_sharded` and removed with
+    /// `delete_sharded`; the other methods, `load` included, don't see them.
+    pub async fn store_sharded(
+        &self,
+        id: &str,
+        session: &str,
+        expires: i64,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        let mut item = self.session_item(id, session, expires)?;
+        let shard = rand::thread_rng().gen_range(0..self.write_shards()?);
+        item.extend(self.shard_key(id, shard)?);
+        item.insert(SHARD_WRITTEN_ATTRIBUTE.into(), AttributeValue::N(unix_micros().to_string()));
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .put_item()
+            .table_name(self.full_table_name(table_name))
+            .set_item(Some(item));
+
+        self.send(|| request.clone().send())
+            .await
+            .map_err(|e| error::map_sdk_error(e, DatabaseError::GenericInsertError))?;
+        Ok(())
+    }
+
+    /// Loads the session `store_sharded` wrote last, reading every shard
+    /// with one batched read. Each load thus costs as many reads as there
+    /// are shards, the price of spreading the writes. Returns `None` if the
+    /// newest shard has expired.
+    pub async fn load_sharded(&self, id: &str, table_name: &str) -> Result<Option<String>, DatabaseError> {
+        let keys = self.shard_keys(id)?;
+
+        let _permit = self.permit().await?;
+
+        let (items, unprocessed) = self.batch_get(table_name, keys, None).await?;
+        if !unprocessed.is_empty() {
+            return Err(DatabaseError::GenericSelectError(format!(
+                "batch get from {table_name} left {} shards of session {id} unprocessed",
+                unprocessed.len()
+            )));
+        }
+
+        let newest = items.into_iter().max_by_key(|item| {
+            item.get(SHARD_WRITTEN_ATTRIBUTE)
+                .and_then(|written| written.as_n().ok())
+                .and_then(|written| written.parse::<i64>().ok())
+        });
+
+        match newest {
+            Some(item) if !self.is_expired(&item) && !is_soft_deleted(&item) => self.decode_session(id, &item),
+            _ => Ok(None),
+        }
+    }
+
+    /// Deletes every shard of a session written by `store_sharded`.
+    pub async fn delete_sharded(&self, id: &str, table_name: &str) -> Result<(), DatabaseError> {
+        let requests = self
+            .shard_keys(id)?
+            .into_iter()
+            .map(|key| {
+                DeleteRequest::builder()
+                    .set_key(Some(key))
+                    .build()
+                    .map(|delete| WriteRequest::builder().delete_request(delete).build())
+                    .map_err(|e| DatabaseError::GenericDeleteError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let _permit = self.permit().await?;
+
+        self.batch_write(table_name, requests).await?;
+        Ok(())
+    }
+
+    /// Shard count set by `with_write_shards`.
+    fn write_shards(&self) -> Result<u8, DatabaseError> {
+        self.config.write_shards.ok_or_else(|| {
+            DatabaseError::GenericNotSupportedError("sharded sessions require with_write_shards".into())
+        })
+    }
+
+    /// Keys of every shard of `id`.
+    fn shard_keys(&self, id: &str) -> Result<Vec<Item>, DatabaseError> {
+        (0..self.write_shards()?).map(|shard| self.shard_key(id, shard)).collect()
+    }
+
+    /// Key of one shard of `id`: the session's key with the shard number
+    /// appended to its partition key value. Only String partition keys can
+    /// take a suffix, and under `KeyLayout::Composite` the partition key is
+    /// shared by every session, so its sessions can't be sharded.
+    fn shard_key(&self, id: &str, shard: u8) -> Result<Item, DatabaseError> {
+        if let KeyLayout::Composite { .. } = self.config.key_layout {
+            return Err(DatabaseError::GenericNotSupportedError(
+                "sharded sessions require KeyLayout::Simple".into(),
+            ));
+        }
+
+        let mut key = self.key(id)?;
+        match key.get_mut(self.id_attribute()) {
+            Some(AttributeValue::S(value)) => value.push_str(&format!("{SHARD_SEPARATOR}{shard}")),
+            _ => {
+                return Err(DatabaseError::GenericNotSupportedError(
+                    "sharded sessions require String session ids".into(),
+                ))
+            }
+        }
+        Ok(key)
+    }
+}
+
+/// Current unix timestamp in microseconds.
+fn unix_micros() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros() as i64)
+}
@@ -0,0 +1,203 @@
+use std::{collections::VecDeque, time::Duration};
+
+use aws_sdk_dynamodbstreams::types::{AttributeValue, OperationType, Record, ShardIteratorType};
+use axum_session::DatabaseError;
+use futures::Stream;
+
+pub type DynamoDbStreamsClient = aws_sdk_dynamodbstreams::Client;
+
+/// How long to wait before polling again once every shard returned an empty page.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change to a session item, decoded from a DynamoDB Streams record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionEvent {
+    Created { id: String, expires: Option<i64> },
+    Updated { id: String, expires: Option<i64> },
+    Deleted { id: String },
+    /// The item was removed by DynamoDB's TTL process rather than by a client.
+    Expired { id: String },
+}
+
+impl SessionEvent {
+    /// Decodes a single stream record. Returns `None` for records that don't
+    /// carry a session id.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        let change = record.dynamodb()?;
+        let id = change.keys()?.get("id")?.as_s().ok()?.clone();
+        let expires = change
+            .new_image()
+            .and_then(|image| image.get("expires"))
+            .and_then(|expires| match expires {
+                AttributeValue::N(n) => n.parse().ok(),
+                _ => None,
+            });
+
+        match record.event_name()? {
+            OperationType::Insert => Some(Self::Created { id, expires }),
+            OperationType::Modify => Some(Self::Updated { id, expires }),
+            OperationType::Remove if is_ttl_removal(record) => Some(Self::Expired { id }),
+            OperationType::Remove => Some(Self::Deleted { id }),
+            _ => None,
+        }
+    }
+}
+
+/// TTL deletions are attributed to the DynamoDB service principal.
+fn is_ttl_removal(record: &Record) -> bool {
+    record.user_identity().is_some_and(|identity| {
+        identity.r#type() == Some("Service")
+            && identity.principal_id() == Some("dynamodb.amazonaws.com")
+    })
+}
+
+struct StreamState {
+    client: DynamoDbStreamsClient,
+    iterators: VecDeque<String>,
+    pending: VecDeque<SessionEvent>,
+}
+
+/// Subscribes to the stream of a session table and yields its session events,
+/// starting from the latest position of every open shard.
+///
+/// Streams must be enabled on the table with a view type that includes keys;
+/// `expires` is only decoded when new images are part of the view. Records
+/// are read without a pool, so the `id` key and the `expires` attribute
+/// are expected under their default names.
+pub async fn session_events(
+    client: DynamoDbStreamsClient,
+    stream_arn: &str,
+) -> Result<impl Stream<Item = Result<SessionEvent, DatabaseError>>, DatabaseError> {
+    let description = client
+        .describe_stream()
+        .stream_arn(stream_arn)
+        .send()
+        .await
+        .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?
+        .stream_description
+        .ok_or_else(|| DatabaseError::GenericSelectError(format!("stream {stream_arn} not found")))?;
+
+    let mut iterators = VecDeque::new();
+
+    for shard in description.shards() {
+        let is_open = shard
+            .sequence_number_range()
+            .is_some_and(|range| range.ending_sequence_number().is_none());
+        let Some(shard_id) = shard.shard_id().filter(|_| is_open) else {
+            continue;
+        };
+
+        let iterator = client
+            .get_shard_iterator()
+            .stream_arn(stream_arn)
+            .shard_id(shard_id)
+            .shard_iterator_type(ShardIteratorType::Latest)
+            .send()
+            .await
+            .map_err(|e| DatabaseError::GenericSelectError(e.to_string()))?;
+
+        iterators.extend(iterator.shard_iterator);
+    }
+
+    let state = StreamState {
+        client,
+        iterators,
+        pending: VecDeque::new(),
+    };
+
+    Ok(futures::stream::unfold(state, |mut state| async move {
+        let mut empty_polls = 0;
+
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+
+            let iterator = state.iterators.pop_front()?;
+
+            if empty_polls > state.iterators.len() {
+                tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+                empty_polls = 0;
+            }
+
+            let page = match state.client.get_records().shard_iterator(iterator).send().await {
+                Ok(page) => page,
+                Err(e) => return Some((Err(DatabaseError::GenericSelectError(e.to_string())), state)),
+            };
+
+            if page.records().is_empty() {
+                empty_polls += 1;
+            }
+
+            state
+                .pending
+                .extend(page.records().iter().filter_map(SessionEvent::from_record));
+            state.iterators.extend(page.next_shard_iterator);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodbstreams::types::{Identity, StreamRecord};
+
+    use super::*;
+
+    fn record(event: OperationType, change: StreamRecord) -> Record {
+        Record::builder().event_name(event).dynamodb(change).build()
+    }
+
+    #[test]
+    fn from_record_decodes_inserts_and_updates() {
+        let change = StreamRecord::builder()
+            .keys("id", AttributeValue::S("a".into()))
+            .new_image("id", AttributeValue::S("a".into()))
+            .new_image("expires", AttributeValue::N("100".into()))
+            .build();
+
+        assert_eq!(
+            SessionEvent::from_record(&record(OperationType::Insert, change.clone())),
+            Some(SessionEvent::Created { id: "a".into(), expires: Some(100) })
+        );
+        assert_eq!(
+            SessionEvent::from_record(&record(OperationType::Modify, change)),
+            Some(SessionEvent::Updated { id: "a".into(), expires: Some(100) })
+        );
+    }
+
+    #[test]
+    fn from_record_tolerates_a_missing_expires() {
+        let change = StreamRecord::builder()
+            .keys("id", AttributeValue::S("a".into()))
+            .new_image("id", AttributeValue::S("a".into()))
+            .build();
+
+        assert_eq!(
+            SessionEvent::from_record(&record(OperationType::Insert, change)),
+            Some(SessionEvent::Created { id: "a".into(), expires: None })
+        );
+    }
+
+    #[test]
+    fn from_record_tells_ttl_removals_apart() {
+        let change = StreamRecord::builder().keys("id", AttributeValue::S("a".into())).build();
+        let deleted = record(OperationType::Remove, change.clone());
+        let mut expired = record(OperationType::Remove, change);
+        expired.user_identity = Some(
+            Identity::builder()
+                .r#type("Service")
+                .principal_id("dynamodb.amazonaws.com")
+                .build(),
+        );
+
+        assert_eq!(SessionEvent::from_record(&deleted), Some(SessionEvent::Deleted { id: "a".into() }));
+        assert_eq!(SessionEvent::from_record(&expired), Some(SessionEvent::Expired { id: "a".into() }));
+    }
+
+    #[test]
+    fn from_record_skips_records_without_an_id() {
+        let change = StreamRecord::builder().keys("other", AttributeValue::S("a".into())).build();
+
+        assert_eq!(SessionEvent::from_record(&record(OperationType::Insert, change)), None);
+    }
+}
@@ -0,0 +1,336 @@
+use aws_sdk_dynamodb::{
+    operation::transact_write_items::TransactWriteItemsError,
+    types::{Delete, Put, ReturnValuesOnConditionCheckFailure, TransactWriteItem},
+};
+use axum_session::DatabaseError;
+use rand::Rng;
+
+use crate::{error, ReadConsistency, SessionDynamoDbPool, SessionKey, StoreMode};
+
+/// Most items a single `TransactWriteItems` call may write.
+const MAX_TRANSACT_ITEMS: usize = 100;
+
+/// Longest `ClientRequestToken` DynamoDB accepts.
+const MAX_TOKEN_LEN: usize = 36;
+
+/// Mints a `ClientRequestToken` for one logical `TransactWriteItems` call.
+///
+/// The pool's retries of a call resend its token, so DynamoDB applies the
+/// transaction at most once within its 10 minute idempotency window, while
+/// a new call gets a new token and repeating a write applies it again.
+/// Callers retrying on their own can mint a token once and pass it to every
+/// attempt through `regenerate_with_token` or `store_transactional_with_token`.
+pub fn client_request_token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; MAX_TOKEN_LEN / 2]>())
+}
+
+/// Rejects tokens DynamoDB wouldn't accept, before the transaction is sent.
+fn check_token(token: &str) -> Result<(), DatabaseError> {
+    if (1..=MAX_TOKEN_LEN).contains(&token.len()) {
+        Ok(())
+    } else {
+        Err(DatabaseError::GenericInsertError(format!(
+            "client request token {token:?} must be 1 to {MAX_TOKEN_LEN} characters long"
+        )))
+    }
+}
+
+impl<K: SessionKey> SessionDynamoDbPool<K> {
+    /// Moves the session stored under `old_id` to `new_id`, keeping its data
+    /// and expiry, e.g. to issue a fresh id on login. The write and the
+    /// delete run in a single transaction, so exactly one of the two ids
+    /// holds the session at any time.
+    ///
+    /// Returns `false` if there is no live session under `old_id`. Fails
+    /// with a [`CONDITION_FAILED`](crate::CONDITION_FAILED) error if
+    /// `new_id` is already taken.
+    pub async fn regenerate(&self, old_id: &str, new_id: &str, table_name: &str) -> Result<bool, DatabaseError> {
+        self.regenerate_with_token(old_id, new_id, &client_request_token(), table_name)
+            .await
+    }
+
+    /// `regenerate` with a caller supplied `ClientRequestToken`, see
+    /// `client_request_token`.
+    pub async fn regenerate_with_token(
+        &self,
+        old_id: &str,
+        new_id: &str,
+        token: &str,
+        table_name: &str,
+    ) -> Result<bool, DatabaseError> {
+        check_token(token)?;
+
+        let Some(mut item) = self.live_item(old_id, ReadConsistency::Strong, table_name).await? else {
+            return Ok(false);
+        };
+
+        let old_key = self.key(old_id)?;
+        for name in old_key.keys() {
+            item.remove(name);
+        }
+        item.extend(self.key(new_id)?);
+
+        let table_name = self.full_table_name(table_name);
+
+        let put = Put::builder()
+            .table_name(&table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+            .build()
+            .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))?;
+
+        let delete = Delete::builder()
+            .table_name(&table_name)
+            .set_key(Some(old_key))
+            .condition_expression("attribute_exists(#id)")
+            .expression_attribute_names("#id", self.id_attribute())
+            .build()
+            .map_err(|e| DatabaseError::GenericInsertError(e.to_string()))?;
+
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .transact_write_items()
+            .transact_items(TransactWriteItem::builder().put(put).build())
+            .transact_items(TransactWriteItem::builder().delete(delete).build())
+            .client_request_token(token);
+
+        let err = match self.send(|| request.clone().send()).await {
+            Ok(_) => return Ok(true),
+            Err(err) => err,
+        };
+
+        if let Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) = err.as_service_error() {
+            let failed = |i: usize| {
+                cancelled
+                    .cancellation_reasons()
+                    .get(i)
+                    .and_then(|reason| reason.code())
+                    == Some("ConditionalCheckFailed")
+            };
+
+            if failed(0) {
+                let message = format!("{}: session {new_id} already exists", error::CONDITION_FAILED);
+                let existing = cancelled.cancellation_reasons().first().and_then(|reason| reason.item());
+                return Err(DatabaseError::GenericInsertError(match existing {
+                    Some(item) => error::with_current_item(&message, item),
+                    None => message,
+                }));
+            }
+
+            // The old session was deleted after it was read.
+            if failed(1) {
+                return Ok(false);
+            }
+        }
+
+        Err(error::map_sdk_error(err, DatabaseError::GenericInsertError))
+    }
+
+    /// Stores sessions given as `(id, session, expires)` in one transaction,
+    /// so either all of them are written or none is. At most 100 sessions
+    /// fit in a transaction; larger inputs are rejected rather than split,
+    /// which would give up atomicity.
+    ///
+    /// Under `StoreMode::RejectExisting` the transaction is cancelled if any
+    /// of the ids already has a session, with a
+    /// [`SESSION_EXISTS`](crate::SESSION_EXISTS) error naming it. Other
+    /// cancellations are reported with DynamoDB's reason for each item.
+    /// Unlike `store`, these writes aren't audited and don't keep sessions
+    /// pinned.
+    pub async fn store_transactional(
+        &self,
+        items: &[(String, String, i64)],
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        self.store_transactional_with_token(items, &client_request_token(), table_name)
+            .await
+    }
+
+    /// `store_transactional` with a caller supplied `ClientRequestToken`,
+    /// see `client_request_token`.
+    pub async fn store_transactional_with_token(
+        &self,
+        items: &[(String, String, i64)],
+        token: &str,
+        table_name: &str,
+    ) -> Result<(), DatabaseError> {
+        check_token(token)?;
+
+        if items.is_empty() {
+            return Ok(());
+        }
+        if items.len() > MAX_TRANSACT_ITEMS {
+            return Err(DatabaseError::GenericInsertError(format!(
+                "store_transactional got {} sessions, at most {MAX_TRANSACT_ITEMS} fit in a transaction",
+                items.len()
+            )));
+        }
+
+        let reject_existing = self.config.store_mode == StoreMode::RejectExisting;
+        let full_name = self.full_table_name(table_name);
+
+        let mut writes = Vec::with_capacity(items.len());
+        for (id, session, expires) in items {
+            let mut put = Put::builder()
+                .table_name(&full_name)
+                .set_item(Some(self.session_item(id, session, *expires)?));
+            if reject_existing {
+                put = put
+                    .condition_expression("attribute_not_exists(#id)")
+                    .expression_attribute_names("#id", self.id_attribute())
+                    .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld);
+            }
+            let put = put.build().map_err(|e| DatabaseError::GenericInsertError(e.to_string()))?;
+
+            writes.push(TransactWriteItem::builder().put(put).build());
+        }
+
+        let ids: Vec<&str> = items.iter().map(|(id, _, _)| id.as_str()).collect();
+        let _permit = self.permit().await?;
+
+        let request = self
+            .client
+            .transact_write_items()
+            .set_transact_items(Some(writes))
+            .client_request_token(token);
+
+        let err = match self.send(|| request.clone().send()).await {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+
+        if let Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) = err.as_service_error() {
+            let reasons = cancelled.cancellation_reasons();
+
+            if let Some((i, reason)) = reasons
+                .iter()
+                .enumerate()
+                .find(|(_, reason)| reason.code() == Some("ConditionalCheckFailed"))
+            {
+                let message = format!("{}: {}", error::SESSION_EXISTS, ids[i]);
+                return Err(DatabaseError::GenericInsertError(match reason.item() {
+                    Some(item) => error::with_current_item(&message, item),
+                    None => message,
+                }));
+            }
+
+            let reasons: Vec<_> = ids
+                .iter()
+                .zip(reasons)
+                .filter_map(|(id, reason)| Some(format!("{id}: {}", reason.code().filter(|code| *code != "None")?)))
+                .collect();
+            return Err(DatabaseError::GenericInsertError(format!(
+                "transaction cancelled: {}",
+                reasons.join(", ")
+            )));
+        }
+
+        Err(error::map_sdk_error(err, DatabaseError::GenericInsertError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use axum_session::DatabasePool;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        mock::{self, mock_pool},
+        session_pool::{test_pool, unix_now},
+        AuditMode, RetryPolicy,
+    };
+
+    /// A pool failing every `TransactWriteItems` call with `failures`
+    /// throttling errors before it succeeds, along with the tokens the
+    /// calls were sent with.
+    fn flaky_pool(failures: usize) -> (SessionDynamoDbPool, Arc<Mutex<Vec<String>>>) {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let sent = Arc::clone(&tokens);
+
+        let pool = mock_pool(move |operation, body| {
+            assert_eq!(operation, "TransactWriteItems");
+            let mut sent = sent.lock().unwrap();
+            sent.push(body["ClientRequestToken"].as_str().unwrap_or_default().to_string());
+
+            match sent.len() % (failures + 1) {
+                0 => (200, json!({})),
+                _ => mock::error("ThrottlingException"),
+            }
+        })
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        });
+
+        (pool, tokens)
+    }
+
+    fn sessions() -> Vec<(String, String, i64)> {
+        vec![("a".into(), "{}".into(), unix_now() + 60), ("b".into(), "{}".into(), unix_now() + 60)]
+    }
+
+    #[test]
+    fn client_request_token_is_fresh_and_fits_dynamodb() {
+        let token = client_request_token();
+
+        assert_eq!(token.len(), MAX_TOKEN_LEN);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, client_request_token());
+    }
+
+    #[test]
+    fn every_retry_of_a_call_carries_its_token() {
+        let (pool, tokens) = flaky_pool(2);
+
+        mock::block_on(pool.store_transactional(&sessions(), "sessions")).unwrap();
+        mock::block_on(pool.store_transactional(&sessions(), "sessions")).unwrap();
+
+        let tokens = tokens.lock().unwrap();
+        assert_eq!(tokens.len(), 6);
+        assert!(tokens[..3].iter().all(|token| token == &tokens[0]));
+        assert!(tokens[3..].iter().all(|token| token == &tokens[3]));
+        assert_ne!(tokens[0], tokens[3]);
+    }
+
+    #[test]
+    fn callers_can_supply_the_token() {
+        let (pool, tokens) = flaky_pool(1);
+
+        mock::block_on(pool.store_transactional_with_token(&sessions(), "import-42", "sessions")).unwrap();
+
+        assert_eq!(*tokens.lock().unwrap(), ["import-42", "import-42"]);
+    }
+
+    #[test]
+    fn tokens_dynamodb_would_reject_fail_upfront() {
+        let pool = test_pool();
+
+        for token in [String::new(), "a".repeat(MAX_TOKEN_LEN + 1)] {
+            let result = futures::executor::block_on(pool.store_transactional_with_token(&sessions(), &token, "sessions"));
+            assert!(matches!(result, Err(DatabaseError::GenericInsertError(_))));
+        }
+    }
+
+    #[test]
+    fn transactional_audits_carry_a_token() {
+        let (pool, tokens) = flaky_pool(0);
+        let pool = pool.with_audit_table("audit", AuditMode::Transactional);
+
+        mock::block_on(pool.store("a", "{}", unix_now() + 60, "sessions")).unwrap();
+
+        let tokens = tokens.lock().unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].len(), MAX_TOKEN_LEN);
+    }
+}